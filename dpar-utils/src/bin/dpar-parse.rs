@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::env::args;
 use std::fs::File;
 use std::io::{BufRead, BufWriter, Write};
@@ -5,23 +6,26 @@ use std::path::Path;
 use std::process;
 use std::time::Instant;
 
-use conllx::{Deprojectivize, HeadProjectivizer, ReadSentence, Sentence, WriteSentence};
+use conllx::{Deprojectivize, Features, ReadSentence, Sentence, Token, WriteSentence};
 use dpar::features::InputVectorizer;
-use dpar::guide::BatchGuide;
+use dpar::guide::ScoredBatchGuide;
 use dpar::models::tensorflow::{LayerOps, TensorflowModel};
-use dpar::parser::{GreedyParser, ParseBatch};
+use dpar::parser::{GreedyParser, ParseBatch, ScoredParseBatch, TreeCompletion};
+use dpar::pproj::PseudoProjectivizer;
 use dpar::system::{DependencySet, TransitionSystem};
 use dpar::systems::{
-    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, StackProjectiveSystem, StackSwapSystem,
+    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, ArcSwiftSystem, CovingtonSystem,
+    StackProjectiveSystem, StackSwapSystem,
 };
 use failure::Error;
 use getopts::Options;
+use serde_derive::Serialize;
 use stdinout::{Input, OrExit, Output};
 
-use dpar_utils::{Config, SerializableTransitionSystem, TomlRead};
+use dpar_utils::{Bundle, Config, ConfigOverride, SerializableTransitionSystem, TomlRead};
 
 fn print_usage(program: &str, opts: &Options) {
-    let brief = format!("Usage: {} [options] CONFIG [INPUT]", program);
+    let brief = format!("Usage: {} [options] CONFIG|BUNDLE [INPUT]", program);
     print!("{}", opts.usage(&brief));
 }
 
@@ -30,7 +34,24 @@ fn main() {
     let program = args[0].clone();
 
     let mut opts = Options::new();
+    opts.optflag(
+        "c",
+        "constrained",
+        "use existing heads and relations in the input as constraints",
+    );
     opts.optflag("h", "help", "print this help menu");
+    opts.optopt(
+        "",
+        "scores",
+        "write arc and sentence scores, as CoNLL features (conll) or JSON (json)",
+        "FORMAT",
+    );
+    opts.optmulti(
+        "s",
+        "set",
+        "override a configuration value",
+        "SECTION.KEY=VALUE",
+    );
     let matches = opts.parse(&args[1..]).or_exit("Cannot parse options", 1);
 
     if matches.opt_present("h") {
@@ -43,54 +64,104 @@ fn main() {
         return;
     }
 
-    let config_file = File::open(&matches.free[0]).or_exit("Cannot open configuration file", 1);
-    let mut config =
-        Config::from_toml_read(config_file).or_exit("Cannot read configuration file as TOML", 1);
+    let overrides = matches
+        .opt_strs("s")
+        .iter()
+        .map(|o| o.parse())
+        .collect::<Result<Vec<ConfigOverride>, _>>()
+        .or_exit("Cannot parse configuration override", 1);
+
+    // The bundle is extracted to a temporary directory, so it should be
+    // kept around until parsing is finished.
+    let bundle = if Bundle::is_bundle(&matches.free[0]).or_exit("Cannot open model", 1) {
+        Some(Bundle::open(&matches.free[0]).or_exit("Cannot open model bundle", 1))
+    } else {
+        None
+    };
+    let config_path = match bundle {
+        Some(ref bundle) => bundle.config_path(),
+        None => matches.free[0].clone().into(),
+    };
+
+    let config_file = File::open(&config_path).or_exit("Cannot open configuration file", 1);
+    let mut config = Config::from_toml_read_with_overrides(config_file, &overrides)
+        .or_exit("Cannot read configuration file as TOML", 1);
     config
-        .relativize_paths(&matches.free[0])
+        .relativize_paths(&config_path)
         .or_exit("Cannot relativize paths in the configuration file", 1);
 
     let input = Input::from(matches.free.get(1));
     let reader = conllx::Reader::new(input.buf_read().or_exit("Cannot open treebank", 1));
 
     let output = Output::from(matches.free.get(2));
-    let writer = conllx::Writer::new(BufWriter::new(
+    let write = BufWriter::new(
         output
             .write()
             .or_exit("Cannot open output treebank for writing", 1),
-    ));
+    );
+    let (writer, scored) = match matches.opt_str("scores").as_ref().map(String::as_str) {
+        None => (SentenceWriter::Conll(conllx::Writer::new(write)), false),
+        Some("conll") => (SentenceWriter::Conll(conllx::Writer::new(write)), true),
+        Some("json") => (SentenceWriter::Json(write), true),
+        Some(format) => {
+            eprintln!("Unknown score format: {}", format);
+            process::exit(1);
+        }
+    };
+
+    let options = ParseOptions {
+        constrained: matches.opt_present("c"),
+        scored,
+    };
+    if options.constrained && config.parser.pproj.is_some() {
+        eprintln!("Warning: constraints are applied to projectivized trees");
+    }
 
-    parse(&config, reader, writer).or_exit("Parsing failed", 1);
+    parse(&config, reader, writer, options).or_exit("Parsing failed", 1);
+}
+
+/// Parsing options.
+#[derive(Clone, Copy)]
+struct ParseOptions {
+    /// Use the heads and relations in the input as constraints.
+    constrained: bool,
+
+    /// Compute arc and sentence scores.
+    scored: bool,
 }
 
 fn parse<R, W>(
     config: &Config,
     reader: conllx::Reader<R>,
-    writer: conllx::Writer<W>,
+    writer: SentenceWriter<W>,
+    options: ParseOptions,
 ) -> Result<(), Error>
 where
     R: BufRead,
     W: Write,
 {
-    let parse_fun: Box<Fn(_, _, _) -> Result<_, _>> = match config.parser.system.as_ref() {
+    let parse_fun: Box<Fn(_, _, _, _) -> Result<_, _>> = match config.parser.system.as_ref() {
         "arceager" => Box::new(parse_with_system::<R, W, ArcEagerSystem>),
         "archybrid" => Box::new(parse_with_system::<R, W, ArcHybridSystem>),
         "arcstandard" => Box::new(parse_with_system::<R, W, ArcStandardSystem>),
+        "arcswift" => Box::new(parse_with_system::<R, W, ArcSwiftSystem>),
         "stackproj" => Box::new(parse_with_system::<R, W, StackProjectiveSystem>),
         "stackswap" => Box::new(parse_with_system::<R, W, StackSwapSystem>),
+        "covington" => Box::new(parse_with_system::<R, W, CovingtonSystem>),
         _ => {
             eprintln!("Unsupported transition system: {}", config.parser.system);
             process::exit(1);
         }
     };
 
-    parse_fun(config, reader, writer)
+    parse_fun(config, reader, writer, options)
 }
 
 fn parse_with_system<R, W, S>(
     config: &Config,
     reader: conllx::Reader<R>,
-    writer: conllx::Writer<W>,
+    writer: SentenceWriter<W>,
+    options: ParseOptions,
 ) -> Result<(), Error>
 where
     R: BufRead,
@@ -100,10 +171,18 @@ where
     let inputs = config.parser.load_inputs()?;
     let lookups = config.lookups.load_lookups()?;
     let layer_ops = config.lookups.layer_ops();
-    let vectorizer = InputVectorizer::new(lookups, inputs);
+    let vectorizer = InputVectorizer::new(lookups, inputs)?;
     let system: S = load_system_generic(config)?;
     let guide = load_model(&config, system, vectorizer, &layer_ops)?;
-    let parser = GreedyParser::new(guide);
+    let parser = if options.constrained {
+        GreedyParser::new_constrained(guide)
+    } else {
+        GreedyParser::new(guide)
+    };
+    let parser = parser.with_completion(TreeCompletion::new(
+        config.parser.default_relation.clone(),
+        config.parser.single_root,
+    ));
 
     let mut n_sents = 0;
     let start = Instant::now();
@@ -111,9 +190,10 @@ where
     {
         let mut sent_proc = SentProcessor::new(
             parser,
-            config.parser.pproj,
+            config.parser.pproj.map(PseudoProjectivizer::new),
             config.parser.parse_batch_size,
             writer,
+            options.scored,
         );
 
         for sentence in reader.sentences() {
@@ -138,38 +218,126 @@ where
     Ok(())
 }
 
+/// Scores of a parsed sentence.
+struct SentenceScores {
+    /// The score of the attachment of each token.
+    arcs: Vec<Option<f32>>,
+
+    /// The sentence score.
+    sentence: f32,
+}
+
+/// Writer for parsed sentences.
+enum SentenceWriter<W>
+where
+    W: Write,
+{
+    /// CoNLL-X output, scores are added as features.
+    Conll(conllx::Writer<W>),
+
+    /// JSON output, one sentence per line.
+    Json(W),
+}
+
+impl<W> SentenceWriter<W>
+where
+    W: Write,
+{
+    fn write_sentence(
+        &mut self,
+        sentence: &[Token],
+        scores: Option<&SentenceScores>,
+    ) -> Result<(), Error> {
+        match self {
+            SentenceWriter::Conll(writer) => match scores {
+                Some(scores) => {
+                    let mut sentence = sentence.to_owned();
+                    for (token, &score) in sentence.iter_mut().zip(&scores.arcs) {
+                        if let Some(score) = score {
+                            add_feature(token, "arc_score", score);
+                        }
+                    }
+                    if let Some(token) = sentence.first_mut() {
+                        add_feature(token, "sentence_score", scores.sentence);
+                    }
+                    writer.write_sentence(&sentence)?;
+                }
+                None => writer.write_sentence(sentence)?,
+            },
+            SentenceWriter::Json(write) => {
+                serde_json::to_writer(&mut *write, &JsonSentence::new(sentence, scores))?;
+                writeln!(write)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct JsonSentence<'a> {
+    score: Option<f32>,
+    tokens: Vec<JsonToken<'a>>,
+}
+
+impl<'a> JsonSentence<'a> {
+    fn new(sentence: &'a [Token], scores: Option<&SentenceScores>) -> Self {
+        let tokens = sentence
+            .iter()
+            .enumerate()
+            .map(|(idx, token)| JsonToken {
+                form: token.form(),
+                head: token.head(),
+                relation: token.head_rel(),
+                score: scores.and_then(|scores| scores.arcs[idx]),
+            })
+            .collect();
+
+        JsonSentence {
+            score: scores.map(|scores| scores.sentence),
+            tokens,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonToken<'a> {
+    form: &'a str,
+    head: Option<usize>,
+    relation: Option<&'a str>,
+    score: Option<f32>,
+}
+
 struct SentProcessor<G, W>
 where
-    G: BatchGuide,
+    G: ScoredBatchGuide,
     W: Write,
 {
     parser: GreedyParser<G>,
-    projectivizer: Option<HeadProjectivizer>,
-    writer: conllx::Writer<W>,
+    projectivizer: Option<PseudoProjectivizer>,
+    writer: SentenceWriter<W>,
+    scored: bool,
     batch_size: usize,
     batch_sents: Vec<Sentence>,
 }
 
 impl<G, W> SentProcessor<G, W>
 where
-    G: BatchGuide,
+    G: ScoredBatchGuide,
     W: Write,
 {
     pub fn new(
         parser: GreedyParser<G>,
-        projectivize: bool,
+        projectivizer: Option<PseudoProjectivizer>,
         batch_size: usize,
-        writer: conllx::Writer<W>,
+        writer: SentenceWriter<W>,
+        scored: bool,
     ) -> Self {
-        let projectivizer = if projectivize {
-            Some(HeadProjectivizer::new())
-        } else {
-            None
-        };
         SentProcessor {
             parser,
             projectivizer,
             writer,
+            scored,
             batch_size,
             batch_sents: Vec::new(),
         }
@@ -186,30 +354,74 @@ where
     }
 
     fn parse_batch(&mut self) -> Result<(), Error> {
-        let dependencies = self
-            .parser
-            .parse_batch(&self.batch_sents)
-            .or_exit("Batch parsing failed", 1);
-        update_sentences(&mut self.batch_sents, dependencies);
-
-        for sentence in &self.batch_sents {
-            if let Some(ref projectivizer) = self.projectivizer {
-                self.writer
-                    .write_sentence(&projectivizer.deprojectivize(&sentence)?)?;
-            } else {
-                self.writer.write_sentence(&sentence)?;
+        // A batch that cannot be parsed is written without dependencies,
+        // so that the output stays aligned with the input.
+        let (parsed, scores) = match self.parse_batch_sents() {
+            Ok(scores) => (true, scores),
+            Err(err) => {
+                eprintln!(
+                    "Cannot parse batch of {} sentences, skipping: {}",
+                    self.batch_sents.len(),
+                    err
+                );
+                self.batch_sents.iter_mut().for_each(clear_dependencies);
+                (false, self.batch_sents.iter().map(|_| None).collect())
             }
+        };
+
+        for (sentence, scores) in self.batch_sents.iter().zip(scores) {
+            let sentence = match self.projectivizer {
+                Some(ref projectivizer) if parsed => {
+                    Cow::Owned(projectivizer.deprojectivize(sentence)?)
+                }
+                _ => Cow::Borrowed(sentence),
+            };
+
+            self.writer.write_sentence(&sentence, scores.as_ref())?;
         }
 
         self.batch_sents.clear();
 
         Ok(())
     }
+
+    /// Parse the sentences in the batch, returning the scores if scoring
+    /// is enabled.
+    fn parse_batch_sents(&mut self) -> Result<Vec<Option<SentenceScores>>, Error> {
+        if !self.scored {
+            let dependencies = self.parser.parse_batch(&self.batch_sents)?;
+            update_sentences(&mut self.batch_sents, dependencies);
+            return Ok(self.batch_sents.iter().map(|_| None).collect());
+        }
+
+        let parses = self.parser.parse_batch_scored(&self.batch_sents)?;
+
+        let mut scores = Vec::with_capacity(parses.len());
+        let mut dependencies = Vec::with_capacity(parses.len());
+        for (sentence, parse) in self.batch_sents.iter().zip(parses) {
+            let mut arcs = vec![None; sentence.len()];
+            let mut dep_set = DependencySet::new();
+            for scored in parse.dependencies {
+                arcs[scored.dependency.dependent - 1] = Some(scored.score);
+                dep_set.insert(scored.dependency);
+            }
+
+            scores.push(Some(SentenceScores {
+                arcs,
+                sentence: parse.score,
+            }));
+            dependencies.push(dep_set);
+        }
+
+        update_sentences(&mut self.batch_sents, dependencies);
+
+        Ok(scores)
+    }
 }
 
 impl<G, W> Drop for SentProcessor<G, W>
 where
-    G: BatchGuide,
+    G: ScoredBatchGuide,
     W: Write,
 {
     fn drop(&mut self) {
@@ -260,11 +472,7 @@ fn update_sentences(sentences: &mut [Sentence], dependencies: Vec<DependencySet>
 
     for (sentence, dep_set) in sentences.iter_mut().zip(dependencies) {
         // Clear any existing annotations.
-        for token in sentence.iter_mut() {
-            token.set_head(None);
-            let none: Option<&str> = None;
-            token.set_head_rel(none);
-        }
+        clear_dependencies(sentence);
 
         for dependency in dep_set {
             let idx = dependency.dependent - 1;
@@ -273,3 +481,20 @@ fn update_sentences(sentences: &mut [Sentence], dependencies: Vec<DependencySet>
         }
     }
 }
+
+fn clear_dependencies(sentence: &mut Sentence) {
+    for token in sentence.iter_mut() {
+        token.set_head(None);
+        let none: Option<&str> = None;
+        token.set_head_rel(none);
+    }
+}
+
+fn add_feature(token: &mut Token, feature: &str, value: f32) {
+    let feature = format!("{}:{}", feature, value);
+    let features = match token.features() {
+        Some(features) => format!("{}|{}", features.as_str(), feature),
+        None => feature,
+    };
+    token.set_features(Some(Features::from_string(features)));
+}
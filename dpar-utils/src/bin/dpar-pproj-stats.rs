@@ -0,0 +1,117 @@
+use std::env::args;
+use std::io::{BufRead, BufWriter, Write};
+
+use conllx::ReadSentence;
+use dpar::pproj::{Encoding, ProjectivizationStats, PseudoProjectivizer};
+use failure::Error;
+use getopts::Options;
+use stdinout::{Input, OrExit, Output};
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] [INPUT] [OUTPUT]", program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn main() {
+    let args: Vec<String> = args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[1..]).or_exit("Cannot parse options", 1);
+
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        return;
+    }
+
+    if matches.free.len() > 2 {
+        print_usage(&program, opts);
+        return;
+    }
+
+    let input = Input::from(matches.free.get(0));
+    let reader = conllx::Reader::new(input.buf_read().or_exit("Cannot open treebank", 1));
+
+    let output = Output::from(matches.free.get(1));
+    let mut writer = BufWriter::new(output.write().or_exit("Cannot create output", 1));
+
+    let stats = collect_stats(reader).or_exit("Cannot projectivize treebank", 1);
+    print_stats(&mut writer, &stats).or_exit("Cannot write statistics", 1);
+}
+
+/// Projectivize the treebank with every encoding.
+fn collect_stats<R>(
+    reader: conllx::Reader<R>,
+) -> Result<Vec<(Encoding, ProjectivizationStats)>, Error>
+where
+    R: BufRead,
+{
+    let projectivizers: Vec<_> = Encoding::all()
+        .iter()
+        .cloned()
+        .map(PseudoProjectivizer::new)
+        .collect();
+    let mut stats = vec![ProjectivizationStats::default(); projectivizers.len()];
+
+    for sentence in reader.sentences() {
+        let sentence = sentence?;
+
+        for (projectivizer, stats) in projectivizers.iter().zip(stats.iter_mut()) {
+            projectivizer.projectivize_with_stats(&sentence, stats)?;
+        }
+    }
+
+    Ok(projectivizers
+        .iter()
+        .map(PseudoProjectivizer::encoding)
+        .zip(stats)
+        .collect())
+}
+
+fn print_stats<W>(writer: &mut W, stats: &[(Encoding, ProjectivizationStats)]) -> Result<(), Error>
+where
+    W: Write,
+{
+    // The treebank statistics do not depend on the encoding.
+    if let Some((_, stats)) = stats.first() {
+        writeln!(writer, "Sentences: {}", stats.n_sentences)?;
+        writeln!(
+            writer,
+            "Non-projective sentences: {} ({:.2}%)",
+            stats.n_nonprojective_sentences,
+            percentage(stats.n_nonprojective_sentences, stats.n_sentences)
+        )?;
+        writeln!(writer, "Arcs: {}", stats.n_arcs)?;
+        writeln!(
+            writer,
+            "Lifted arcs: {} ({:.2}%)",
+            stats.n_lifted_arcs,
+            percentage(stats.n_lifted_arcs, stats.n_arcs)
+        )?;
+        writeln!(writer, "Lifts: {}", stats.n_lifts)?;
+        writeln!(writer, "Relations: {}", stats.n_relations())?;
+        writeln!(writer)?;
+    }
+
+    writeln!(writer, "Encoding\tRelations\tAdded")?;
+    for (encoding, stats) in stats {
+        writeln!(
+            writer,
+            "{}\t{}\t{}",
+            encoding,
+            stats.n_encoded_relations(),
+            stats.n_added_relations()
+        )?;
+    }
+
+    Ok(())
+}
+
+fn percentage(n: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.
+    } else {
+        n as f64 / total as f64 * 100.
+    }
+}
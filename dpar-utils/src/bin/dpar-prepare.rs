@@ -1,22 +1,38 @@
+use std::collections::BTreeMap;
 use std::env::args;
 use std::fs::File;
-use std::io::{BufRead, Write};
+use std::io::{self, BufRead, BufWriter, Write};
 use std::path::Path;
 use std::process;
 
-use conllx::{DisplaySentence, HeadProjectivizer, Projectivize, ReadSentence};
+use conllx::{DisplaySentence, Projectivize, ReadSentence};
 use dpar::features::{InputVectorizer, Layer, Lookup};
-use dpar::system::{sentence_to_dependencies, ParserState};
+use dpar::pproj::PseudoProjectivizer;
+use dpar::system::{sentence_to_dependencies, DependencySet, ParserState, TransitionSystem};
+use dpar::systems::stack_swap::StackSwapOracle;
 use dpar::systems::{
-    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, StackProjectiveSystem, StackSwapSystem,
+    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, ArcSwiftSystem, CovingtonSystem,
+    StackProjectiveSystem, StackSwapSystem,
 };
-use dpar::train::{GreedyTrainer, NoopCollector};
+use dpar::train::{GreedyTrainer, InstanceCollector, NoopCollector};
 use failure::Error;
 use getopts::Options;
 use serde_derive::Serialize;
 use stdinout::{Input, OrExit, Output};
 
-use dpar_utils::{Config, SerializableTransitionSystem, TomlRead};
+use dpar_utils::{
+    Config, ConfigOverride, Lookup as LookupConfig, LookupCoverage, Lookups,
+    SerializableTransitionSystem, SwapOracle, TomlRead, FREQUENCY_BUCKETS,
+};
+
+/// Input layers that have dedicated fields in `Shapes`.
+const DEFAULT_LAYERS: [Layer; 5] = [
+    Layer::TOKEN,
+    Layer::TAG,
+    Layer::DEPREL,
+    Layer::FEATURE,
+    Layer::CHAR,
+];
 
 /// Ad-hoc shapes structure, which can be used to construct the
 /// Tensorflow parsing graph.
@@ -28,9 +44,32 @@ struct Shapes {
     tags: usize,
     deprels: usize,
     features: usize,
+    chars: usize,
     deprel_embeds: usize,
     n_features: usize,
+    char_embeds: usize,
     n_labels: usize,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    fine_tuned: BTreeMap<String, EmbedsShape>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    inputs: BTreeMap<String, InputShape>,
+}
+
+/// Shape of a named input layer.
+#[derive(Serialize)]
+struct InputShape {
+    /// Number of indices per parser state.
+    size: usize,
+
+    /// Number of distinct indices.
+    embeds: usize,
+}
+
+/// Shape of a fine-tuned embedding matrix.
+#[derive(Serialize)]
+struct EmbedsShape {
+    embeds: usize,
+    dims: usize,
 }
 
 fn print_usage(program: &str, opts: Options) {
@@ -44,6 +83,24 @@ fn main() {
 
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this help menu");
+    opts.optopt(
+        "v",
+        "vocab",
+        "write the vocabularies of fine-tuned embeddings to DIR",
+        "DIR",
+    );
+    opts.optopt(
+        "",
+        "validation",
+        "report the lookup coverage of a validation treebank",
+        "FILE",
+    );
+    opts.optmulti(
+        "s",
+        "set",
+        "override a configuration value",
+        "SECTION.KEY=VALUE",
+    );
     let matches = opts.parse(&args[1..]).or_exit("Cannot parse options", 1);
 
     if matches.opt_present("h") {
@@ -56,50 +113,136 @@ fn main() {
         return;
     }
 
+    let overrides = matches
+        .opt_strs("s")
+        .iter()
+        .map(|o| o.parse())
+        .collect::<Result<Vec<ConfigOverride>, _>>()
+        .or_exit("Cannot parse configuration override", 1);
     let config_file = File::open(&matches.free[0]).or_exit("Cannot open configuration file", 1);
-    let mut config =
-        Config::from_toml_read(config_file).or_exit("Cannot read configuration file as TOML", 1);
+    let mut config = Config::from_toml_read_with_overrides(config_file, &overrides)
+        .or_exit("Cannot read configuration file as TOML", 1);
     config
         .relativize_paths(&matches.free[0])
         .or_exit("Cannot relativize paths in the configuration file", 1);
 
     let input = Input::from(matches.free.get(1));
     let treebank_reader = conllx::Reader::new(input.buf_read().or_exit("Cannot open treebank", 1));
+    let validation_reader = matches.opt_str("validation").map(|path| {
+        let input = Input::from(Some(path));
+        conllx::Reader::new(
+            input
+                .buf_read()
+                .or_exit("Cannot open validation treebank", 1),
+        )
+    });
     let output = Output::from(matches.free.get(2));
     let shapes_writer = output.write().or_exit("Cannot create shape file", 1);
 
-    prepare(&config, treebank_reader, shapes_writer).or_exit("Cannot prepare parser data", 1);
+    prepare(&config, treebank_reader, validation_reader, shapes_writer)
+        .or_exit("Cannot prepare parser data", 1);
+
+    if let Some(vocab_dir) = matches.opt_str("v") {
+        write_vocabs(&config, vocab_dir).or_exit("Cannot write vocabularies", 1);
+    }
+}
+
+/// Write the vocabularies of fine-tuned embeddings.
+///
+/// The vocabulary of a layer is written to *LAYER.vocab*, with one
+/// value per line in the order of the embedding matrix indices.
+fn write_vocabs<P>(config: &Config, vocab_dir: P) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    for (layer, lookup) in config.lookups.fine_tuned() {
+        let embeddings = Lookups::load_lookup_embeddings(lookup)?;
+        let path = vocab_dir.as_ref().join(format!("{}.vocab", layer));
+        let mut writer = BufWriter::new(File::create(path)?);
+        for value in embeddings.index_vocab() {
+            writeln!(writer, "{}", value)?;
+        }
+    }
+
+    Ok(())
 }
 
 fn prepare<R, W>(
     config: &Config,
     treebank_reader: conllx::Reader<R>,
+    validation_reader: Option<conllx::Reader<R>>,
     shapes_write: W,
 ) -> Result<(), Error>
 where
     R: BufRead,
     W: Write,
 {
-    let prepare_fun: Box<Fn(_, _, _) -> Result<_, _>> = match config.parser.system.as_ref() {
+    let prepare_fun: Box<Fn(_, _, _, _) -> Result<_, _>> = match config.parser.system.as_ref() {
         "arceager" => Box::new(prepare_with_system::<R, W, ArcEagerSystem>),
         "archybrid" => Box::new(prepare_with_system::<R, W, ArcHybridSystem>),
         "arcstandard" => Box::new(prepare_with_system::<R, W, ArcStandardSystem>),
+        "arcswift" => Box::new(prepare_with_system::<R, W, ArcSwiftSystem>),
         "stackproj" => Box::new(prepare_with_system::<R, W, StackProjectiveSystem>),
-        "stackswap" => Box::new(prepare_with_system::<R, W, StackSwapSystem>),
+        "stackswap" => match config.parser.swap_oracle {
+            SwapOracle::Eager => Box::new(prepare_with_system::<R, W, StackSwapSystem>),
+            SwapOracle::Lazy => Box::new(prepare_with_lazy_swap::<R, W>),
+        },
+        "covington" => Box::new(prepare_with_system::<R, W, CovingtonSystem>),
         _ => {
             eprintln!("Unsupported transition system: {}", config.parser.system);
             process::exit(1);
         }
     };
 
-    prepare_fun(config, treebank_reader, shapes_write)
+    prepare_fun(config, treebank_reader, validation_reader, shapes_write)
 }
 
 fn prepare_with_system<R, W, S>(
     config: &Config,
     treebank_reader: conllx::Reader<R>,
+    validation_reader: Option<conllx::Reader<R>>,
     shapes_write: W,
 ) -> Result<(), Error>
+where
+    R: BufRead,
+    S: SerializableTransitionSystem,
+    W: Write,
+{
+    prepare_with_oracle::<R, W, S>(
+        config,
+        treebank_reader,
+        validation_reader,
+        shapes_write,
+        S::oracle,
+    )
+}
+
+fn prepare_with_lazy_swap<R, W>(
+    config: &Config,
+    treebank_reader: conllx::Reader<R>,
+    validation_reader: Option<conllx::Reader<R>>,
+    shapes_write: W,
+) -> Result<(), Error>
+where
+    R: BufRead,
+    W: Write,
+{
+    prepare_with_oracle::<R, W, StackSwapSystem>(
+        config,
+        treebank_reader,
+        validation_reader,
+        shapes_write,
+        StackSwapOracle::new_lazy,
+    )
+}
+
+fn prepare_with_oracle<R, W, S>(
+    config: &Config,
+    treebank_reader: conllx::Reader<R>,
+    validation_reader: Option<conllx::Reader<R>>,
+    shapes_write: W,
+    oracle: fn(&DependencySet) -> S::Oracle,
+) -> Result<(), Error>
 where
     R: BufRead,
     S: SerializableTransitionSystem,
@@ -107,17 +250,90 @@ where
 {
     let lookups = config.lookups.create_lookups()?;
     let inputs = config.parser.load_inputs()?;
-    let vectorizer = InputVectorizer::new(lookups, inputs);
+    let vectorizer = InputVectorizer::new(lookups, inputs)?;
     let system: S = S::default();
-    let collector = NoopCollector::new(system, vectorizer)?;
-    let mut trainer = GreedyTrainer::new(collector);
-    let projectivizer = HeadProjectivizer::new();
+
+    // The tables that are built are not looked up for coverage
+    // statistics, since a lookup adds the value to the table.
+    let embedding_layers =
+        config
+            .lookups
+            .layer_lookups()
+            .into_iter()
+            .filter_map(|(layer, lookup)| match lookup {
+                LookupConfig::Embedding { .. } => Some(layer),
+                LookupConfig::Table { .. } | LookupConfig::Hash { .. } => None,
+            });
+    let collector = CoverageCollector {
+        collector: NoopCollector::new(system, vectorizer)?,
+        coverage: LookupCoverage::new(embedding_layers),
+    };
+    let mut trainer = GreedyTrainer::with_oracle(collector, oracle);
+    collect_treebank(config, &mut trainer, treebank_reader)?;
+    let collector = trainer.into_collector();
+
+    write_transition_system(&config, collector.collector.transition_system())?;
+
+    write_shapes(config, &collector.collector, shapes_write)?;
+
+    write_coverage(
+        &mut io::stderr(),
+        "training data",
+        &collector.coverage,
+        collector.collector.input_vectorizer(),
+    )?;
+
+    // Write the lookup tables.
+    drop(collector);
+
+    if let Some(validation_reader) = validation_reader {
+        validation_coverage::<R, S>(config, validation_reader, oracle)?;
+    }
+
+    Ok(())
+}
+
+/// Collector that gathers lookup coverage statistics of the states that
+/// are collected.
+struct CoverageCollector<S> {
+    collector: NoopCollector<S>,
+    coverage: LookupCoverage,
+}
+
+impl<S> InstanceCollector<S> for CoverageCollector<S>
+where
+    S: TransitionSystem,
+{
+    fn collect(&mut self, t: &S::Transition, state: &ParserState) -> Result<(), Error> {
+        self.coverage
+            .count(self.collector.input_vectorizer(), state);
+        self.collector.collect(t, state)
+    }
+
+    fn finish_sentence(&mut self) -> Result<(), Error> {
+        self.coverage.finish_sentence();
+        self.collector.finish_sentence()
+    }
+}
+
+/// Collect the parser states of a treebank using its gold-standard
+/// transitions.
+fn collect_treebank<R, S, C>(
+    config: &Config,
+    trainer: &mut GreedyTrainer<S, C>,
+    treebank_reader: conllx::Reader<R>,
+) -> Result<(), Error>
+where
+    R: BufRead,
+    S: TransitionSystem,
+    C: InstanceCollector<S>,
+{
+    let projectivizer = config.parser.pproj.map(PseudoProjectivizer::new);
 
     for sentence in treebank_reader.sentences() {
-        let sentence = if config.parser.pproj {
-            projectivizer.projectivize(&sentence?)?
-        } else {
-            sentence?
+        let sentence = match projectivizer {
+            Some(ref projectivizer) => projectivizer.projectivize(&sentence?)?,
+            None => sentence?,
         };
 
         let dependencies = sentence_to_dependencies(&sentence).or_exit(
@@ -132,42 +348,176 @@ where
         trainer.parse_state(&dependencies, &mut state)?;
     }
 
-    write_transition_system(&config, trainer.collector().transition_system())?;
+    Ok(())
+}
+
+/// Report the lookup coverage of a validation treebank.
+///
+/// The lookup tables that were built from the training data are used,
+/// so unknown values are out-of-vocabulary values.
+fn validation_coverage<R, S>(
+    config: &Config,
+    validation_reader: conllx::Reader<R>,
+    oracle: fn(&DependencySet) -> S::Oracle,
+) -> Result<(), Error>
+where
+    R: BufRead,
+    S: SerializableTransitionSystem,
+{
+    let lookups = config.lookups.load_lookups()?;
+    let inputs = config.parser.load_inputs()?;
+    let vectorizer = InputVectorizer::new(lookups, inputs)?;
+    let layers: Vec<_> = vectorizer
+        .layer_lookups()
+        .iter()
+        .map(|(layer, _)| layer.clone())
+        .collect();
+
+    let collector = CoverageCollector {
+        collector: NoopCollector::new(S::default(), vectorizer)?,
+        coverage: LookupCoverage::new(layers),
+    };
+    let mut trainer = GreedyTrainer::with_oracle(collector, oracle);
+    collect_treebank(config, &mut trainer, validation_reader)?;
+    let collector = trainer.into_collector();
 
-    write_shapes(config, trainer, shapes_write)
+    write_coverage(
+        &mut io::stderr(),
+        "validation data",
+        &collector.coverage,
+        collector.collector.input_vectorizer(),
+    )
+}
+
+/// Write a lookup coverage report.
+///
+/// For each input layer, the report contains the number of distinct
+/// values, the distribution of their corpus frequencies, and the
+/// fractions of lookups of null and unknown values. The out-of-vocabulary
+/// rate is the fraction of unknown values among the non-null lookups.
+fn write_coverage<W>(
+    write: &mut W,
+    description: &str,
+    coverage: &LookupCoverage,
+    vectorizer: &InputVectorizer,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    writeln!(write, "Lookup coverage of the {}:", description)?;
+
+    for (layer, layer_coverage) in coverage.layers() {
+        let lookup_len = vectorizer
+            .layer_lookups()
+            .layer_lookup(layer)
+            .map(Lookup::len)
+            .unwrap_or(0);
+        let n_lookups = layer_coverage.lookups;
+
+        writeln!(
+            write,
+            "  {}: {} values, {} lookups, lookup size: {}",
+            layer,
+            layer_coverage.frequencies.len(),
+            n_lookups,
+            lookup_len
+        )?;
+
+        let distribution: Vec<_> = layer_coverage
+            .frequency_distribution()
+            .into_iter()
+            .enumerate()
+            .map(|(idx, n_values)| {
+                let lower = FREQUENCY_BUCKETS[idx];
+                let bucket = match FREQUENCY_BUCKETS.get(idx + 1) {
+                    Some(&upper) if upper == lower + 1 => lower.to_string(),
+                    Some(&upper) => format!("{}-{}", lower, upper - 1),
+                    None => format!("{}+", lower),
+                };
+                format!("{}: {}", bucket, n_values)
+            })
+            .collect();
+        writeln!(write, "    frequencies: {}", distribution.join(", "))?;
+
+        match layer_coverage.unknown {
+            Some(unknown) => writeln!(
+                write,
+                "    null: {:.2}%, unknown: {:.2}%, OOV rate: {:.2}%",
+                percentage(layer_coverage.null, n_lookups),
+                percentage(unknown, n_lookups),
+                percentage(unknown, n_lookups - layer_coverage.null)
+            )?,
+            None => writeln!(
+                write,
+                "    null: {:.2}%",
+                percentage(layer_coverage.null, n_lookups)
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+fn percentage(n: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.
+    } else {
+        100. * n as f64 / total as f64
+    }
 }
 
 /// Write shape TOML.
 fn write_shapes<W, S>(
     config: &Config,
-    trainer: GreedyTrainer<S, NoopCollector<S>>,
+    collector: &NoopCollector<S>,
     mut shapes_write: W,
 ) -> Result<(), Error>
 where
     W: Write,
     S: SerializableTransitionSystem,
 {
-    let vectorizer = trainer.collector().input_vectorizer();
+    let vectorizer = collector.input_vectorizer();
     let layer_sizes = vectorizer.lookup_layer_sizes();
     let embed_size = vectorizer.embedding_layer_size();
     let layer_lookups = vectorizer.layer_lookups();
+    let layer_size = |layer: &Layer| layer_sizes.get(layer).cloned().unwrap_or(0);
+    let lookup_len = |layer: &Layer| {
+        layer_lookups
+            .layer_lookup(layer)
+            .map(Lookup::len)
+            .unwrap_or(0)
+    };
 
     let shapes = Shapes {
         batch_size: config.parser.train_batch_size,
         embed_size,
-        tokens: layer_sizes[Layer::Token],
-        tags: layer_sizes[Layer::Tag],
-        deprels: layer_sizes[Layer::DepRel],
-        features: layer_sizes[Layer::Feature],
-        deprel_embeds: layer_lookups
-            .layer_lookup(Layer::DepRel)
-            .map(Lookup::len)
-            .unwrap_or(0),
-        n_features: layer_lookups
-            .layer_lookup(Layer::Feature)
-            .map(Lookup::len)
-            .unwrap_or(0),
-        n_labels: trainer.collector().transition_system().transitions().len(),
+        tokens: layer_size(&Layer::TOKEN),
+        tags: layer_size(&Layer::TAG),
+        deprels: layer_size(&Layer::DEPREL),
+        features: layer_size(&Layer::FEATURE),
+        chars: layer_size(&Layer::CHAR),
+        deprel_embeds: lookup_len(&Layer::DEPREL),
+        n_features: lookup_len(&Layer::FEATURE),
+        char_embeds: lookup_len(&Layer::CHAR),
+        n_labels: collector.transition_system().transitions().len(),
+        fine_tuned: fine_tuned_shapes(config)?,
+        // Named input layers with embedding lookups are part of the
+        // embedding layer and do not need a placeholder.
+        inputs: config
+            .lookups
+            .layer_lookups()
+            .into_iter()
+            .map(|(layer, _)| layer)
+            .filter(|layer| !DEFAULT_LAYERS.contains(layer))
+            .filter(|layer| layer_sizes.contains_key(layer))
+            .map(|layer| {
+                let shape = InputShape {
+                    size: layer_size(&layer),
+                    embeds: lookup_len(&layer),
+                };
+                (layer.to_string(), shape)
+            })
+            .collect(),
     };
 
     write!(
@@ -179,6 +529,24 @@ where
     Ok(())
 }
 
+/// Get the shapes of the fine-tuned embedding matrices.
+fn fine_tuned_shapes(config: &Config) -> Result<BTreeMap<String, EmbedsShape>, Error> {
+    let mut shapes = BTreeMap::new();
+
+    for (layer, lookup) in config.lookups.fine_tuned() {
+        let embeddings = Lookups::load_lookup_embeddings(lookup)?;
+        shapes.insert(
+            layer.to_string(),
+            EmbedsShape {
+                embeds: embeddings.len(),
+                dims: embeddings.dims(),
+            },
+        );
+    }
+
+    Ok(shapes)
+}
+
 fn write_transition_system<T>(config: &Config, system: &T) -> Result<(), Error>
 where
     T: SerializableTransitionSystem,
@@ -0,0 +1,261 @@
+use std::env::args;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::process;
+
+use colored::*;
+use conllx::{DisplaySentence, Projectivize, ReadSentence};
+use dpar::features::{InputVectorizer, Lookup, LookupResult};
+use dpar::guide::Guide;
+use dpar::pproj::PseudoProjectivizer;
+use dpar::system::{
+    sentence_to_dependencies, DependencySet, ParserState, Transition, TransitionSystem,
+};
+use dpar::systems::stack_swap::StackSwapOracle;
+use dpar::systems::{
+    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, ArcSwiftSystem, CovingtonSystem,
+    StackProjectiveSystem, StackSwapSystem,
+};
+use failure::{format_err, Error};
+use getopts::Options;
+use stdinout::{Input, OrExit, Output};
+
+use dpar_utils::{Config, ConfigOverride, SwapOracle, TomlRead};
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] CONFIG [INPUT] [OUTPUT]", program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn main() {
+    let args: Vec<String> = args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optmulti(
+        "s",
+        "set",
+        "override a configuration value",
+        "SECTION.KEY=VALUE",
+    );
+    let matches = opts.parse(&args[1..]).or_exit("Cannot parse options", 1);
+
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        return;
+    }
+
+    if matches.free.is_empty() || matches.free.len() > 3 {
+        print_usage(&program, opts);
+        return;
+    }
+
+    let overrides = matches
+        .opt_strs("s")
+        .iter()
+        .map(|o| o.parse())
+        .collect::<Result<Vec<ConfigOverride>, _>>()
+        .or_exit("Cannot parse configuration override", 1);
+    let config_file = File::open(&matches.free[0]).or_exit("Cannot open configuration file", 1);
+    let mut config = Config::from_toml_read_with_overrides(config_file, &overrides)
+        .or_exit("Cannot read configuration file as TOML", 1);
+    config
+        .relativize_paths(&matches.free[0])
+        .or_exit("Cannot relativize paths in the configuration file", 1);
+
+    let input = Input::from(matches.free.get(1));
+    let reader = conllx::Reader::new(input.buf_read().or_exit("Cannot open treebank", 1));
+
+    let output = Output::from(matches.free.get(2));
+    let writer = BufWriter::new(output.write().or_exit("Cannot create feature output", 1));
+
+    parse(&config, reader, writer).or_exit("Cannot print features", 1);
+}
+
+fn parse<R, W>(
+    config: &Config,
+    reader: conllx::Reader<R>,
+    writer: BufWriter<W>,
+) -> Result<(), Error>
+where
+    R: BufRead,
+    W: Write,
+{
+    let lookups = config.lookups.load_lookups()?;
+    let inputs = config.parser.load_inputs()?;
+    let vectorizer = InputVectorizer::new(lookups, inputs)?;
+    let projectivizer = config.parser.pproj.map(PseudoProjectivizer::new);
+
+    match config.parser.system.as_ref() {
+        "arceager" => {
+            parse_with_system::<R, W, ArcEagerSystem>(&vectorizer, reader, writer, projectivizer)
+        }
+        "archybrid" => {
+            parse_with_system::<R, W, ArcHybridSystem>(&vectorizer, reader, writer, projectivizer)
+        }
+        "arcstandard" => {
+            parse_with_system::<R, W, ArcStandardSystem>(&vectorizer, reader, writer, projectivizer)
+        }
+        "arcswift" => {
+            parse_with_system::<R, W, ArcSwiftSystem>(&vectorizer, reader, writer, projectivizer)
+        }
+        "stackproj" => parse_with_system::<R, W, StackProjectiveSystem>(
+            &vectorizer,
+            reader,
+            writer,
+            projectivizer,
+        ),
+        "stackswap" => match config.parser.swap_oracle {
+            SwapOracle::Eager => parse_with_system::<R, W, StackSwapSystem>(
+                &vectorizer,
+                reader,
+                writer,
+                projectivizer,
+            ),
+            SwapOracle::Lazy => parse_with_oracle::<R, W, StackSwapSystem>(
+                &vectorizer,
+                reader,
+                writer,
+                projectivizer,
+                StackSwapOracle::new_lazy,
+            ),
+        },
+        "covington" => {
+            parse_with_system::<R, W, CovingtonSystem>(&vectorizer, reader, writer, projectivizer)
+        }
+        _ => {
+            eprintln!("Unsupported transition system: {}", config.parser.system);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_with_system<R, W, S>(
+    vectorizer: &InputVectorizer,
+    reader: conllx::Reader<R>,
+    writer: BufWriter<W>,
+    projectivizer: Option<PseudoProjectivizer>,
+) -> Result<(), Error>
+where
+    R: BufRead,
+    W: Write,
+    S: TransitionSystem,
+{
+    parse_with_oracle::<R, W, S>(vectorizer, reader, writer, projectivizer, S::oracle)
+}
+
+fn parse_with_oracle<R, W, S>(
+    vectorizer: &InputVectorizer,
+    reader: conllx::Reader<R>,
+    mut writer: BufWriter<W>,
+    projectivizer: Option<PseudoProjectivizer>,
+    oracle: fn(&DependencySet) -> S::Oracle,
+) -> Result<(), Error>
+where
+    R: BufRead,
+    W: Write,
+    S: TransitionSystem,
+{
+    for sentence in reader.sentences() {
+        let sentence = match projectivizer {
+            Some(ref projectivizer) => projectivizer.projectivize(&sentence?)?,
+            None => sentence?,
+        };
+
+        let gold_dependencies = sentence_to_dependencies(&sentence).or_exit(
+            format!(
+                "Cannot extract gold dependencies:\n{}",
+                DisplaySentence(&sentence)
+            ),
+            1,
+        );
+        let mut oracle = oracle(&gold_dependencies);
+
+        let mut state = ParserState::new(&sentence);
+
+        while !S::is_terminal(&state) {
+            let next_transition = oracle.best_transition(&state)?;
+
+            // Print the gold transition and the features of the state
+            // in which it is applied.
+            writeln!(writer, "{}", format!("{:?}", next_transition).purple())?;
+            print_features(&mut writer, vectorizer, &state)?;
+
+            next_transition.apply(&mut state);
+        }
+
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+/// Print the features of a parser state.
+///
+/// Every addressed value is printed on a separate line with its value,
+/// input layer, and the lookup indices that the vectorizer uses.
+fn print_features<W>(
+    writer: &mut W,
+    vectorizer: &InputVectorizer,
+    state: &ParserState,
+) -> Result<(), Error>
+where
+    W: Write,
+{
+    for ((addr_value, input_layer), results) in vectorizer
+        .layer_addrs()
+        .0
+        .iter()
+        .zip(vectorizer.input_layers())
+        .zip(vectorizer.lookup_results(state))
+    {
+        let lookup = vectorizer
+            .layer_lookups()
+            .layer_lookup(input_layer)
+            .ok_or_else(|| format_err!("Missing layer lookup for: {}", input_layer))?;
+
+        let value = addr_value.get(state);
+        let indices: Vec<_> = results
+            .iter()
+            .map(|(value, result)| {
+                lookup_marker(lookup, value.as_ref().map(String::as_str), result)
+            })
+            .collect();
+
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            addr_value,
+            value
+                .as_ref()
+                .map(|v| v.escape_debug().to_string())
+                .unwrap_or_else(|| "_".to_owned()),
+            input_layer,
+            indices.join(" ")
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Format a lookup result.
+///
+/// Absent values are marked as `NULL` and values that are not in the
+/// lookup as `UNK`. Other values are printed as their index, or as
+/// `<embedding>` for embedding lookups.
+fn lookup_marker(lookup: &Lookup, value: Option<&str>, result: &LookupResult) -> String {
+    let value = match value {
+        Some(value) => value,
+        None => return "NULL".blue().to_string(),
+    };
+
+    if lookup.lookup(value).is_none() {
+        return "UNK".red().to_string();
+    }
+
+    match *result {
+        LookupResult::Index(idx) => idx.to_string(),
+        LookupResult::Embedding(_) => "<embedding>".to_owned(),
+    }
+}
@@ -3,11 +3,16 @@ use std::io::{BufRead, BufWriter, Write};
 use std::process;
 
 use colored::*;
-use conllx::{DisplaySentence, HeadProjectivizer, Projectivize, ReadSentence};
+use conllx::{DisplaySentence, Projectivize, ReadSentence};
 use dpar::guide::Guide;
-use dpar::system::{sentence_to_dependencies, ParserState, Transition, TransitionSystem};
+use dpar::pproj::{Encoding, PseudoProjectivizer};
+use dpar::system::{
+    sentence_to_dependencies, DependencySet, ParserState, Transition, TransitionSystem,
+};
+use dpar::systems::stack_swap::StackSwapOracle;
 use dpar::systems::{
-    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, StackProjectiveSystem, StackSwapSystem,
+    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, ArcSwiftSystem, CovingtonSystem,
+    StackProjectiveSystem, StackSwapSystem,
 };
 use failure::Error;
 use getopts::Options;
@@ -24,6 +29,13 @@ fn main() {
 
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this help menu");
+    opts.optflag("l", "lazy-swap", "use the lazy oracle for stackswap");
+    opts.optopt(
+        "p",
+        "pproj",
+        "pseudo-projective encoding: head, head+path, path, or none (default: head)",
+        "ENCODING",
+    );
     let matches = opts.parse(&args[1..]).or_exit("Cannot parse options", 1);
 
     if matches.opt_present("h") {
@@ -36,26 +48,69 @@ fn main() {
         return;
     }
 
+    let projectivizer = match matches.opt_str("p").as_ref().map(String::as_str) {
+        Some("none") => None,
+        Some(encoding) => Some(PseudoProjectivizer::new(
+            encoding
+                .parse()
+                .or_exit("Cannot parse pseudo-projective encoding", 1),
+        )),
+        None => Some(PseudoProjectivizer::new(Encoding::Head)),
+    };
+
     let input = Input::from(matches.free.get(1));
     let reader = conllx::Reader::new(input.buf_read().or_exit("Cannot open treebank", 1));
 
     let output = Output::from(matches.free.get(2));
     let writer = BufWriter::new(output.write().or_exit("Cannot create transition output", 1));
 
-    parse(&matches.free[0], reader, writer).or_exit("Cannot print transitions", 1);
+    let options = PrintOptions {
+        lazy_swap: matches.opt_present("l"),
+        projectivizer,
+    };
+
+    parse(&matches.free[0], options, reader, writer).or_exit("Cannot print transitions", 1);
+}
+
+/// Transition printing options.
+struct PrintOptions {
+    /// Use the lazy stack-swap oracle.
+    lazy_swap: bool,
+
+    /// Projectivizer that is applied to the gold trees.
+    projectivizer: Option<PseudoProjectivizer>,
 }
 
-fn parse<R, W>(system: &str, reader: conllx::Reader<R>, writer: BufWriter<W>) -> Result<(), Error>
+fn parse<R, W>(
+    system: &str,
+    options: PrintOptions,
+    reader: conllx::Reader<R>,
+    writer: BufWriter<W>,
+) -> Result<(), Error>
 where
     R: BufRead,
     W: Write,
 {
+    let projectivizer = options.projectivizer;
+
     match system {
-        "arceager" => parse_with_system::<R, W, ArcEagerSystem>(reader, writer),
-        "archybrid" => parse_with_system::<R, W, ArcHybridSystem>(reader, writer),
-        "arcstandard" => parse_with_system::<R, W, ArcStandardSystem>(reader, writer),
-        "stackproj" => parse_with_system::<R, W, StackProjectiveSystem>(reader, writer),
-        "stackswap" => parse_with_system::<R, W, StackSwapSystem>(reader, writer),
+        "arceager" => parse_with_system::<R, W, ArcEagerSystem>(reader, writer, projectivizer),
+        "archybrid" => parse_with_system::<R, W, ArcHybridSystem>(reader, writer, projectivizer),
+        "arcstandard" => {
+            parse_with_system::<R, W, ArcStandardSystem>(reader, writer, projectivizer)
+        }
+        "arcswift" => parse_with_system::<R, W, ArcSwiftSystem>(reader, writer, projectivizer),
+        "stackproj" => {
+            parse_with_system::<R, W, StackProjectiveSystem>(reader, writer, projectivizer)
+        }
+        "stackswap" if options.lazy_swap => parse_with_oracle::<R, W, StackSwapSystem>(
+            reader,
+            writer,
+            projectivizer,
+            StackSwapOracle::new_lazy,
+        ),
+        "stackswap" => parse_with_system::<R, W, StackSwapSystem>(reader, writer, projectivizer),
+        "covington" => parse_with_system::<R, W, CovingtonSystem>(reader, writer, projectivizer),
         _ => {
             eprintln!("Unsupported transition system: {}", system);
             process::exit(1);
@@ -65,17 +120,33 @@ where
 
 fn parse_with_system<R, W, S>(
     reader: conllx::Reader<R>,
-    mut writer: BufWriter<W>,
+    writer: BufWriter<W>,
+    projectivizer: Option<PseudoProjectivizer>,
 ) -> Result<(), Error>
 where
     R: BufRead,
     W: Write,
     S: TransitionSystem,
 {
-    let projectivizer = HeadProjectivizer::new();
+    parse_with_oracle::<R, W, S>(reader, writer, projectivizer, S::oracle)
+}
 
+fn parse_with_oracle<R, W, S>(
+    reader: conllx::Reader<R>,
+    mut writer: BufWriter<W>,
+    projectivizer: Option<PseudoProjectivizer>,
+    oracle: fn(&DependencySet) -> S::Oracle,
+) -> Result<(), Error>
+where
+    R: BufRead,
+    W: Write,
+    S: TransitionSystem,
+{
     for sentence in reader.sentences() {
-        let sentence = projectivizer.projectivize(&sentence?)?;
+        let sentence = match projectivizer {
+            Some(ref projectivizer) => projectivizer.projectivize(&sentence?)?,
+            None => sentence?,
+        };
 
         let gold_dependencies = sentence_to_dependencies(&sentence).or_exit(
             format!(
@@ -84,7 +155,7 @@ where
             ),
             1,
         );
-        let mut oracle = S::oracle(&gold_dependencies);
+        let mut oracle = oracle(&gold_dependencies);
 
         let mut state = ParserState::new(&sentence);
 
@@ -93,7 +164,7 @@ where
         print_tokens(&mut writer, &state, Source::Buffer)?;
 
         while !S::is_terminal(&state) {
-            let next_transition = oracle.best_transition(&state);
+            let next_transition = oracle.best_transition(&state)?;
             next_transition.apply(&mut state);
 
             // Print transition and state.
@@ -120,21 +191,20 @@ where
         Source::Stack => "Stack",
     };
 
-    let indices = match source {
-        Source::Buffer => state.buffer(),
-        Source::Stack => state.stack(),
-    };
-
-    writeln!(
-        writer,
-        "{}: {}",
-        prefix,
-        indices
+    let tokens = match source {
+        Source::Buffer => state
+            .buffer()
             .iter()
             .map(|&idx| state.tokens()[idx])
-            .collect::<Vec<_>>()
-            .join(", ")
-    )?;
+            .collect::<Vec<_>>(),
+        Source::Stack => state
+            .stack()
+            .iter()
+            .map(|&idx| state.tokens()[idx])
+            .collect::<Vec<_>>(),
+    };
+
+    writeln!(writer, "{}: {}", prefix, tokens.join(", "))?;
 
     Ok(())
 }
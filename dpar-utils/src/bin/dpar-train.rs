@@ -1,25 +1,39 @@
 use std::env::args;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, BufWriter};
 use std::path::Path;
 use std::process;
 
-use conllx::{DisplaySentence, HeadProjectivizer, Projectivize, ReadSentence};
+use conllx::{DisplaySentence, Projectivize, ReadSentence};
 use dpar::features::InputVectorizer;
 use dpar::models::lr::LearningRateSchedule;
-use dpar::models::tensorflow::{TensorCollector, TensorCollectorParts, TensorflowModel};
-use dpar::system::{sentence_to_dependencies, ParserState};
+use dpar::models::tensorflow::{
+    IndexDropout, TensorCollector, TensorCollectorParts, TensorflowModel,
+};
+use dpar::pproj::PseudoProjectivizer;
+use dpar::system::{sentence_to_dependencies, DependencySet, ParserState};
+use dpar::systems::stack_swap::StackSwapOracle;
 use dpar::systems::{
-    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, StackProjectiveSystem, StackSwapSystem,
+    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, ArcSwiftSystem, CovingtonSystem,
+    StackProjectiveSystem, StackSwapSystem,
 };
 use dpar::train::GreedyTrainer;
 use failure::Error;
 use getopts::Options;
 use indicatif::{ProgressBar, ProgressStyle};
 use itertools::izip;
+use rand::{thread_rng, Rng};
+use rust2vec::io::WriteEmbeddings;
 use stdinout::OrExit;
 
-use dpar_utils::{Config, FileProgress, SerializableTransitionSystem, TomlRead};
+use dpar_utils::{
+    write_bundle, Config, ConfigOverride, FileProgress, Lookups, SerializableTransitionSystem,
+    SwapOracle, TomlRead, TomlWrite,
+};
+
+/// The effective configuration (after applying overrides) is stored
+/// alongside the epoch parameter files.
+static EFFECTIVE_CONFIG: &str = "effective-config.conf";
 
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [options] CONFIG TRAIN_DATA VALID_DATA", program);
@@ -31,7 +45,14 @@ fn main() {
     let program = args[0].clone();
 
     let mut opts = Options::new();
+    opts.optopt("b", "bundle", "write the best model to a bundle", "BUNDLE");
     opts.optflag("h", "help", "print this help menu");
+    opts.optmulti(
+        "s",
+        "set",
+        "override a configuration value",
+        "SECTION.KEY=VALUE",
+    );
     let matches = opts.parse(&args[1..]).or_exit("Cannot parse options", 1);
 
     if matches.opt_present("h") {
@@ -44,12 +65,19 @@ fn main() {
         return;
     }
 
+    let overrides = matches
+        .opt_strs("s")
+        .iter()
+        .map(|o| o.parse())
+        .collect::<Result<Vec<ConfigOverride>, _>>()
+        .or_exit("Cannot parse configuration override", 1);
     let config_file = File::open(&matches.free[0]).or_exit("Cannot open configuration file", 1);
-    let mut config =
-        Config::from_toml_read(config_file).or_exit("Cannot read configuration file as TOML", 1);
+    let mut config = Config::from_toml_read_with_overrides(config_file, &overrides)
+        .or_exit("Cannot read configuration file as TOML", 1);
     config
         .relativize_paths(&matches.free[0])
         .or_exit("Cannot relativize paths in the configuration file", 1);
+    write_effective_config(&config).or_exit("Cannot write effective configuration", 1);
 
     let input_file = File::open(&matches.free[1]).or_exit("Cannot open training treebank", 1);
     let reader = conllx::Reader::new(BufReader::new(
@@ -64,7 +92,8 @@ fn main() {
         .parser
         .load_inputs()
         .or_exit("Cannot load lookups", 1);
-    let vectorizer = InputVectorizer::new(lookups, inputs);
+    let vectorizer =
+        InputVectorizer::new(lookups, inputs).or_exit("Cannot construct vectorizer", 1);
 
     eprintln!("Vectorizing training data...");
     let train_parts =
@@ -78,7 +107,15 @@ fn main() {
     let validation_parts =
         collect_data(&config, &vectorizer, reader).or_exit("Tensor collection failed", 1);
 
-    train(&config, vectorizer, train_parts, validation_parts).or_exit("Training failed", 1);
+    let best_epoch =
+        train(&config, vectorizer, train_parts, validation_parts).or_exit("Training failed", 1);
+
+    if let Some(bundle) = matches.opt_str("b") {
+        eprintln!("Writing model bundle to: {}", bundle);
+        let bundle_file = File::create(&bundle).or_exit("Cannot create model bundle", 1);
+        write_bundle(&config, &format!("epoch-{}", best_epoch), bundle_file)
+            .or_exit("Cannot write model bundle", 1);
+    }
 }
 
 fn train(
@@ -86,13 +123,15 @@ fn train(
     vectorizer: InputVectorizer,
     train_parts: TensorCollectorParts,
     validation_parts: TensorCollectorParts,
-) -> Result<(), Error> {
+) -> Result<usize, Error> {
     let train_fun: Box<Fn(_, _, _, _) -> Result<_, _>> = match config.parser.system.as_ref() {
         "arceager" => Box::new(train_with_system::<ArcEagerSystem>),
         "archybrid" => Box::new(train_with_system::<ArcHybridSystem>),
         "arcstandard" => Box::new(train_with_system::<ArcStandardSystem>),
+        "arcswift" => Box::new(train_with_system::<ArcSwiftSystem>),
         "stackproj" => Box::new(train_with_system::<StackProjectiveSystem>),
         "stackswap" => Box::new(train_with_system::<StackSwapSystem>),
+        "covington" => Box::new(train_with_system::<CovingtonSystem>),
         _ => {
             eprintln!("Unsupported transition system: {}", config.parser.system);
             process::exit(1);
@@ -107,11 +146,12 @@ fn train_with_system<S>(
     vectorizer: InputVectorizer,
     train_parts: TensorCollectorParts,
     validation_parts: TensorCollectorParts,
-) -> Result<(), Error>
+) -> Result<usize, Error>
 where
     S: SerializableTransitionSystem,
 {
-    let system = S::default();
+    let system: S = load_transition_system_or_new(config)?;
+    let dropout = config.train.index_dropout(vectorizer.layer_lookups())?;
     let mut model = TensorflowModel::load_graph(
         &config
             .model
@@ -130,11 +170,12 @@ where
     let mut best_acc = 0.0;
 
     let lr_schedule = config.train.lr_schedule();
+    let mut rng = thread_rng();
 
     for epoch in 0.. {
         let lr = lr_schedule.learning_rate(epoch);
 
-        let (loss, acc) = run_epoch(&mut model, &train_parts, true, lr);
+        let (loss, acc) = run_epoch(&mut model, &train_parts, true, &dropout, &mut rng, lr);
         eprintln!(
             "Epoch {} (train, lr: {}): loss: {:.4}, acc: {:.4}",
             epoch, lr, loss, acc
@@ -143,7 +184,7 @@ where
             .save(format!("epoch-{}", epoch))
             .or_exit(format!("Cannot save model for epoch {}", epoch), 1);
 
-        let (_, acc) = run_epoch(&mut model, &validation_parts, false, lr);
+        let (_, acc) = run_epoch(&mut model, &validation_parts, false, &dropout, &mut rng, lr);
 
         if acc > best_acc {
             best_epoch = epoch;
@@ -164,17 +205,56 @@ where
         }
     }
 
+    write_fine_tuned_embeddings(config, &mut model, best_epoch)?;
+
+    Ok(best_epoch)
+}
+
+/// Write the fine-tuned embeddings of the best epoch.
+///
+/// The embeddings of each layer with fine-tuned embeddings are written
+/// to *epoch-N-LAYER.embeds*.
+fn write_fine_tuned_embeddings<S>(
+    config: &Config,
+    model: &mut TensorflowModel<S>,
+    best_epoch: usize,
+) -> Result<(), Error>
+where
+    S: SerializableTransitionSystem,
+{
+    let fine_tuned = config.lookups.fine_tuned();
+    if fine_tuned.is_empty() {
+        return Ok(());
+    }
+
+    model.restore(format!("epoch-{}", best_epoch))?;
+
+    for (layer, lookup) in fine_tuned {
+        let embeddings = Lookups::load_lookup_embeddings(lookup)?;
+        let matrix = model.embedding_matrix(&layer)?;
+        let embeddings = embeddings.fine_tuned(matrix.view())?;
+
+        let path = format!("epoch-{}-{}.embeds", best_epoch, layer);
+        eprintln!("Writing fine-tuned {} embeddings to: {}", layer, path);
+        let mut f = BufWriter::new(File::create(&path)?);
+        embeddings.write_embeddings(&mut f)?;
+    }
+
     Ok(())
 }
 
-fn run_epoch<S>(
+/// Run an epoch. Index dropout is applied to the batches when training.
+fn run_epoch<S, R>(
     model: &mut TensorflowModel<S>,
     parts: &TensorCollectorParts,
     is_training: bool,
+    dropout: &IndexDropout,
+    rng: &mut R,
     lr: f32,
 ) -> (f32, f32)
 where
     S: SerializableTransitionSystem,
+    R: Rng,
 {
     let epoch_type = if is_training { "train" } else { "validation" };
 
@@ -192,7 +272,9 @@ where
         parts.embeds.iter(),
         parts.inputs.iter()
     ) {
-        let batch_perf = if is_training {
+        let batch_perf = if is_training && !dropout.is_empty() {
+            model.train(embeds, &dropout.apply(inputs, rng), labels, lr)
+        } else if is_training {
             model.train(embeds, inputs, labels, lr)
         } else {
             model.validate(embeds, inputs, labels)
@@ -223,8 +305,13 @@ where
         "arceager" => Box::new(collect_with_system::<R, ArcEagerSystem>),
         "archybrid" => Box::new(collect_with_system::<R, ArcHybridSystem>),
         "arcstandard" => Box::new(collect_with_system::<R, ArcStandardSystem>),
+        "arcswift" => Box::new(collect_with_system::<R, ArcSwiftSystem>),
         "stackproj" => Box::new(collect_with_system::<R, StackProjectiveSystem>),
-        "stackswap" => Box::new(collect_with_system::<R, StackSwapSystem>),
+        "stackswap" => match config.parser.swap_oracle {
+            SwapOracle::Eager => Box::new(collect_with_system::<R, StackSwapSystem>),
+            SwapOracle::Lazy => Box::new(collect_with_lazy_swap::<R>),
+        },
+        "covington" => Box::new(collect_with_system::<R, CovingtonSystem>),
         _ => {
             eprintln!("Unsupported transition system: {}", config.parser.system);
             process::exit(1);
@@ -239,20 +326,43 @@ fn collect_with_system<R, S>(
     vectorizer: &InputVectorizer,
     reader: conllx::Reader<R>,
 ) -> Result<TensorCollectorParts, Error>
+where
+    R: BufRead,
+    S: SerializableTransitionSystem,
+{
+    collect_with_oracle::<R, S>(config, vectorizer, reader, S::oracle)
+}
+
+fn collect_with_lazy_swap<R>(
+    config: &Config,
+    vectorizer: &InputVectorizer,
+    reader: conllx::Reader<R>,
+) -> Result<TensorCollectorParts, Error>
+where
+    R: BufRead,
+{
+    collect_with_oracle::<R, StackSwapSystem>(config, vectorizer, reader, StackSwapOracle::new_lazy)
+}
+
+fn collect_with_oracle<R, S>(
+    config: &Config,
+    vectorizer: &InputVectorizer,
+    reader: conllx::Reader<R>,
+    oracle: fn(&DependencySet) -> S::Oracle,
+) -> Result<TensorCollectorParts, Error>
 where
     R: BufRead,
     S: SerializableTransitionSystem,
 {
     let system: S = load_transition_system_or_new(&config)?;
     let collector = TensorCollector::new(system, &vectorizer, config.parser.train_batch_size);
-    let mut trainer = GreedyTrainer::new(collector);
-    let projectivizer = HeadProjectivizer::new();
+    let mut trainer = GreedyTrainer::with_oracle(collector, oracle);
+    let projectivizer = config.parser.pproj.map(PseudoProjectivizer::new);
 
     for sentence in reader.sentences() {
-        let sentence = if config.parser.pproj {
-            projectivizer.projectivize(&sentence?)?
-        } else {
-            sentence?
+        let sentence = match projectivizer {
+            Some(ref projectivizer) => projectivizer.projectivize(&sentence?)?,
+            None => sentence?,
         };
 
         let dependencies = sentence_to_dependencies(&sentence).or_exit(
@@ -286,3 +396,12 @@ where
 
     Ok(system)
 }
+
+fn write_effective_config(config: &Config) -> Result<(), Error> {
+    eprintln!("Writing effective configuration to: {}", EFFECTIVE_CONFIG);
+
+    let mut f = BufWriter::new(File::create(EFFECTIVE_CONFIG)?);
+    config.to_toml_write(&mut f)?;
+
+    Ok(())
+}
@@ -0,0 +1,505 @@
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use failure::{ensure, format_err, Error};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tar::{Archive, Builder, Header};
+use tempfile::TempDir;
+
+use crate::{Config, Lookup, Lookups, TomlWrite};
+
+/// Version of the bundle format.
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+static CONFIG: &str = "config.conf";
+static MANIFEST: &str = "MANIFEST.toml";
+
+/// Kind of a file in a model bundle.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    Config,
+    Embeddings,
+    Graph,
+    Inputs,
+    LookupTable,
+    Parameters,
+    Transitions,
+}
+
+impl FileKind {
+    /// The version of the file format that is written by this version
+    /// of dpar.
+    ///
+    /// The version should be bumped when a file format changes in an
+    /// incompatible way.
+    pub fn format_version(self) -> u32 {
+        match self {
+            FileKind::Config => 1,
+            FileKind::Embeddings => 1,
+            FileKind::Graph => 1,
+            FileKind::Inputs => 1,
+            FileKind::LookupTable => 2,
+            FileKind::Parameters => 1,
+            FileKind::Transitions => 1,
+        }
+    }
+}
+
+/// A file in a model bundle.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ManifestEntry {
+    /// The path of the file within the bundle.
+    pub path: String,
+
+    /// The kind of file.
+    pub kind: FileKind,
+
+    /// The version of the file format.
+    pub format_version: u32,
+
+    /// The SHA-256 checksum of the file.
+    pub sha256: String,
+}
+
+/// Manifest of a model bundle.
+///
+/// The manifest lists all the files in a bundle, together with their
+/// format versions and checksums.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Manifest {
+    /// The version of the bundle format.
+    pub format_version: u32,
+
+    /// The version of dpar that created the bundle.
+    pub dpar_version: String,
+
+    /// The files in the bundle.
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Verify the files of an extracted bundle.
+    ///
+    /// This checks that the format versions are supported and that the
+    /// checksums of the files match the checksums in the manifest.
+    pub fn verify<P>(&self, bundle_dir: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let bundle_dir = bundle_dir.as_ref();
+
+        ensure!(
+            self.format_version <= BUNDLE_FORMAT_VERSION,
+            "Unsupported bundle format version: {} (supported: {})",
+            self.format_version,
+            BUNDLE_FORMAT_VERSION
+        );
+
+        for entry in &self.files {
+            ensure!(
+                entry.format_version <= entry.kind.format_version(),
+                "Unsupported format version for {}: {} (supported: {})",
+                entry.path,
+                entry.format_version,
+                entry.kind.format_version()
+            );
+
+            let f = File::open(bundle_dir.join(&entry.path))
+                .map_err(|err| format_err!("Cannot open bundle file {}: {}", entry.path, err))?;
+            let checksum = sha256(f)?;
+            ensure!(
+                checksum == entry.sha256,
+                "Checksum mismatch for bundle file {}",
+                entry.path
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// A model bundle.
+///
+/// A model bundle is a single archive that contains the configuration
+/// of a trained model and all the files that the configuration refers to.
+/// An opened bundle is extracted to a temporary directory, which is
+/// removed when the bundle is dropped.
+pub struct Bundle {
+    dir: TempDir,
+    manifest: Manifest,
+}
+
+impl Bundle {
+    /// Open and verify a model bundle.
+    pub fn open<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = TempDir::new()?;
+
+        let f = File::open(path)?;
+        Archive::new(BufReader::new(f)).unpack(dir.path())?;
+
+        let manifest: Manifest = toml::from_str(&fs::read_to_string(dir.path().join(MANIFEST))?)?;
+        manifest.verify(dir.path())?;
+
+        Ok(Bundle { dir, manifest })
+    }
+
+    /// Check whether a file is a model bundle.
+    pub fn is_bundle<P>(path: P) -> Result<bool, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut header = Vec::with_capacity(512);
+        File::open(path)?.take(512).read_to_end(&mut header)?;
+
+        // Check for the ustar magic of tar archives.
+        Ok(header.len() == 512 && &header[257..262] == b"ustar")
+    }
+
+    /// Get the path of the bundle configuration.
+    ///
+    /// The paths in the configuration are relative to the configuration
+    /// file, so `Config::relativize_paths` should be used after reading it.
+    pub fn config_path(&self) -> PathBuf {
+        self.dir.path().join(CONFIG)
+    }
+
+    /// Get the manifest of the bundle.
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+}
+
+/// Write a model bundle.
+///
+/// The bundle will contain the configuration and all the files that it
+/// refers to. The model parameters are read from the checkpoint with the
+/// given prefix. The paths in the configuration should be absolute or
+/// relative to the current working directory (see
+/// `Config::relativize_paths`).
+pub fn write_bundle<W>(config: &Config, parameters: &str, write: W) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut bundle_config = config.clone();
+    let mut files = Vec::new();
+
+    files.push((
+        PathBuf::from(&config.model.graph),
+        "graph".to_owned(),
+        FileKind::Graph,
+    ));
+    bundle_config.model.graph = "graph".to_owned();
+
+    for (path, suffix) in checkpoint_files(parameters)? {
+        files.push((path, format!("parameters{}", suffix), FileKind::Parameters));
+    }
+    bundle_config.model.parameters = "parameters".to_owned();
+
+    files.push((
+        PathBuf::from(&config.parser.inputs),
+        "inputs".to_owned(),
+        FileKind::Inputs,
+    ));
+    bundle_config.parser.inputs = "inputs".to_owned();
+
+    files.push((
+        PathBuf::from(&config.parser.transitions),
+        "transitions".to_owned(),
+        FileKind::Transitions,
+    ));
+    bundle_config.parser.transitions = "transitions".to_owned();
+
+    let lookups = &mut bundle_config.lookups;
+    bundle_lookup(&mut files, lookups.word.as_mut(), "word");
+    bundle_lookup(&mut files, lookups.tag.as_mut(), "tag");
+    bundle_lookup(&mut files, lookups.deprel.as_mut(), "deprel");
+    bundle_lookup(&mut files, lookups.feature.as_mut(), "feature");
+    bundle_lookup(&mut files, lookups.chars.as_mut(), "chars");
+    for (name, lookup) in &mut lookups.features {
+        bundle_lookup(
+            &mut files,
+            Some(lookup),
+            Lookups::feature_layer(name).name(),
+        );
+    }
+    for (name, lookup) in &mut lookups.inputs {
+        bundle_lookup(&mut files, Some(lookup), name);
+    }
+
+    let mut config_data = Vec::new();
+    bundle_config.to_toml_write(&mut config_data)?;
+
+    let mut manifest = Manifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        dpar_version: env!("CARGO_PKG_VERSION").to_owned(),
+        files: vec![manifest_entry(
+            CONFIG,
+            FileKind::Config,
+            config_data.as_slice(),
+        )?],
+    };
+
+    for (path, name, kind) in &files {
+        let f = File::open(path)
+            .map_err(|err| format_err!("Cannot open {}: {}", path.display(), err))?;
+        manifest
+            .files
+            .push(manifest_entry(name, *kind, BufReader::new(f))?);
+    }
+
+    let manifest_data = toml::to_string(&manifest)?;
+
+    let mut builder = Builder::new(BufWriter::new(write));
+    append_data(&mut builder, MANIFEST, manifest_data.as_bytes())?;
+    append_data(&mut builder, CONFIG, &config_data)?;
+    for (path, name, _) in &files {
+        builder.append_path_with_name(path, name)?;
+    }
+    builder.into_inner()?.flush()?;
+
+    Ok(())
+}
+
+fn append_data<W>(builder: &mut Builder<W>, path: &str, data: &[u8]) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut header = Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+/// Add the file of a lookup to the bundle files and rewrite its filename.
+fn bundle_lookup(
+    files: &mut Vec<(PathBuf, String, FileKind)>,
+    lookup: Option<&mut Lookup>,
+    name: &str,
+) {
+    let (filename, kind) = match lookup {
+        Some(Lookup::Embedding { filename, .. }) => (filename, FileKind::Embeddings),
+        Some(Lookup::Table { filename, .. }) => (filename, FileKind::LookupTable),
+        Some(Lookup::Hash { .. }) | None => return,
+    };
+
+    let bundle_filename = format!("lookups/{}", name);
+    files.push((PathBuf::from(&filename), bundle_filename.clone(), kind));
+    *filename = bundle_filename;
+}
+
+/// Find the files of a Tensorflow checkpoint.
+///
+/// Returns the paths of the files together with their suffixes, e.g.
+/// `.index` for `epoch-1.index`.
+fn checkpoint_files(prefix: &str) -> Result<Vec<(PathBuf, String)>, Error> {
+    let prefix = Path::new(prefix);
+    let dir = match prefix.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = prefix
+        .file_name()
+        .and_then(OsStr::to_str)
+        .ok_or_else(|| format_err!("Invalid parameter file prefix: {}", prefix.display()))?;
+    let name_dot = format!("{}.", name);
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let file_name = match file_name.to_str() {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+
+        if file_name.starts_with(&name_dot) {
+            files.push((entry.path(), file_name[name.len()..].to_owned()));
+        }
+    }
+
+    ensure!(
+        !files.is_empty(),
+        "No parameter files found for: {}",
+        prefix.display()
+    );
+
+    files.sort();
+
+    Ok(files)
+}
+
+fn manifest_entry<R>(path: &str, kind: FileKind, read: R) -> Result<ManifestEntry, Error>
+where
+    R: Read,
+{
+    Ok(ManifestEntry {
+        path: path.to_owned(),
+        kind,
+        format_version: kind.format_version(),
+        sha256: sha256(read)?,
+    })
+}
+
+fn sha256<R>(mut read: R) -> Result<String, Error>
+where
+    R: Read,
+{
+    let mut hasher = Sha256::new();
+    io::copy(&mut read, &mut hasher)?;
+    Ok(format!("{:x}", hasher.result()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    use dpar::pproj::Encoding;
+    use tempfile::TempDir;
+
+    use crate::{Config, Lookup, Lookups, Model, Parser, SwapOracle, TomlRead, Train};
+
+    use super::{write_bundle, Bundle};
+
+    fn write_file(dir: &Path, name: &str, data: &str) -> String {
+        let path = dir.join(name);
+        fs::write(&path, data).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    fn test_config(dir: &Path) -> Config {
+        Config {
+            parser: Parser {
+                pproj: Some(Encoding::Head),
+                system: String::from("stackproj"),
+                inputs: write_file(dir, "parser.inputs", "[STACK 0] TOKEN"),
+                transitions: write_file(dir, "parser.transitions", "transitions"),
+                train_batch_size: 8192,
+                parse_batch_size: 8192,
+                swap_oracle: SwapOracle::Eager,
+                default_relation: String::from("dep"),
+                single_root: false,
+            },
+            model: Model {
+                graph: write_file(dir, "parser.graph", "graph"),
+                parameters: String::from("params"),
+                intra_op_parallelism_threads: 2,
+                inter_op_parallelism_threads: 2,
+            },
+            train: Train {
+                initial_lr: 0.05.into(),
+                decay_rate: 0.95.into(),
+                decay_steps: 10,
+                staircase: true,
+                patience: 5,
+                word_dropout: 0.0.into(),
+                tag_dropout: 0.0.into(),
+            },
+            lookups: Lookups {
+                word: None,
+                tag: None,
+                deprel: Some(Lookup::Table {
+                    filename: write_file(dir, "deprels.lookup", "deprels"),
+                    op: String::from("model/deprels"),
+                    normalizers: Vec::new(),
+                    min_count: 1,
+                }),
+                feature: None,
+                chars: None,
+                features: BTreeMap::new(),
+                inputs: BTreeMap::new(),
+            },
+        }
+    }
+
+    fn bundle_roundtrip(dir: &TempDir) -> Bundle {
+        write_file(dir.path(), "epoch-3.index", "index");
+        write_file(dir.path(), "epoch-3.data-00000-of-00001", "data");
+        write_file(dir.path(), "epoch-30.index", "other index");
+
+        let config = test_config(dir.path());
+        let bundle_path = dir.path().join("model.bundle");
+        let parameters = dir.path().join("epoch-3");
+        write_bundle(
+            &config,
+            parameters.to_str().unwrap(),
+            File::create(&bundle_path).unwrap(),
+        )
+        .unwrap();
+
+        assert!(Bundle::is_bundle(&bundle_path).unwrap());
+        assert!(!Bundle::is_bundle(dir.path().join("parser.graph")).unwrap());
+
+        Bundle::open(&bundle_path).unwrap()
+    }
+
+    #[test]
+    fn bundle_roundtrip_test() {
+        let dir = TempDir::new().unwrap();
+        let bundle = bundle_roundtrip(&dir);
+
+        let config_path = bundle.config_path();
+        let mut config = Config::from_toml_read(File::open(&config_path).unwrap()).unwrap();
+        config.relativize_paths(&config_path).unwrap();
+
+        assert_eq!(fs::read_to_string(&config.model.graph).unwrap(), "graph");
+        assert_eq!(
+            fs::read_to_string(format!("{}.index", config.model.parameters)).unwrap(),
+            "index"
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{}.data-00000-of-00001", config.model.parameters)).unwrap(),
+            "data"
+        );
+        assert_eq!(
+            fs::read_to_string(&config.parser.inputs).unwrap(),
+            "[STACK 0] TOKEN"
+        );
+        match config.lookups.deprel {
+            Some(Lookup::Table { ref filename, .. }) => {
+                assert_eq!(fs::read_to_string(filename).unwrap(), "deprels")
+            }
+            _ => panic!("Incorrect deprel lookup"),
+        }
+
+        let files: Vec<_> = bundle
+            .manifest()
+            .files
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                "config.conf",
+                "graph",
+                "parameters.data-00000-of-00001",
+                "parameters.index",
+                "inputs",
+                "transitions",
+                "lookups/deprel",
+            ]
+        );
+    }
+
+    #[test]
+    fn bundle_checksum_test() {
+        let dir = TempDir::new().unwrap();
+        let bundle = bundle_roundtrip(&dir);
+
+        let graph_path = bundle.config_path().with_file_name("graph");
+        bundle.manifest().verify(bundle.dir.path()).unwrap();
+        fs::write(&graph_path, "tampered").unwrap();
+        assert!(bundle.manifest().verify(bundle.dir.path()).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::Path;
@@ -9,17 +10,27 @@ use rust2vec::{
     embeddings::Embeddings as R2VEmbeddings, io::ReadEmbeddings, storage::StorageWrap,
     vocab::VocabWrap,
 };
+use serde::{Deserialize, Deserializer};
 use serde_derive::{Deserialize, Serialize};
 use tf_proto::ConfigProto;
 
 use dpar::features;
-use dpar::features::{AddressedValues, Embeddings, Layer, LayerLookups};
+use dpar::features::{
+    AddressedValues, EmbeddingOptions, Embeddings, HashedLookup, Layer, LayerLookups,
+    Normalization, NormalizedLookup, Normalizer,
+};
 use dpar::models::lr::ExponentialDecay;
-use dpar::models::tensorflow::{LayerOp, LayerOps};
+use dpar::models::tensorflow::{IndexDropout, LayerOp, LayerOps};
+use dpar::pproj::Encoding;
 
 use crate::StoredLookupTable;
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+/// Parser configuration.
+///
+/// Unknown keys are rejected, so that misspelled keys in configuration
+/// files and overrides are not silently ignored.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub model: Model,
     pub parser: Parser,
@@ -39,23 +50,46 @@ impl Config {
         self.parser.inputs = relativize_path(config_path, &self.parser.inputs)?;
         self.parser.transitions = relativize_path(config_path, &self.parser.transitions)?;
 
-        relativize_embed_path(config_path, &mut self.lookups.word)?;
-        relativize_embed_path(config_path, &mut self.lookups.tag)?;
-        relativize_embed_path(config_path, &mut self.lookups.deprel)?;
-        relativize_embed_path(config_path, &mut self.lookups.feature)?;
+        for lookup in self.lookups.lookups_mut() {
+            relativize_embed_path(config_path, lookup)?;
+        }
 
         Ok(())
     }
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Parser {
-    pub pproj: bool,
+    /// The pseudo-projective encoding of non-projective trees. Trees are
+    /// not projectivized when no encoding is set.
+    ///
+    /// For compatibility with older configuration files, `true` is read
+    /// as the `head` encoding and `false` as no encoding.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_pproj",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub pproj: Option<Encoding>,
     pub system: String,
     pub inputs: String,
     pub transitions: String,
     pub train_batch_size: usize,
     pub parse_batch_size: usize,
+
+    /// The oracle that is used by the stack-swap transition system.
+    #[serde(default)]
+    pub swap_oracle: SwapOracle,
+
+    /// The relation that is used to attach tokens without a head when
+    /// completing parses to well-formed trees.
+    #[serde(default = "default_relation")]
+    pub default_relation: String,
+
+    /// Restrict parses to a single dependent of the root.
+    #[serde(default)]
+    pub single_root: bool,
 }
 
 impl Parser {
@@ -65,35 +99,146 @@ impl Parser {
     }
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+fn default_relation() -> String {
+    "dep".to_owned()
+}
+
+fn deserialize_pproj<'de, D>(deserializer: D) -> Result<Option<Encoding>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum PProj {
+        Enabled(bool),
+        Encoding(Encoding),
+    }
+
+    Ok(match PProj::deserialize(deserializer)? {
+        PProj::Enabled(true) => Some(Encoding::Head),
+        PProj::Enabled(false) => None,
+        PProj::Encoding(encoding) => Some(encoding),
+    })
+}
+
+/// Oracle for the stack-swap transition system.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwapOracle {
+    /// Swap as soon as tokens are not in projective order.
+    Eager,
+
+    /// Postpone swaps using maximal projective components.
+    Lazy,
+}
+
+impl Default for SwapOracle {
+    fn default() -> Self {
+        SwapOracle::Eager
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Lookups {
     pub word: Option<Lookup>,
     pub tag: Option<Lookup>,
     pub deprel: Option<Lookup>,
     pub feature: Option<Lookup>,
+    pub chars: Option<Lookup>,
+
+    /// Lookups of morphological attributes, keyed by the attribute name.
+    ///
+    /// The lookup with the key `*` is used for feature bundles
+    /// (`FEATURE *`). Features without an attribute lookup use the
+    /// `feature` lookup.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, Lookup>,
+
+    /// Lookups of named input layers, keyed by the layer name.
+    ///
+    /// Addressed values are assigned to a named input layer in the
+    /// feature specification, e.g. `[STACK 0] TAG AS coarse_tags`.
+    #[serde(flatten)]
+    pub inputs: BTreeMap<String, Lookup>,
 }
 
 impl Lookups {
+    /// Get the input layers and their lookups.
+    pub fn layer_lookups(&self) -> Vec<(Layer, &Lookup)> {
+        let mut lookups: Vec<_> = vec![
+            (Layer::TOKEN, &self.word),
+            (Layer::TAG, &self.tag),
+            (Layer::DEPREL, &self.deprel),
+            (Layer::FEATURE, &self.feature),
+            (Layer::CHAR, &self.chars),
+        ]
+        .into_iter()
+        .filter_map(|(layer, lookup)| lookup.as_ref().map(|lookup| (layer, lookup)))
+        .collect();
+
+        lookups.extend(
+            self.features
+                .iter()
+                .map(|(name, lookup)| (Self::feature_layer(name), lookup)),
+        );
+
+        lookups.extend(
+            self.inputs
+                .iter()
+                .map(|(name, lookup)| (Layer::new(name.as_str()), lookup)),
+        );
+
+        lookups
+    }
+
+    fn lookups_mut(&mut self) -> impl Iterator<Item = &mut Lookup> {
+        vec![
+            &mut self.word,
+            &mut self.tag,
+            &mut self.deprel,
+            &mut self.feature,
+            &mut self.chars,
+        ]
+        .into_iter()
+        .filter_map(Option::as_mut)
+        .chain(self.features.values_mut())
+        .chain(self.inputs.values_mut())
+    }
+
+    /// Get the input layer of a morphological attribute lookup.
+    pub fn feature_layer(name: &str) -> Layer {
+        match name {
+            "*" => Layer::FEATURE_BUNDLE,
+            name => Layer::feature(name),
+        }
+    }
+
     pub fn construct_lookups_with<F>(&self, load_fun: F) -> Result<LayerLookups, Error>
     where
         F: Fn(&Lookup) -> Result<Box<features::Lookup>, Error>,
     {
-        let mut lookups = LayerLookups::new();
-
-        if let Some(ref lookup) = self.word {
-            lookups.insert(Layer::Token, load_fun(lookup)?);
-        }
-
-        if let Some(ref lookup) = self.tag {
-            lookups.insert(Layer::Tag, load_fun(lookup)?);
-        }
+        let load_fun = |lookup: &Lookup| -> Result<Box<features::Lookup>, Error> {
+            let normalizer = lookup.normalizer();
+            if normalizer.is_empty() {
+                load_fun(lookup)
+            } else {
+                Ok(Box::new(NormalizedLookup::new(
+                    normalizer,
+                    load_fun(lookup)?,
+                )))
+            }
+        };
 
-        if let Some(ref lookup) = self.deprel {
-            lookups.insert(Layer::DepRel, load_fun(lookup)?);
+        let layer_lookups = self.layer_lookups();
+        for (idx, (layer, _)) in layer_lookups.iter().enumerate() {
+            if layer_lookups[..idx].iter().any(|(other, _)| other == layer) {
+                return Err(format_err!("Multiple lookups for the {} layer", layer));
+            }
         }
 
-        if let Some(ref lookup) = self.feature {
-            lookups.insert(Layer::Feature, load_fun(lookup)?);
+        let mut lookups = LayerLookups::new();
+        for (layer, lookup) in layer_lookups {
+            lookups.insert(layer, load_fun(lookup)?);
         }
 
         Ok(lookups)
@@ -105,12 +250,17 @@ impl Lookups {
 
     fn create_layer_tables(&self, lookup: &Lookup) -> Result<Box<features::Lookup>, Error> {
         match *lookup {
-            Lookup::Embedding { ref filename, .. } => {
-                Ok(Box::new(Self::load_embeddings(filename)?))
-            }
-            Lookup::Table { ref filename, .. } => {
-                Ok(Box::new(StoredLookupTable::create(filename)?))
-            }
+            Lookup::Embedding {
+                ref filename,
+                ref options,
+                ..
+            } => Ok(Box::new(Self::load_embeddings(filename, options)?)),
+            Lookup::Table {
+                ref filename,
+                min_count,
+                ..
+            } => Ok(Box::new(StoredLookupTable::create(filename, min_count)?)),
+            Lookup::Hash { buckets, seed, .. } => Ok(Box::new(HashedLookup::new(buckets, seed)?)),
         }
     }
 
@@ -121,71 +271,172 @@ impl Lookups {
     pub fn layer_ops(&self) -> LayerOps<String> {
         let mut names = LayerOps::new();
 
-        self.insert_layer_op(&mut names, Layer::Token, &self.word);
-        self.insert_layer_op(&mut names, Layer::Tag, &self.tag);
-        self.insert_layer_op(&mut names, Layer::DepRel, &self.deprel);
-        self.insert_layer_op(&mut names, Layer::Feature, &self.feature);
+        for (layer, lookup) in self.layer_lookups() {
+            self.insert_layer_op(&mut names, layer, lookup);
+        }
 
         names
     }
 
-    fn insert_layer_op(&self, names: &mut LayerOps<String>, layer: Layer, lookup: &Option<Lookup>) {
-        let lookup = match lookup {
-            Some(ref lookup) => lookup,
-            None => return,
-        };
+    fn insert_layer_op(&self, names: &mut LayerOps<String>, layer: Layer, lookup: &Lookup) {
+        match lookup {
+            Lookup::Embedding {
+                ref op,
+                ref embed_op,
+                ref options,
+                ..
+            } if options.fine_tune => {
+                names.insert(layer.clone(), LayerOp(op.clone()));
+                names.insert_embeds(layer, LayerOp(embed_op.clone()));
+            }
+            Lookup::Embedding { .. } => (),
+            Lookup::Table { ref op, .. } | Lookup::Hash { ref op, .. } => {
+                names.insert(layer, LayerOp(op.clone()))
+            }
+        }
+    }
+
+    /// Get the layers with fine-tuned embeddings and their lookups.
+    pub fn fine_tuned(&self) -> Vec<(Layer, &Lookup)> {
+        self.layer_lookups()
+            .into_iter()
+            .filter(|(_, lookup)| lookup.fine_tune())
+            .collect()
+    }
 
-        if let Lookup::Table { ref op, .. } = lookup {
-            names.insert(layer, LayerOp(op.clone()));
+    /// Load the embeddings of a lookup.
+    ///
+    /// Returns an error if the lookup is not an embedding lookup.
+    pub fn load_lookup_embeddings(lookup: &Lookup) -> Result<Embeddings, Error> {
+        match *lookup {
+            Lookup::Embedding {
+                ref filename,
+                ref options,
+                ..
+            } => Self::load_embeddings(filename, options),
+            Lookup::Table { .. } | Lookup::Hash { .. } => {
+                Err(format_err!("Lookup is not an embedding lookup"))
+            }
         }
     }
 
     fn load_layer_tables(&self, lookup: &Lookup) -> Result<Box<features::Lookup>, Error> {
         match *lookup {
-            Lookup::Embedding { ref filename, .. } => {
-                Ok(Box::new(Self::load_embeddings(filename)?))
-            }
+            Lookup::Embedding {
+                ref filename,
+                ref options,
+                ..
+            } => Ok(Box::new(Self::load_embeddings(filename, options)?)),
             Lookup::Table { ref filename, .. } => Ok(Box::new(StoredLookupTable::open(filename)?)),
+            Lookup::Hash { buckets, seed, .. } => Ok(Box::new(HashedLookup::new(buckets, seed)?)),
         }
     }
 
-    fn load_embeddings(filename: &str) -> Result<Embeddings, Error> {
+    fn load_embeddings(filename: &str, options: &EmbeddingOptions) -> Result<Embeddings, Error> {
         let f = File::open(filename)?;
         let embeds: R2VEmbeddings<VocabWrap, StorageWrap> =
             ReadEmbeddings::read_embeddings(&mut BufReader::new(f))?;
 
-        Ok(embeds.into())
+        Embeddings::with_options(embeds, options)
     }
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
-#[serde(untagged)]
+/// Lookup configuration.
+///
+/// The type of lookup is determined by its fields. Unknown fields are
+/// rejected, so that e.g. an entry with both a `filename` and `buckets`
+/// is not silently read as a lookup table.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields, untagged)]
 pub enum Lookup {
     Embedding {
         filename: String,
         op: String,
         embed_op: String,
+
+        /// Normalizations that are applied to values before lookup.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        normalizers: Vec<Normalization>,
+
+        /// Normalization of the embeddings and special vectors.
+        #[serde(flatten)]
+        options: EmbeddingOptions,
     },
     Table {
         filename: String,
         op: String,
+
+        /// Normalizations that are applied to values before lookup.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        normalizers: Vec<Normalization>,
+
+        /// Features that occur less than `min_count` times in the
+        /// training data are mapped to the unknown index.
+        #[serde(default = "default_min_count")]
+        min_count: usize,
+    },
+    Hash {
+        op: String,
+
+        /// Normalizations that are applied to values before lookup.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        normalizers: Vec<Normalization>,
+
+        /// The number of buckets that features are hashed into.
+        buckets: usize,
+
+        /// Seed of the hash function.
+        #[serde(default)]
+        seed: u64,
     },
 }
 
-fn relativize_embed_path(config_path: &Path, embed: &mut Option<Lookup>) -> Result<(), Error> {
-    if let Some(embed) = embed.as_mut() {
-        match *embed {
+impl Lookup {
+    /// Returns `true` if the lookup uses fine-tuned embeddings.
+    pub fn fine_tune(&self) -> bool {
+        match *self {
+            Lookup::Embedding { ref options, .. } => options.fine_tune,
+            Lookup::Table { .. } | Lookup::Hash { .. } => false,
+        }
+    }
+
+    /// Get the normalizer for values of the lookup.
+    ///
+    /// The normalizers are part of the configuration, which is stored
+    /// with the model. So, values are normalized in the same manner
+    /// when preparing the training data, training, and parsing.
+    pub fn normalizer(&self) -> Normalizer {
+        match *self {
             Lookup::Embedding {
-                ref mut filename, ..
-            } => {
-                *filename = relativize_path(config_path, &filename)?;
+                ref normalizers, ..
             }
-            Lookup::Table {
-                ref mut filename, ..
-            } => {
-                *filename = relativize_path(config_path, &filename)?;
+            | Lookup::Table {
+                ref normalizers, ..
             }
+            | Lookup::Hash {
+                ref normalizers, ..
+            } => Normalizer::new(normalizers.clone()),
+        }
+    }
+}
+
+fn default_min_count() -> usize {
+    1
+}
+
+fn relativize_embed_path(config_path: &Path, embed: &mut Lookup) -> Result<(), Error> {
+    match *embed {
+        Lookup::Embedding {
+            ref mut filename, ..
+        } => {
+            *filename = relativize_path(config_path, &filename)?;
+        }
+        Lookup::Table {
+            ref mut filename, ..
+        } => {
+            *filename = relativize_path(config_path, &filename)?;
         }
+        Lookup::Hash { .. } => (),
     }
 
     Ok(())
@@ -223,7 +474,8 @@ fn relativize_path(config_path: &Path, filename: &str) -> Result<String, Error>
         .to_owned())
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Model {
     /// The filename of the Tensorflow graph.
     pub graph: String,
@@ -260,13 +512,28 @@ impl Model {
     }
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Train {
     pub initial_lr: NotNan<f32>,
     pub decay_rate: NotNan<f32>,
     pub decay_steps: usize,
     pub staircase: bool,
     pub patience: usize,
+
+    /// Word dropout α. During training, the index of a token with
+    /// frequency *f* is replaced by the unknown index with probability
+    /// *α / (α + f)*. Word dropout is disabled when α is *0*.
+    #[serde(default = "default_dropout")]
+    pub word_dropout: NotNan<f32>,
+
+    /// Tag dropout α, see `word_dropout`.
+    #[serde(default = "default_dropout")]
+    pub tag_dropout: NotNan<f32>,
+}
+
+fn default_dropout() -> NotNan<f32> {
+    0.0.into()
 }
 
 impl Train {
@@ -278,4 +545,28 @@ impl Train {
             self.staircase,
         )
     }
+
+    /// Construct index dropout for the token and tag layers.
+    pub fn index_dropout(&self, lookups: &LayerLookups) -> Result<IndexDropout, Error> {
+        let mut dropout = IndexDropout::new();
+
+        for (layer, alpha) in [
+            (Layer::TOKEN, self.word_dropout),
+            (Layer::TAG, self.tag_dropout),
+        ]
+        .iter()
+        .cloned()
+        {
+            if alpha.into_inner() <= 0. {
+                continue;
+            }
+
+            let lookup = lookups
+                .layer_lookup(&layer)
+                .ok_or_else(|| format_err!("Dropout requires a lookup for the {} layer", layer))?;
+            dropout.add_layer(layer, lookup, alpha.into_inner())?;
+        }
+
+        Ok(dropout)
+    }
 }
@@ -1,18 +1,27 @@
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
 
+use dpar::features::{EmbeddingOptions, Layer, MutableLookupTable, Normalization, SpecialVector};
+use dpar::pproj::Encoding;
+use failure::Error;
 use lazy_static::*;
 
-use super::{Config, Lookup, Lookups, Model, Parser, TomlRead, Train};
+use super::{
+    Config, ConfigOverride, Lookup, Lookups, Model, Parser, SwapOracle, TomlRead, TomlWrite, Train,
+};
 
 lazy_static! {
     static ref BASIC_PARSER_CHECK: Config = Config {
         parser: Parser {
-            pproj: true,
+            pproj: Some(Encoding::Head),
             system: String::from("stackproj"),
             inputs: String::from("parser.inputs"),
             transitions: String::from("parser.transitions"),
             train_batch_size: 8192,
             parse_batch_size: 8192,
+            swap_oracle: SwapOracle::Eager,
+            default_relation: String::from("dep"),
+            single_root: false,
         },
         model: Model {
             graph: String::from("parser.graph"),
@@ -26,26 +35,54 @@ lazy_static! {
             decay_steps: 10,
             staircase: true,
             patience: 5,
+            word_dropout: 0.0.into(),
+            tag_dropout: 0.0.into(),
         },
         lookups: Lookups {
             word: Some(Lookup::Embedding {
                 filename: String::from("word-vectors.bin"),
                 op: String::from("model/tokens"),
                 embed_op: String::from("model/token_embeds"),
+                options: EmbeddingOptions {
+                    normalize: true,
+                    ..EmbeddingOptions::default()
+                },
+                normalizers: vec![Normalization::Url, Normalization::FoldDigits],
             }),
             tag: Some(Lookup::Embedding {
                 filename: String::from("tag-vectors.bin"),
                 op: String::from("model/tags"),
                 embed_op: String::from("model/tag_embeds"),
+                options: EmbeddingOptions {
+                    normalize: true,
+                    ..EmbeddingOptions::default()
+                },
+                normalizers: Vec::new(),
             }),
             deprel: Some(Lookup::Table {
                 filename: String::from("deprels.lookup"),
                 op: String::from("model/deprels"),
+                normalizers: Vec::new(),
+                min_count: 1,
             }),
             feature: Some(Lookup::Table {
                 filename: String::from("features.lookup"),
                 op: String::from("model/features"),
+                normalizers: Vec::new(),
+                min_count: 1,
             }),
+            chars: Some(Lookup::Embedding {
+                filename: String::from("char-vectors.bin"),
+                op: String::from("model/chars"),
+                embed_op: String::from("model/char_embeds"),
+                options: EmbeddingOptions {
+                    normalize: true,
+                    ..EmbeddingOptions::default()
+                },
+                normalizers: Vec::new(),
+            }),
+            features: BTreeMap::new(),
+            inputs: BTreeMap::new(),
         }
     };
 }
@@ -56,3 +93,439 @@ fn test_parse_config() {
     let config = Config::from_toml_read(f).unwrap();
     assert_eq!(*BASIC_PARSER_CHECK, config);
 }
+
+fn read_with_overrides(overrides: &[&str]) -> Result<Config, Error> {
+    let overrides = overrides
+        .iter()
+        .map(|o| o.parse())
+        .collect::<Result<Vec<ConfigOverride>, _>>()?;
+    let f = File::open("testdata/basic-parse.conf").unwrap();
+    Config::from_toml_read_with_overrides(f, &overrides)
+}
+
+#[test]
+fn test_override_config() {
+    let config = read_with_overrides(&[
+        "train.initial_lr=0.1",
+        "train.decay_rate=1",
+        "parser.parse_batch_size=512",
+        "parser.system=arceager",
+        "lookups.deprel.filename=other.lookup",
+        "lookups.feature.min_count=2",
+    ])
+    .unwrap();
+
+    assert_eq!(config.train.initial_lr, 0.1.into());
+    assert_eq!(config.train.decay_rate, 1.0.into());
+    assert_eq!(config.parser.parse_batch_size, 512);
+    assert_eq!(config.parser.system, "arceager");
+    assert_eq!(
+        config.lookups.deprel,
+        Some(Lookup::Table {
+            filename: String::from("other.lookup"),
+            op: String::from("model/deprels"),
+            normalizers: Vec::new(),
+            min_count: 1,
+        })
+    );
+
+    assert_eq!(
+        config.lookups.feature,
+        Some(Lookup::Table {
+            filename: String::from("features.lookup"),
+            op: String::from("model/features"),
+            normalizers: Vec::new(),
+            min_count: 2,
+        })
+    );
+
+    // Other values should be unchanged.
+    assert_eq!(config.model, BASIC_PARSER_CHECK.model);
+}
+
+#[test]
+fn test_normalizers_config() {
+    let config =
+        read_with_overrides(&["lookups.deprel.normalizers=[\"lowercase\", \"nfkc\"]"]).unwrap();
+    let deprel = config.lookups.deprel.unwrap();
+    assert_eq!(
+        deprel.normalizer().normalizations(),
+        &[Normalization::Lowercase, Normalization::Nfkc]
+    );
+    assert!(config.lookups.tag.unwrap().normalizer().is_empty());
+
+    assert!(read_with_overrides(&["lookups.deprel.normalizers=[\"uppercase\"]"]).is_err());
+}
+
+#[test]
+fn test_embedding_options_config() {
+    let data = fs::read_to_string("testdata/basic-parse.conf").unwrap();
+    let data = data.replacen(
+        "normalize = true",
+        "normalize = false\n  unknown = { word = \"<unk>\" }\n  null = \"average\"",
+        1,
+    );
+    let config = Config::from_toml_read(data.as_bytes()).unwrap();
+
+    match config.lookups.word {
+        Some(Lookup::Embedding { ref options, .. }) => assert_eq!(
+            options,
+            &EmbeddingOptions {
+                normalize: false,
+                unknown: SpecialVector::Word("<unk>".to_owned()),
+                null: SpecialVector::Average,
+                fine_tune: false,
+            }
+        ),
+        _ => panic!("Expected an embedding lookup"),
+    }
+
+    // Other lookups should be unchanged.
+    assert_eq!(config.lookups.tag, BASIC_PARSER_CHECK.lookups.tag);
+
+    let config = read_with_overrides(&["lookups.tag.unknown=zero"]).unwrap();
+    match config.lookups.tag {
+        Some(Lookup::Embedding { ref options, .. }) => {
+            assert_eq!(options.unknown, SpecialVector::Zero)
+        }
+        _ => panic!("Expected an embedding lookup"),
+    }
+
+    assert!(read_with_overrides(&["lookups.tag.unknown=random"]).is_err());
+}
+
+#[test]
+fn test_special_vector_roundtrip() {
+    let data = fs::read_to_string("testdata/basic-parse.conf").unwrap();
+    let data = data.replacen(
+        "normalize = true",
+        "normalize = true\n  unknown = { word = \"<unk>\" }\n  null = \"zero\"",
+        1,
+    );
+    let config = Config::from_toml_read(data.as_bytes()).unwrap();
+
+    let mut toml_data = Vec::new();
+    config.to_toml_write(&mut toml_data).unwrap();
+    assert_eq!(
+        Config::from_toml_read(toml_data.as_slice()).unwrap(),
+        config
+    );
+
+    // Overrides should work with configurations that use words.
+    let overrides = vec!["train.patience=3".parse::<ConfigOverride>().unwrap()];
+    let config = Config::from_toml_read_with_overrides(data.as_bytes(), &overrides).unwrap();
+    match config.lookups.word {
+        Some(Lookup::Embedding { ref options, .. }) => {
+            assert_eq!(options.unknown, SpecialVector::Word("<unk>".to_owned()))
+        }
+        _ => panic!("Expected an embedding lookup"),
+    }
+}
+
+#[test]
+fn test_hash_lookup_config() {
+    let data = fs::read_to_string("testdata/basic-parse.conf").unwrap();
+    let data = data.replacen(
+        "filename = \"features.lookup\"",
+        "buckets = 1024\n  seed = 7",
+        1,
+    );
+    let config = Config::from_toml_read(data.as_bytes()).unwrap();
+
+    assert_eq!(
+        config.lookups.feature,
+        Some(Lookup::Hash {
+            op: "model/features".to_owned(),
+            normalizers: Vec::new(),
+            buckets: 1024,
+            seed: 7,
+        })
+    );
+    assert_eq!(
+        config
+            .lookups
+            .layer_ops()
+            .layer_lookup(&Layer::FEATURE)
+            .map(|op| op.0.as_str()),
+        Some("model/features")
+    );
+
+    // Hashed lookups do not have files, so they should be constructed
+    // without any preparation.
+    let lookups = Lookups {
+        word: None,
+        tag: None,
+        deprel: None,
+        feature: config.lookups.feature.clone(),
+        chars: None,
+        features: BTreeMap::new(),
+        inputs: BTreeMap::new(),
+    }
+    .load_lookups()
+    .unwrap();
+    let feature = lookups.layer_lookup(&Layer::FEATURE).unwrap();
+    assert_eq!(feature.len(), 1026);
+    assert!(feature.lookup("case:nom").is_some());
+
+    // The seed is optional.
+    let data = data.replacen("\n  seed = 7", "", 1);
+    let config = Config::from_toml_read(data.as_bytes()).unwrap();
+    match config.lookups.feature {
+        Some(Lookup::Hash { seed, .. }) => assert_eq!(seed, 0),
+        _ => panic!("Expected a hashed lookup"),
+    }
+}
+
+#[test]
+fn test_ambiguous_lookup_config() {
+    // A lookup with both a file and buckets is rejected, rather than
+    // read as a table or embeddings.
+    let data = fs::read_to_string("testdata/basic-parse.conf").unwrap();
+    let ambiguous = data.replacen(
+        "filename = \"features.lookup\"",
+        "filename = \"features.lookup\"\n  buckets = 1024",
+        1,
+    );
+    assert!(Config::from_toml_read(ambiguous.as_bytes()).is_err());
+    let ambiguous = data.replacen(
+        "filename = \"word-vectors.bin\"",
+        "filename = \"word-vectors.bin\"\n  buckets = 1024",
+        1,
+    );
+    assert!(Config::from_toml_read(ambiguous.as_bytes()).is_err());
+}
+
+#[test]
+fn test_named_inputs_config() {
+    let data = fs::read_to_string("testdata/basic-parse.conf").unwrap();
+    let data = format!(
+        "{}\n  [lookups.coarse_tags]\n  filename = \"coarse-tags.lookup\"\n  op = \"model/coarse_tags\"\n",
+        data
+    );
+    let mut config = Config::from_toml_read(data.as_bytes()).unwrap();
+
+    assert_eq!(config.lookups.tag, BASIC_PARSER_CHECK.lookups.tag);
+    assert_eq!(
+        config.lookups.inputs.get("coarse_tags"),
+        Some(&Lookup::Table {
+            filename: "coarse-tags.lookup".to_owned(),
+            op: "model/coarse_tags".to_owned(),
+            normalizers: Vec::new(),
+            min_count: 1,
+        })
+    );
+
+    let layers: Vec<_> = config
+        .lookups
+        .layer_lookups()
+        .into_iter()
+        .map(|(layer, _)| layer)
+        .collect();
+    assert_eq!(
+        layers,
+        vec![
+            Layer::TOKEN,
+            Layer::TAG,
+            Layer::DEPREL,
+            Layer::FEATURE,
+            Layer::CHAR,
+            Layer::new("coarse_tags")
+        ]
+    );
+    assert_eq!(
+        config
+            .lookups
+            .layer_ops()
+            .layer_lookup(&Layer::new("coarse_tags"))
+            .map(|op| op.0.as_str()),
+        Some("model/coarse_tags")
+    );
+
+    // Named inputs can be overridden like other lookups.
+    let overrides = ["lookups.coarse_tags.min_count=2".parse().unwrap()];
+    let config_with_overrides =
+        Config::from_toml_read_with_overrides(data.as_bytes(), &overrides).unwrap();
+    match config_with_overrides.lookups.inputs.get("coarse_tags") {
+        Some(Lookup::Table { min_count, .. }) => assert_eq!(*min_count, 2),
+        _ => panic!("Expected a table lookup"),
+    }
+
+    // Paths of named inputs are relativized.
+    config
+        .relativize_paths("testdata/basic-parse.conf")
+        .unwrap();
+    match config.lookups.inputs.get("coarse_tags") {
+        Some(Lookup::Table { filename, .. }) => {
+            assert!(filename.ends_with("testdata/coarse-tags.lookup"))
+        }
+        _ => panic!("Expected a table lookup"),
+    }
+
+    // Named inputs should roundtrip.
+    let mut toml_data = Vec::new();
+    config.to_toml_write(&mut toml_data).unwrap();
+    assert_eq!(
+        Config::from_toml_read(toml_data.as_slice()).unwrap(),
+        config
+    );
+
+    // Every layer gets its own lookup.
+    let lookups = config
+        .lookups
+        .construct_lookups_with(|_| Ok(Box::new(MutableLookupTable::new())))
+        .unwrap();
+    assert_eq!(lookups.iter().count(), 6);
+
+    // A named input cannot redefine the lookup of a default layer.
+    let data = format!(
+        "{}\n  [lookups.tokens]\n  buckets = 10\n  op = \"model/tokens\"\n",
+        data
+    );
+    let config = Config::from_toml_read(data.as_bytes()).unwrap();
+    assert!(config
+        .lookups
+        .construct_lookups_with(|_| Ok(Box::new(MutableLookupTable::new())))
+        .is_err());
+}
+
+#[test]
+fn test_feature_lookups_config() {
+    let data = fs::read_to_string("testdata/basic-parse.conf").unwrap();
+    let data = format!(
+        "{}\n  [lookups.features.case]\n  filename = \"case.lookup\"\n  op = \"model/features.case\"\n\n  [lookups.features.\"*\"]\n  filename = \"feature-bundles.lookup\"\n  op = \"model/feature_bundle\"\n",
+        data
+    );
+    let mut config = Config::from_toml_read(data.as_bytes()).unwrap();
+
+    assert_eq!(config.lookups.feature, BASIC_PARSER_CHECK.lookups.feature);
+    assert!(config.lookups.inputs.is_empty());
+    assert_eq!(
+        config.lookups.features.get("case"),
+        Some(&Lookup::Table {
+            filename: "case.lookup".to_owned(),
+            op: "model/features.case".to_owned(),
+            normalizers: Vec::new(),
+            min_count: 1,
+        })
+    );
+
+    // Attribute lookups get their own input layers.
+    let layers: Vec<_> = config
+        .lookups
+        .layer_lookups()
+        .into_iter()
+        .map(|(layer, _)| layer)
+        .skip(5)
+        .collect();
+    assert_eq!(layers, vec![Layer::FEATURE_BUNDLE, Layer::feature("case")]);
+    let layer_ops = config.lookups.layer_ops();
+    assert_eq!(
+        layer_ops
+            .layer_lookup(&Layer::feature("case"))
+            .map(|op| op.0.as_str()),
+        Some("model/features.case")
+    );
+    assert_eq!(
+        layer_ops
+            .layer_lookup(&Layer::FEATURE_BUNDLE)
+            .map(|op| op.0.as_str()),
+        Some("model/feature_bundle")
+    );
+
+    // Paths of attribute lookups are relativized.
+    config
+        .relativize_paths("testdata/basic-parse.conf")
+        .unwrap();
+    match config.lookups.features.get("*") {
+        Some(Lookup::Table { filename, .. }) => {
+            assert!(filename.ends_with("testdata/feature-bundles.lookup"))
+        }
+        _ => panic!("Expected a table lookup"),
+    }
+
+    // Attribute lookups should roundtrip.
+    let mut toml_data = Vec::new();
+    config.to_toml_write(&mut toml_data).unwrap();
+    assert_eq!(
+        Config::from_toml_read(toml_data.as_slice()).unwrap(),
+        config
+    );
+}
+
+#[test]
+fn test_fine_tune_config() {
+    let config = read_with_overrides(&[]).unwrap();
+    assert!(config.lookups.fine_tuned().is_empty());
+
+    let config = read_with_overrides(&["lookups.word.fine_tune=true"]).unwrap();
+    let fine_tuned = config.lookups.fine_tuned();
+    assert_eq!(fine_tuned.len(), 1);
+    assert_eq!(fine_tuned[0].0, Layer::TOKEN);
+    assert!(fine_tuned[0].1.fine_tune());
+
+    let layer_ops = config.lookups.layer_ops();
+    assert_eq!(
+        layer_ops
+            .layer_embeds(&Layer::TOKEN)
+            .map(|op| op.0.as_str()),
+        Some("model/token_embeds")
+    );
+    assert!(layer_ops.layer_embeds(&Layer::TAG).is_none());
+}
+
+#[test]
+fn test_pproj_config() {
+    let config = read_with_overrides(&["parser.pproj=head+path"]).unwrap();
+    assert_eq!(config.parser.pproj, Some(Encoding::HeadPath));
+    assert!(read_with_overrides(&["parser.pproj=lifted"]).is_err());
+
+    // Overrides keep the type of the TOML value, even when the current
+    // value is a string.
+    let config = read_with_overrides(&["parser.pproj=false"]).unwrap();
+    assert_eq!(config.parser.pproj, None);
+    let config = read_with_overrides(&["parser.pproj=true"]).unwrap();
+    assert_eq!(config.parser.pproj, Some(Encoding::Head));
+
+    // Boolean values from older configuration files.
+    let data = fs::read_to_string("testdata/basic-parse.conf").unwrap();
+    let config = Config::from_toml_read(data.replace("\"head\"", "true").as_bytes()).unwrap();
+    assert_eq!(config.parser.pproj, Some(Encoding::Head));
+    let config = Config::from_toml_read(data.replace("\"head\"", "false").as_bytes()).unwrap();
+    assert_eq!(config.parser.pproj, None);
+}
+
+#[test]
+fn test_incorrect_overrides() {
+    // Malformed overrides.
+    assert!("train.initial_lr".parse::<ConfigOverride>().is_err());
+    assert!("initial_lr=0.1".parse::<ConfigOverride>().is_err());
+    assert!("train..initial_lr=0.1".parse::<ConfigOverride>().is_err());
+
+    // Type errors.
+    assert!(read_with_overrides(&["parser.parse_batch_size=many"]).is_err());
+    assert!(read_with_overrides(&["train.staircase=0.5"]).is_err());
+
+    // Keys that are not in the schema.
+    assert!(read_with_overrides(&["train.inital_lr=0.1"]).is_err());
+    assert!(read_with_overrides(&["parser.system.name=arceager"]).is_err());
+    assert!(read_with_overrides(&["model.graphs=other.graph"]).is_err());
+    assert!(read_with_overrides(&["lookups.deprel.min_cout=2"]).is_err());
+}
+
+#[test]
+fn test_override_skipped_defaults() {
+    // Values that are not serialized can be overridden.
+    let config = read_with_overrides(&["parser.pproj=false"]).unwrap();
+    assert_eq!(config.parser.pproj, None);
+
+    let config = read_with_overrides(&["lookups.word.normalizers=[]"]).unwrap();
+    assert!(config.lookups.word.unwrap().normalizer().is_empty());
+}
+
+#[test]
+fn test_config_roundtrip() {
+    let mut data = Vec::new();
+    BASIC_PARSER_CHECK.to_toml_write(&mut data).unwrap();
+    let config = Config::from_toml_read(data.as_slice()).unwrap();
+    assert_eq!(*BASIC_PARSER_CHECK, config);
+}
@@ -0,0 +1,236 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use dpar::features::addr;
+use dpar::features::{padded_chars, InputVectorizer, Layer, Lookup, SentenceOccurrences};
+use dpar::system::ParserState;
+
+/// Lower bounds of the buckets of frequency distributions.
+pub const FREQUENCY_BUCKETS: [usize; 5] = [1, 2, 5, 10, 100];
+
+/// Lookup coverage of an input layer.
+///
+/// The frequencies of values are the number of occurrences of values in
+/// the corpus. The other counts are numbers of lookups, a value is
+/// looked up once in every parser state in which it is used.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LayerCoverage {
+    /// Corpus frequencies of the (non-null) values of the layer.
+    pub frequencies: HashMap<String, usize>,
+
+    /// The number of lookups, including lookups of null values.
+    pub lookups: usize,
+
+    /// The number of lookups of null values.
+    pub null: usize,
+
+    /// The number of lookups of values that are not in the lookup. This
+    /// is `None` when unknown values are not counted for the layer.
+    pub unknown: Option<usize>,
+}
+
+impl LayerCoverage {
+    /// Get the frequency distribution of the values.
+    ///
+    /// Returns for each bucket in `FREQUENCY_BUCKETS` the number of
+    /// values with a corpus frequency in the bucket.
+    pub fn frequency_distribution(&self) -> Vec<usize> {
+        let mut distribution = vec![0; FREQUENCY_BUCKETS.len()];
+
+        for &freq in self.frequencies.values() {
+            if let Some(bucket) = FREQUENCY_BUCKETS.iter().rposition(|&lower| freq >= lower) {
+                distribution[bucket] += 1;
+            }
+        }
+
+        distribution
+    }
+
+    fn count_occurrence(&mut self, value: String) {
+        *self.frequencies.entry(value).or_insert(0) += 1;
+    }
+
+    fn count_lookup(&mut self, lookup: Option<&Lookup>, value: Option<&str>) {
+        self.lookups += 1;
+
+        let value = match value {
+            Some(value) => value,
+            None => {
+                self.null += 1;
+                return;
+            }
+        };
+
+        if let (Some(unknown), Some(lookup)) = (self.unknown.as_mut(), lookup) {
+            if lookup.lookup(value).is_none() {
+                *unknown += 1;
+            }
+        }
+    }
+}
+
+/// Lookup coverage statistics of input layers.
+///
+/// The statistics are gathered from the values that an `InputVectorizer`
+/// looks up in parser states. `finish_sentence` should be called after
+/// the parser states of a sentence were counted, so that values are
+/// counted once per occurrence in the corpus.
+#[derive(Default)]
+pub struct LookupCoverage {
+    layers: BTreeMap<Layer, LayerCoverage>,
+    occurrences: SentenceOccurrences,
+}
+
+impl LookupCoverage {
+    /// Construct lookup coverage statistics.
+    ///
+    /// Unknown values are only counted for `unknown_layers`. Lookups of
+    /// other layers are never used, since looking up a value in a
+    /// `MutableLookupTable` changes the table.
+    pub fn new<I>(unknown_layers: I) -> Self
+    where
+        I: IntoIterator<Item = Layer>,
+    {
+        let layers = unknown_layers
+            .into_iter()
+            .map(|layer| {
+                let coverage = LayerCoverage {
+                    unknown: Some(0),
+                    ..LayerCoverage::default()
+                };
+                (layer, coverage)
+            })
+            .collect();
+
+        LookupCoverage {
+            layers,
+            occurrences: SentenceOccurrences::new(),
+        }
+    }
+
+    /// Count the values of a parser state.
+    pub fn count(&mut self, vectorizer: &InputVectorizer, state: &ParserState) {
+        for (addr_value, input_layer) in vectorizer
+            .layer_addrs()
+            .0
+            .iter()
+            .zip(vectorizer.input_layers())
+        {
+            if !self.layers.contains_key(input_layer) {
+                self.layers
+                    .insert(input_layer.clone(), LayerCoverage::default());
+            }
+            let coverage = self.layers.get_mut(input_layer).unwrap();
+
+            let lookup = match coverage.unknown {
+                Some(_) => vectorizer.layer_lookups().layer_lookup(input_layer),
+                None => None,
+            };
+
+            let value = addr_value.get(state);
+            match addr_value.layer {
+                addr::Layer::Char(len) => {
+                    // Characters are counted as they are vectorized, including padding.
+                    let mut char_buf = [0; 4];
+                    for c in padded_chars(value.as_ref().map(Cow::as_ref), len) {
+                        coverage.count_lookup(lookup, c.map(|c| &*c.encode_utf8(&mut char_buf)));
+                    }
+                }
+                _ => coverage.count_lookup(lookup, value.as_ref().map(Cow::as_ref)),
+            }
+        }
+
+        for (input_layer, value) in vectorizer.occurrences(state, &mut self.occurrences) {
+            if let Some(coverage) = self.layers.get_mut(input_layer) {
+                coverage.count_occurrence(value);
+            }
+        }
+    }
+
+    /// Finish counting the parser states of a sentence.
+    pub fn finish_sentence(&mut self) {
+        self.occurrences.clear();
+    }
+
+    /// Get the coverage statistics of the input layers.
+    pub fn layers(&self) -> &BTreeMap<Layer, LayerCoverage> {
+        &self.layers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use conllx::Token;
+    use dpar::features::addr::{AddressedValue, Source};
+    use dpar::features::{
+        self, AddressedValues, InputVectorizer, LayerLookups, Lookup, LookupTable,
+        MutableLookupTable,
+    };
+    use dpar::system::{ParserState, Transition};
+    use dpar::systems::stack_projective::StackProjectiveTransition;
+
+    use super::LookupCoverage;
+
+    fn test_vectorizer(lookup: Box<Lookup>) -> InputVectorizer {
+        let addrs = (0..3)
+            .map(|idx| AddressedValue {
+                address: vec![Source::Buffer(idx)],
+                layer: features::addr::Layer::Token,
+                input: None,
+            })
+            .collect();
+
+        let mut lookups = LayerLookups::new();
+        lookups.insert(features::Layer::TOKEN, lookup);
+        InputVectorizer::new(lookups, AddressedValues(addrs)).unwrap()
+    }
+
+    #[test]
+    fn count_values() {
+        let sent = vec![Token::new("a"), Token::new("b"), Token::new("a")];
+        let mut state = ParserState::new(&sent);
+        let short_sent = vec![Token::new("b")];
+        let short_state = ParserState::new(&short_sent);
+
+        let vectorizer = test_vectorizer(Box::new(MutableLookupTable::new()));
+        let mut coverage = LookupCoverage::new(vec![]);
+        coverage.count(&vectorizer, &state);
+        StackProjectiveTransition::Shift.apply(&mut state);
+        coverage.count(&vectorizer, &state);
+        coverage.finish_sentence();
+        coverage.count(&vectorizer, &short_state);
+        coverage.finish_sentence();
+
+        // Values are counted once per occurrence, lookups are counted
+        // in every parser state.
+        let tokens = &coverage.layers()[&features::Layer::TOKEN];
+        assert_eq!(tokens.frequencies.len(), 2);
+        assert_eq!(tokens.frequencies["a"], 2);
+        assert_eq!(tokens.frequencies["b"], 2);
+        assert_eq!(tokens.lookups, 9);
+        assert_eq!(tokens.null, 3);
+        assert_eq!(tokens.unknown, None);
+        assert_eq!(tokens.frequency_distribution(), vec![0, 2, 0, 0, 0]);
+
+        // The table should not be modified by counting.
+        assert_eq!(vectorizer.layer_lookups().iter().next().unwrap().1.len(), 2);
+    }
+
+    #[test]
+    fn count_unknown() {
+        let table = MutableLookupTable::new();
+        table.add_occurrence("a");
+        let vectorizer = test_vectorizer(Box::new(LookupTable::from(table)));
+
+        let sent = vec![Token::new("a"), Token::new("b"), Token::new("c")];
+        let state = ParserState::new(&sent);
+
+        let mut coverage = LookupCoverage::new(vec![features::Layer::TOKEN]);
+        coverage.count(&vectorizer, &state);
+
+        let tokens = &coverage.layers()[&features::Layer::TOKEN];
+        assert_eq!(tokens.null, 0);
+        assert_eq!(tokens.unknown, Some(2));
+        assert_eq!(tokens.frequency_distribution(), vec![3, 0, 0, 0, 0]);
+    }
+}
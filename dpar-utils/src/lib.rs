@@ -1,11 +1,24 @@
+mod bundle;
+pub use crate::bundle::{
+    write_bundle, Bundle, FileKind, Manifest, ManifestEntry, BUNDLE_FORMAT_VERSION,
+};
+
 mod config;
-pub use crate::config::{Config, Lookup, Lookups, Model, Parser, Train};
+pub use crate::config::{Config, Lookup, Lookups, Model, Parser, SwapOracle, Train};
+
+mod coverage;
+pub use crate::coverage::{LayerCoverage, LookupCoverage, FREQUENCY_BUCKETS};
+
+mod overrides;
+pub use crate::overrides::ConfigOverride;
 
 mod progress;
 pub use crate::progress::FileProgress;
 
 mod serialization;
-pub use crate::serialization::{CborRead, CborWrite, SerializableTransitionSystem, TomlRead};
+pub use crate::serialization::{
+    CborRead, CborWrite, SerializableTransitionSystem, TomlRead, TomlWrite,
+};
 
 mod stored_table;
 pub use crate::stored_table::StoredLookupTable;
@@ -0,0 +1,106 @@
+use std::fmt;
+use std::str::FromStr;
+
+use failure::{format_err, Error};
+use toml::value::Table;
+use toml::Value;
+
+/// A command-line override of a configuration value.
+///
+/// An override has the form `section.key=value`, for example
+/// `train.initial_lr=0.05`. Nested tables can be addressed by adding
+/// more path components, such as `lookups.word.filename=words.bin`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigOverride {
+    path: Vec<String>,
+    value: String,
+}
+
+impl ConfigOverride {
+    /// Apply the override to a parsed configuration file.
+    ///
+    /// The override value is interpreted as a TOML value. If the value
+    /// cannot be parsed as such, the value is used as a string. An integer
+    /// value that replaces a float is converted into a float. All other
+    /// type checking happens when the configuration is deserialized.
+    pub fn apply(&self, config: &mut Value) -> Result<(), Error> {
+        let (key, tables) = self
+            .path
+            .split_last()
+            .expect("Override without a configuration key");
+
+        let mut table = config
+            .as_table_mut()
+            .ok_or_else(|| format_err!("Configuration is not a table"))?;
+        for component in tables {
+            table = table
+                .entry(component.clone())
+                .or_insert_with(|| Value::Table(Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| {
+                    format_err!("Cannot override {}: {} is not a table", self, component)
+                })?;
+        }
+
+        let value = self.typed_value(table.get(key));
+        table.insert(key.clone(), value);
+
+        Ok(())
+    }
+
+    /// The configuration key, e.g. `train.initial_lr`.
+    pub fn key(&self) -> String {
+        self.path.join(".")
+    }
+
+    fn typed_value(&self, old_value: Option<&Value>) -> Value {
+        let value = match toml::from_str::<Table>(&format!("value = {}", self.value))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+        {
+            Some(value) => value,
+            None => return Value::String(self.value.clone()),
+        };
+
+        match (old_value, value) {
+            (Some(Value::Float(_)), Value::Integer(i)) => Value::Float(i as f64),
+            (_, value) => value,
+        }
+    }
+}
+
+impl FromStr for ConfigOverride {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        let key = parts.next().unwrap_or("").trim();
+        let value = parts
+            .next()
+            .ok_or_else(|| format_err!("Override is not of the form section.key=value: {}", s))?
+            .trim();
+
+        let path: Vec<_> = key
+            .split('.')
+            .map(str::trim)
+            .map(ToOwned::to_owned)
+            .collect();
+        if path.len() < 2 || path.iter().any(String::is_empty) {
+            return Err(format_err!(
+                "Override key is not of the form section.key: {}",
+                key
+            ));
+        }
+
+        Ok(ConfigOverride {
+            path,
+            value: value.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for ConfigOverride {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.key(), self.value)
+    }
+}
@@ -4,7 +4,8 @@ use std::io::{Read, Write};
 use dpar::features::LookupTable;
 use dpar::system::TransitionSystem;
 use dpar::systems::{
-    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, StackProjectiveSystem, StackSwapSystem,
+    ArcEagerSystem, ArcHybridSystem, ArcStandardSystem, ArcSwiftSystem, CovingtonSystem,
+    StackProjectiveSystem, StackSwapSystem,
 };
 use dpar::Numberer;
 use failure::Error;
@@ -13,23 +14,68 @@ use serde::Serialize;
 use serde_cbor;
 use toml;
 
-use super::Config;
+use super::{Config, ConfigOverride};
 
 pub trait TomlRead {
     fn from_toml_read<R>(read: R) -> Result<Config, Error>
     where
         R: Read;
+
+    /// Read a configuration, applying the overrides before deserialization.
+    fn from_toml_read_with_overrides<R>(
+        read: R,
+        overrides: &[ConfigOverride],
+    ) -> Result<Config, Error>
+    where
+        R: Read;
 }
 
 impl TomlRead for Config {
-    fn from_toml_read<R>(mut read: R) -> Result<Self, Error>
+    fn from_toml_read<R>(read: R) -> Result<Self, Error>
+    where
+        R: Read,
+    {
+        Self::from_toml_read_with_overrides(read, &[])
+    }
+
+    fn from_toml_read_with_overrides<R>(
+        mut read: R,
+        overrides: &[ConfigOverride],
+    ) -> Result<Self, Error>
     where
         R: Read,
     {
         let mut data = String::new();
         read.read_to_string(&mut data)?;
-        let config: Config = toml::from_str(&data)?;
-        Ok(config)
+        let mut value: toml::Value = toml::from_str(&data)?;
+
+        for config_override in overrides {
+            config_override.apply(&mut value)?;
+        }
+
+        // Overrides of keys that are not in the schema are rejected
+        // during deserialization.
+        Ok(value.try_into()?)
+    }
+}
+
+pub trait TomlWrite {
+    fn to_toml_write<W>(&self, write: &mut W) -> Result<(), Error>
+    where
+        W: Write;
+}
+
+impl TomlWrite for Config {
+    fn to_toml_write<W>(&self, write: &mut W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        // Serialize through a TOML value, which emits plain values before
+        // tables. Serializing the configuration directly fails when a
+        // table-valued field precedes plain values.
+        let data = toml::to_string(&toml::Value::try_from(self)?)?;
+        write.write_all(data.as_bytes())?;
+        Ok(())
     }
 }
 
@@ -60,8 +106,10 @@ macro_rules! cbor_read {
 cbor_read!(ArcEagerSystem);
 cbor_read!(ArcHybridSystem);
 cbor_read!(ArcStandardSystem);
+cbor_read!(ArcSwiftSystem);
 cbor_read!(StackProjectiveSystem);
 cbor_read!(StackSwapSystem);
+cbor_read!(CovingtonSystem);
 cbor_read!(LookupTable);
 
 pub trait CborWrite {
@@ -102,8 +150,10 @@ macro_rules! cbor_write {
 cbor_write!(ArcEagerSystem);
 cbor_write!(ArcHybridSystem);
 cbor_write!(ArcStandardSystem);
+cbor_write!(ArcSwiftSystem);
 cbor_write!(StackProjectiveSystem);
 cbor_write!(StackSwapSystem);
+cbor_write!(CovingtonSystem);
 cbor_write!(LookupTable);
 
 pub trait SerializableTransitionSystem:
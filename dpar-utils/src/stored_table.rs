@@ -25,7 +25,10 @@ impl StoredLookupTable {
         Ok(StoredLookupTable::Table(LookupTable::from_cbor_read(f)?))
     }
 
-    pub fn create<P>(path: P) -> Result<Self, Error>
+    /// Create a fresh table, which is written to the given path when the
+    /// table is dropped. Features that occur less than `min_count` times
+    /// are pruned from the written table.
+    pub fn create<P>(path: P, min_count: usize) -> Result<Self, Error>
     where
         P: AsRef<Path>,
     {
@@ -33,7 +36,7 @@ impl StoredLookupTable {
         let write = BufWriter::new(f);
         Ok(StoredLookupTable::FreshTable {
             write: Box::new(write),
-            table: MutableLookupTable::new(),
+            table: MutableLookupTable::with_min_count(min_count),
         })
     }
 }
@@ -46,7 +49,7 @@ impl Drop for StoredLookupTable {
         } = self
         {
             // We want to serialize an immutable table. So, get the inner MutableLookupTable,
-            // convert it into a LookupTable.
+            // convert it into a LookupTable. This prunes infrequent features.
             let table: LookupTable = mem::replace(table, MutableLookupTable::new()).into();
 
             if let Err(err) = table.to_cbor_write(write) {
@@ -91,4 +94,18 @@ impl Lookup for StoredLookupTable {
             StoredLookupTable::FreshTable { ref table, .. } => table.unknown(),
         }
     }
+
+    fn frequency(&self, index: usize) -> Option<usize> {
+        match self {
+            StoredLookupTable::Table(ref table) => table.frequency(index),
+            StoredLookupTable::FreshTable { ref table, .. } => table.frequency(index),
+        }
+    }
+
+    fn add_occurrence(&self, feature: &str) {
+        match self {
+            StoredLookupTable::Table(ref table) => table.add_occurrence(feature),
+            StoredLookupTable::FreshTable { ref table, .. } => table.add_occurrence(feature),
+        }
+    }
 }
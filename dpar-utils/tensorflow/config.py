@@ -3,5 +3,7 @@ class DefaultConfig:
     hidden_size = 200
     morph_hidden_size = 50
     deprel_embed_size = 50
+    char_embed_size = 25
+    input_embed_size = 50
     keep_prob = 0.95
     keep_prob_input = 0.90
@@ -16,6 +16,7 @@ class Layer(Enum):
     tag = 2
     deprel = 3
     feature = 4
+    char = 5
 
 class ParseModel:
     def __init__(
@@ -47,6 +48,10 @@ class ParseModel:
         self._features = tf.placeholder(
             tf.int32, [batch_size, n_features], "features")
 
+        n_chars = int(shapes.get('chars', 0))
+        self._chars = tf.placeholder(
+            tf.int32, [batch_size, n_chars], "chars")
+
         # For dependency relations, we train a separate layer, which could be seen as an
         # embeddings layer.
         n_deprel_embeds = int(shapes["deprel_embeds"])
@@ -64,9 +69,76 @@ class ParseModel:
         features = tf.one_hot(self._features, n_features, axis=-1)
         features = tf.contrib.layers.flatten(features)
 
-        inputs = tf.concat([self.embeds,
-                            deprel_input,
-                            features],
+        input_layers = [self.embeds, deprel_input, features]
+
+        # Named input layers each have a placeholder with the name of
+        # the layer. This includes the layers of morphological attributes
+        # (e.g. features.case) and feature bundles (feature_bundle).
+        named_inputs = shapes.get("inputs", {})
+        self._inputs = {}
+        for layer, layer_shape in sorted(named_inputs.items()):
+            self._inputs[layer] = tf.placeholder(
+                tf.int32, [batch_size, int(layer_shape["size"])], layer)
+
+        # Pretrained embeddings that are fine-tuned are stored in a
+        # variable, which the parser initializes from the embeddings
+        # through the <name>_init placeholder and <name>_assign op.
+        fine_tuned = shapes.get("fine_tuned", {})
+        layer_inputs = {
+            "tokens": (self._tokens, "token_embeds"),
+            "tags": (self._tags, "tag_embeds"),
+            "deprels": (self._deprels, "deprel_embeds"),
+            "features": (self._features, "feature_embeds"),
+            "chars": (self._chars, "char_embeds"),
+        }
+        for layer, layer_input in self._inputs.items():
+            layer_inputs[layer] = (layer_input, "%s_embeds" % layer)
+        for layer, layer_shape in sorted(fine_tuned.items()):
+            layer_input, name = layer_inputs[layer]
+            embeds_shape = [int(layer_shape["embeds"]), int(layer_shape["dims"])]
+            with tf.device("/cpu:0"):
+                embeds = tf.get_variable(name, embeds_shape)
+                embeds_init = tf.placeholder(
+                    tf.float32, embeds_shape, "%s_init" % name)
+                tf.assign(embeds, embeds_init, name="%s_assign" % name)
+
+            fine_tuned_input = tf.nn.embedding_lookup(embeds, layer_input)
+            fine_tuned_input = tf.reshape(fine_tuned_input, [tf.shape(layer_input)[
+                0], layer_input.shape[1] * embeds.shape[1]])
+            input_layers.append(fine_tuned_input)
+
+        # Other named input layers are embedded using a layer that is
+        # trained through backprop.
+        for layer, layer_shape in sorted(named_inputs.items()):
+            if layer in fine_tuned:
+                continue
+
+            layer_input = self._inputs[layer]
+            with tf.device("/cpu:0"):
+                layer_embeds = tf.get_variable(
+                    "%s_embed" % layer, [
+                        int(layer_shape["embeds"]), config.input_embed_size])
+
+            named_input = tf.nn.embedding_lookup(layer_embeds, layer_input)
+            named_input = tf.reshape(named_input, [tf.shape(layer_input)[
+                0], layer_input.shape[1] * layer_embeds.shape[1]])
+            input_layers.append(named_input)
+
+        # Character sequences of tokens are embedded using a layer that
+        # is trained through backprop.
+        n_char_embeds = int(shapes.get("char_embeds", 0))
+        if n_chars != 0 and n_char_embeds != 0 and "chars" not in fine_tuned:
+            with tf.device("/cpu:0"):
+                char_embeds = tf.get_variable(
+                    "char_embed", [
+                        n_char_embeds, config.char_embed_size])
+
+            char_input = tf.nn.embedding_lookup(char_embeds, self._chars)
+            char_input = tf.reshape(char_input, [tf.shape(self._chars)[
+                0], self._chars.shape[1] * char_embeds.shape[1]])
+            input_layers.append(char_input)
+
+        inputs = tf.concat(input_layers,
                            1,
                            name="concat_inputs")
         with tf.variable_scope("input_norm"):
@@ -123,6 +195,10 @@ class ParseModel:
     def accuracy(self):
         return self._accuracy
 
+    @property
+    def chars(self):
+        return self._chars
+
     @property
     def correct(self):
         return self._correct
@@ -135,6 +211,10 @@ class ParseModel:
     def embeds(self):
         return self._embeds
 
+    @property
+    def inputs(self):
+        return self._inputs
+
     @property
     def is_training(self):
         return self._is_training
@@ -0,0 +1,54 @@
+use conllx::{Token, TokenBuilder};
+use criterion::{criterion_group, criterion_main, Criterion};
+use dpar::system::{ParserState, Transition};
+use dpar::systems::stack_projective::StackProjectiveTransition;
+
+static LENGTHS: [usize; 4] = [10, 100, 1000, 10000];
+
+fn sentence(len: usize) -> Vec<Token> {
+    (0..len)
+        .map(|idx| {
+            TokenBuilder::new(format!("token{}", idx))
+                .pos("TAG")
+                .token()
+        })
+        .collect()
+}
+
+/// Shift all tokens from the buffer to the stack.
+fn shift(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "shift",
+        |b, &&len| {
+            let sentence = sentence(len);
+            b.iter(|| {
+                let mut state = ParserState::new(&sentence);
+                while !state.buffer().is_empty() {
+                    StackProjectiveTransition::Shift.apply(&mut state);
+                }
+                state
+            })
+        },
+        LENGTHS.iter(),
+    );
+}
+
+/// Clone a parser state halfway through the sentence.
+fn clone(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "clone",
+        |b, &&len| {
+            let sentence = sentence(len);
+            let mut state = ParserState::new(&sentence);
+            for _ in 0..len / 2 {
+                StackProjectiveTransition::Shift.apply(&mut state);
+            }
+
+            b.iter(|| state.clone())
+        },
+        LENGTHS.iter(),
+    );
+}
+
+criterion_group!(benches, shift, clone);
+criterion_main!(benches);
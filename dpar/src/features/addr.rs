@@ -1,9 +1,16 @@
 use std::borrow::Cow;
+use std::fmt;
 
 use conllx::Features;
 
 use crate::system::ParserState;
 
+/// Separator of the values in a conjunction.
+///
+/// Tabs cannot occur in CoNLL-X fields, so the joined value is
+/// unambiguous.
+pub const CONJUNCTION_SEPARATOR: &str = "\t";
+
 /// Source of tokens in the parser state.
 ///
 /// For example, `Stack(1)` refers to the second token on the stack.
@@ -26,6 +33,17 @@ pub enum Source {
     RDep(usize),
 }
 
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::Stack(idx) => write!(f, "STACK {}", idx),
+            Source::Buffer(idx) => write!(f, "BUFFER {}", idx),
+            Source::LDep(idx) => write!(f, "LDEP {}", idx),
+            Source::RDep(idx) => write!(f, "RDEP {}", idx),
+        }
+    }
+}
+
 /// Layer in the parser state.
 ///
 /// In addressing the parser state, the layer is used to request
@@ -44,6 +62,59 @@ pub enum Layer {
 
     /// Feature, the string argument should specify the feature name.
     Feature(String),
+
+    /// The full feature bundle of the token as a single value.
+    FeatureBundle,
+
+    /// Conjunction of addressed values. The value of a conjunction is
+    /// the concatenation of the values of its conjuncts, separated by
+    /// `CONJUNCTION_SEPARATOR`. Absent values of conjuncts are empty,
+    /// the conjunction is absent when all of its conjuncts are absent.
+    ///
+    /// Since every conjunct has its own address, the address of an
+    /// addressed value with this layer is empty.
+    Conjunction(Vec<AddressedValue>),
+
+    /// Characters of the token. The argument specifies the length of
+    /// the character sequence, longer tokens are truncated and shorter
+    /// tokens are padded.
+    Char(usize),
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Layer::Token => write!(f, "TOKEN"),
+            Layer::Tag => write!(f, "TAG"),
+            Layer::DepRel => write!(f, "DEPREL"),
+            Layer::Feature(ref name) => write!(f, "FEATURE {}", name),
+            Layer::FeatureBundle => write!(f, "FEATURE *"),
+            Layer::Char(len) => write!(f, "CHARS {}", len),
+            Layer::Conjunction(ref conjuncts) => {
+                for (idx, conjunct) in conjuncts.iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, " + ")?;
+                    }
+                    write!(f, "{}", conjunct)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Layer {
+    /// The number of values that the layer provides for a token.
+    ///
+    /// This is the sequence length for the character layer and *1*
+    /// for all other layers.
+    pub fn seq_len(&self) -> usize {
+        match *self {
+            Layer::Char(len) => len,
+            _ => 1,
+        }
+    }
 }
 
 /// An `AddressedValue` represents a value in the parser state.
@@ -64,27 +135,65 @@ pub enum Layer {
 /// let addr = AddressedValue {
 ///   address: vec![Source::Stack(0), Source::LDep(0)],
 ///   layer: Layer::Tag,
+///   input: None,
 /// };
 /// ```
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct AddressedValue {
     pub address: Vec<Source>,
     pub layer: Layer,
+
+    /// Name of the input layer that the value is part of. If absent,
+    /// the value is part of the default input layer of `layer`.
+    pub input: Option<String>,
+}
+
+impl fmt::Display for AddressedValue {
+    /// Format the addressed value in the feature specification syntax.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.address.is_empty() {
+            let address: Vec<_> = self.address.iter().map(ToString::to_string).collect();
+            write!(f, "[{}] ", address.join(", "))?;
+        }
+
+        write!(f, "{}", self.layer)?;
+
+        if let Some(ref input) = self.input {
+            write!(f, " AS {}", input)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl AddressedValue {
     /// Get the value of the address in the given parser state.
     pub fn get<'a>(&self, state: &'a ParserState) -> Option<Cow<'a, str>> {
+        let token = self.token(state)?;
+        self.resolve_value(state, token)
+    }
+
+    /// Get the token that the address refers to in the given parser state.
+    ///
+    /// Returns `None` if the address does not refer to a token.
+    pub fn token(&self, state: &ParserState) -> Option<usize> {
         let mut token = 0;
         for (idx, source) in self.address.iter().enumerate() {
-            if let Some(next_token) = self.resolve_address(state, *source, idx, token) {
-                token = next_token;
-            } else {
-                return None;
-            }
+            token = self.resolve_address(state, *source, idx, token)?;
         }
 
-        self.resolve_value(state, token)
+        Some(token)
+    }
+
+    /// Get the tokens that the value is a value of.
+    ///
+    /// This is the addressed token for most layers and the addressed
+    /// token of every conjunct for conjunctions.
+    pub fn tokens(&self, state: &ParserState) -> Vec<Option<usize>> {
+        match self.layer {
+            Layer::Conjunction(ref conjuncts) => conjuncts.iter().map(|c| c.token(state)).collect(),
+            _ => vec![self.token(state)],
+        }
     }
 
     fn resolve_address(
@@ -116,7 +225,7 @@ impl AddressedValue {
         match self.layer {
             // Note: indexing is used here rather than get(), accessing a non-existing
             // token is a bug. So we want things to burst in flames.
-            Layer::Token => Some(Cow::Borrowed(state.tokens()[token])),
+            Layer::Token | Layer::Char(_) => Some(Cow::Borrowed(state.tokens()[token])),
             Layer::Tag => Some(Cow::Borrowed(state.tags()[token])),
             Layer::DepRel => state
                 .head(token)
@@ -132,6 +241,21 @@ impl AddressedValue {
                     .map(String::as_str)
                     .map(Cow::Borrowed)
             }
+            Layer::FeatureBundle => state.features()[token]
+                .map(Features::as_str)
+                .map(Cow::Borrowed),
+            Layer::Conjunction(ref conjuncts) => {
+                let values: Vec<_> = conjuncts.iter().map(|c| c.get(state)).collect();
+                if values.iter().all(Option::is_none) {
+                    return None;
+                }
+
+                let values: Vec<_> = values
+                    .iter()
+                    .map(|v| v.as_ref().map(AsRef::as_ref).unwrap_or(""))
+                    .collect();
+                Some(Cow::Owned(values.join(CONJUNCTION_SEPARATOR)))
+            }
         }
     }
 }
@@ -23,6 +23,7 @@ fn buffer(idx: usize, layer: Layer) -> AddressedValue {
     AddressedValue {
         address: vec![Source::Buffer(idx)],
         layer,
+        input: None,
     }
 }
 
@@ -30,6 +31,7 @@ fn stack(idx: usize, layer: Layer) -> AddressedValue {
     AddressedValue {
         address: vec![Source::Stack(idx)],
         layer,
+        input: None,
     }
 }
 
@@ -66,31 +68,66 @@ fn test_features() {
     assert_eq!(None, buffer2e.get(&state));
 }
 
+#[test]
+fn test_feature_bundle() {
+    let buffer0 = buffer(0, Layer::FeatureBundle);
+    let buffer2 = buffer(2, Layer::FeatureBundle);
+
+    let state = ParserState::new(&THREE_TOKEN_SENTENCE);
+
+    assert_eq!(Some(Cow::Borrowed("a:x|b")), buffer0.get(&state));
+    assert_eq!(None, buffer2.get(&state));
+}
+
+#[test]
+fn test_conjunction() {
+    let conjunction = |conjuncts| AddressedValue {
+        address: vec![],
+        layer: Layer::Conjunction(conjuncts),
+        input: None,
+    };
+
+    let stack0_buffer0 = conjunction(vec![stack(0, Layer::Token), buffer(0, Layer::Token)]);
+    let buffer0_buffer3 = conjunction(vec![buffer(0, Layer::Token), buffer(3, Layer::Token)]);
+    let buffer3_stack1 = conjunction(vec![buffer(3, Layer::Token), stack(1, Layer::Token)]);
+
+    let state = ParserState::new(&THREE_TOKEN_SENTENCE);
+
+    assert_eq!(Some(Cow::Borrowed("ROOT\ta")), stack0_buffer0.get(&state));
+    assert_eq!(Some(Cow::Borrowed("a\t")), buffer0_buffer3.get(&state));
+    assert_eq!(None, buffer3_stack1.get(&state));
+}
+
 #[test]
 fn test_ldep_rdep() {
     let ldep0 = AddressedValue {
         address: vec![Source::Stack(0), Source::LDep(0)],
         layer: Layer::DepRel,
+        input: None,
     };
 
     let ldep1 = AddressedValue {
         address: vec![Source::Stack(0), Source::LDep(1)],
         layer: Layer::DepRel,
+        input: None,
     };
 
     let rdep0 = AddressedValue {
         address: vec![Source::Stack(0), Source::RDep(0)],
         layer: Layer::DepRel,
+        input: None,
     };
 
     let rdep1 = AddressedValue {
         address: vec![Source::Stack(0), Source::RDep(1)],
         layer: Layer::DepRel,
+        input: None,
     };
 
     let rdep2 = AddressedValue {
         address: vec![Source::Stack(0), Source::RDep(2)],
         layer: Layer::DepRel,
+        input: None,
     };
 
     let mut state = ParserState::new(&THREE_TOKEN_SENTENCE);
@@ -106,3 +143,31 @@ fn test_ldep_rdep() {
     assert_eq!(Some(Cow::Borrowed("Foo")), rdep1.get(&state));
     assert_eq!(None, rdep2.get(&state));
 }
+
+#[test]
+fn test_cloned_state() {
+    let buffer0 = buffer(0, Layer::Token);
+    let stack0 = stack(0, Layer::Token);
+    let stack0_rdep0 = AddressedValue {
+        address: vec![Source::Stack(0), Source::RDep(0)],
+        layer: Layer::DepRel,
+        input: None,
+    };
+
+    let mut state = ParserState::new(&THREE_TOKEN_SENTENCE);
+    StackProjectiveTransition::Shift.apply(&mut state);
+    StackProjectiveTransition::Shift.apply(&mut state);
+
+    let mut clone = state.clone();
+    StackProjectiveTransition::RightArc("Foo".to_owned()).apply(&mut clone);
+    StackProjectiveTransition::Shift.apply(&mut clone);
+
+    // Transitions on the clone do not affect the original state.
+    assert_eq!(Some(Cow::Borrowed("b")), stack0.get(&state));
+    assert_eq!(Some(Cow::Borrowed("c")), buffer0.get(&state));
+    assert_eq!(None, stack0_rdep0.get(&state));
+
+    assert_eq!(Some(Cow::Borrowed("c")), stack0.get(&clone));
+    assert_eq!(None, buffer0.get(&clone));
+    assert!(clone.head(2).is_some());
+}
@@ -1,16 +1,16 @@
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::io::BufRead;
 use std::result;
 
-use enum_map::{Enum, EnumMap};
-use failure::Error;
+use failure::{format_err, Error};
 use tensorflow::Tensor;
 
 use crate::features::addr;
 use crate::features::lookup::LookupResult;
 use crate::features::parse_addr::parse_addressed_values;
-use crate::features::{BoxedLookup, Lookup, LookupType};
+use crate::features::{Lookup, LookupType};
 use crate::system::ParserState;
 
 /// Multiple addressable parts of the parser state.
@@ -25,11 +25,24 @@ impl AddressedValues {
     /// Such a text file consists of lines with the format
     ///
     /// ~~~text,no_run
-    /// [address+] layer
+    /// [address+] layer (AS input)?
     /// ~~~
     ///
     /// Multiple addresses are used to e.g. address the left/rightmost
-    /// dependency of a token on the stack or buffer.
+    /// dependency of a token on the stack or buffer. The optional input
+    /// name assigns the value to a named input layer, rather than the
+    /// default input layer of the layer.
+    ///
+    /// The format has the following extensions:
+    ///
+    /// * Comments start with `#` and run until the end of the line.
+    /// * A source index can be a range, such as `[STACK 0..3] TAG`,
+    ///   which expands to a value for each index. The end of the
+    ///   range is exclusive.
+    /// * Values can be joined into one value using `+`, as in
+    ///   `[STACK 0] TAG + [BUFFER 0] TAG`.
+    /// * Values in a group `GROUP name { ... }` are part of the
+    ///   input layer `name`.
     pub fn from_buf_read<R>(mut read: R) -> Result<Self, Error>
     where
         R: BufRead,
@@ -48,40 +61,93 @@ impl AddressedValues {
 /// as a 32-bit identifier, which is typically the row of the layer
 /// value in an embedding matrix.
 pub struct InputVector {
-    pub lookup_layers: EnumMap<Layer, Vec<i32>>,
+    pub lookup_layers: BTreeMap<Layer, Vec<i32>>,
     pub embed_layer: Tensor<f32>,
 }
 
-#[derive(Clone, Copy, Debug, Enum, Eq, PartialEq)]
-pub enum Layer {
-    Token,
-    Tag,
-    DepRel,
-    Feature,
+/// Input layer.
+///
+/// An input layer is a named group of addressed values that share a
+/// lookup and a graph placeholder. An addressed value is part of the
+/// default input layer of its parser state layer (e.g. *tokens* for
+/// `TOKEN`), unless the feature specification assigns it to an input
+/// layer with another name.
+///
+/// Morphological features are part of the input layer of their
+/// attribute (e.g. *features.case* for `FEATURE case`) and the
+/// feature bundle is part of *feature_bundle* when there is a lookup
+/// for that layer. Otherwise, they share the *features* layer.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Layer(Cow<'static, str>);
+
+impl Layer {
+    /// Default input layer of tokens.
+    pub const TOKEN: Layer = Layer(Cow::Borrowed("tokens"));
+
+    /// Default input layer of part-of-speech tags.
+    pub const TAG: Layer = Layer(Cow::Borrowed("tags"));
+
+    /// Default input layer of dependency relations.
+    pub const DEPREL: Layer = Layer(Cow::Borrowed("deprels"));
+
+    /// Default input layer of morphological features.
+    pub const FEATURE: Layer = Layer(Cow::Borrowed("features"));
+
+    /// Default input layer of character sequences.
+    pub const CHAR: Layer = Layer(Cow::Borrowed("chars"));
+
+    /// Default input layer of conjunctions.
+    pub const CONJUNCTION: Layer = Layer(Cow::Borrowed("conjunctions"));
+
+    /// Input layer of feature bundles.
+    pub const FEATURE_BUNDLE: Layer = Layer(Cow::Borrowed("feature_bundle"));
+
+    /// Construct an input layer with the given name.
+    pub fn new<S>(name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Layer(Cow::Owned(name.into()))
+    }
+
+    /// Construct the input layer of a morphological attribute.
+    pub fn feature(name: &str) -> Self {
+        Layer::new(format!("features.{}", name))
+    }
+
+    /// Get the name of the input layer.
+    pub fn name(&self) -> &str {
+        &self.0
+    }
 }
 
 impl fmt::Display for Layer {
     fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
-        let s = match *self {
-            Layer::Token => "tokens",
-            Layer::Tag => "tags",
-            Layer::DepRel => "deprels",
-            Layer::Feature => "features",
-        };
-
-        f.write_str(s)
+        f.write_str(&self.0)
     }
 }
 
-// I am not sure whether I like the use of Borrow here, but is there another
-// convenient way to convert from both addr::Layer and &addr::Layer?
 impl From<&addr::Layer> for Layer {
+    /// Get the default input layer of a parser state layer.
     fn from(layer: &addr::Layer) -> Self {
         match *layer {
-            addr::Layer::Token => Layer::Token,
-            addr::Layer::Tag => Layer::Tag,
-            addr::Layer::DepRel => Layer::DepRel,
-            addr::Layer::Feature(_) => Layer::Feature,
+            addr::Layer::Token => Layer::TOKEN,
+            addr::Layer::Tag => Layer::TAG,
+            addr::Layer::DepRel => Layer::DEPREL,
+            addr::Layer::Feature(_) | addr::Layer::FeatureBundle => Layer::FEATURE,
+            addr::Layer::Char(_) => Layer::CHAR,
+            addr::Layer::Conjunction(_) => Layer::CONJUNCTION,
+        }
+    }
+}
+
+impl From<&addr::AddressedValue> for Layer {
+    /// Get the input layer of an addressed value, disregarding
+    /// attribute and feature bundle input layers.
+    fn from(addr_value: &addr::AddressedValue) -> Self {
+        match addr_value.input {
+            Some(ref input) => Layer::new(input.as_str()),
+            None => (&addr_value.layer).into(),
         }
     }
 }
@@ -91,23 +157,30 @@ impl From<&addr::Layer> for Layer {
 /// This data structure bundles lookups for the different layers (tokens,
 /// part-of-speech, etc).
 #[derive(Default)]
-pub struct LayerLookups(EnumMap<Layer, BoxedLookup>);
+pub struct LayerLookups(BTreeMap<Layer, Box<Lookup>>);
 
 impl LayerLookups {
     pub fn new() -> Self {
-        LayerLookups(EnumMap::new())
+        LayerLookups(BTreeMap::new())
     }
 
     pub fn insert<L>(&mut self, layer: Layer, lookup: L)
     where
         L: Into<Box<Lookup>>,
     {
-        self.0[layer] = BoxedLookup::new(lookup)
+        self.0.insert(layer, lookup.into());
     }
 
     /// Get the lookup for a layer.
-    pub fn layer_lookup(&self, layer: Layer) -> Option<&Lookup> {
-        self.0[layer].as_ref()
+    pub fn layer_lookup(&self, layer: &Layer) -> Option<&Lookup> {
+        self.0.get(layer).map(AsRef::as_ref)
+    }
+
+    /// Get an iterator over the layers and their lookups.
+    pub fn iter(&self) -> impl Iterator<Item = (&Layer, &Lookup)> {
+        self.0
+            .iter()
+            .map(|(layer, lookup)| (layer, lookup.as_ref()))
     }
 }
 
@@ -117,6 +190,7 @@ impl LayerLookups {
 pub struct InputVectorizer {
     layer_lookups: LayerLookups,
     input_layer_addrs: AddressedValues,
+    input_layers: Vec<Layer>,
 }
 
 impl InputVectorizer {
@@ -125,20 +199,41 @@ impl InputVectorizer {
     /// The vectorizer is constructed from the layer lookups and the parser
     /// state addresses from which the feature vector should be used. The layer
     /// lookups are used to find the indices that represent the features.
-    pub fn new(layer_lookups: LayerLookups, input_layer_addrs: AddressedValues) -> Self {
-        InputVectorizer {
+    ///
+    /// Returns an error when there is no lookup for the input layer of an
+    /// addressed value.
+    pub fn new(
+        layer_lookups: LayerLookups,
+        input_layer_addrs: AddressedValues,
+    ) -> Result<Self, Error> {
+        let mut input_layers = Vec::with_capacity(input_layer_addrs.0.len());
+        for addr_value in &input_layer_addrs.0 {
+            let layer = input_layer(&layer_lookups, addr_value);
+            if layer_lookups.layer_lookup(&layer).is_none() {
+                return Err(format_err!(
+                    "Missing lookup for input layer {} of: {}",
+                    layer,
+                    addr_value
+                ));
+            }
+
+            input_layers.push(layer);
+        }
+
+        Ok(InputVectorizer {
             layer_lookups,
             input_layer_addrs,
-        }
+            input_layers,
+        })
     }
 
     pub fn embedding_layer_size(&self) -> usize {
         let mut size = 0;
 
-        for layer in &self.input_layer_addrs.0 {
-            if let Some(lookup) = self.layer_lookups.0[(&layer.layer).into()].as_ref() {
+        for (layer, input_layer) in self.input_layer_addrs.0.iter().zip(&self.input_layers) {
+            if let Some(lookup) = self.layer_lookups.layer_lookup(input_layer) {
                 match lookup.lookup_type() {
-                    LookupType::Embedding(dims) => size += dims,
+                    LookupType::Embedding(dims) => size += dims * layer.layer.seq_len(),
                     LookupType::Index => (),
                 }
             }
@@ -151,20 +246,34 @@ impl InputVectorizer {
         &self.input_layer_addrs
     }
 
+    /// Get the input layers of the addressed values.
+    pub fn input_layers(&self) -> &[Layer] {
+        &self.input_layers
+    }
+
     /// Get the layer lookups.
     pub fn layer_lookups(&self) -> &LayerLookups {
         &self.layer_lookups
     }
 
-    pub fn lookup_layer_sizes(&self) -> EnumMap<Layer, usize> {
-        let mut sizes = EnumMap::new();
-
-        for layer in &self.input_layer_addrs.0 {
-            if let Some(lookup) = self.layer_lookups.0[(&layer.layer).into()].as_ref() {
-                match lookup.lookup_type() {
-                    LookupType::Embedding(_) => (),
-                    LookupType::Index => sizes[(&layer.layer).into()] += 1,
-                }
+    /// Get the sizes of the layers with index lookups.
+    ///
+    /// The size of a layer is the number of indices that the vectorizer
+    /// produces for the layer. Every layer with an index lookup is
+    /// included, even if no addressed value uses the layer.
+    pub fn lookup_layer_sizes(&self) -> BTreeMap<Layer, usize> {
+        let mut sizes: BTreeMap<_, _> = self
+            .layer_lookups
+            .iter()
+            .filter_map(|(layer, lookup)| match lookup.lookup_type() {
+                LookupType::Embedding(_) => None,
+                LookupType::Index => Some((layer.clone(), 0)),
+            })
+            .collect();
+
+        for (layer, input_layer) in self.input_layer_addrs.0.iter().zip(&self.input_layers) {
+            if let Some(size) = sizes.get_mut(input_layer) {
+                *size += layer.layer.seq_len();
             }
         }
 
@@ -175,10 +284,11 @@ impl InputVectorizer {
     pub fn realize(&self, state: &ParserState) -> InputVector {
         let mut embed_layer = Tensor::new(&[self.embedding_layer_size() as u64]);
 
-        let mut lookup_layers = EnumMap::new();
-        for (layer, &size) in &self.lookup_layer_sizes() {
-            lookup_layers[layer] = vec![0; size];
-        }
+        let mut lookup_layers: BTreeMap<_, _> = self
+            .lookup_layer_sizes()
+            .into_iter()
+            .map(|(layer, size)| (layer, vec![0; size]))
+            .collect();
 
         self.realize_into(state, &mut embed_layer, &mut lookup_layers);
 
@@ -193,46 +303,284 @@ impl InputVectorizer {
         &self,
         state: &ParserState,
         embed_layer: &mut [f32],
-        lookup_slices: &mut EnumMap<Layer, S>,
+        lookup_slices: &mut BTreeMap<Layer, S>,
     ) where
         S: AsMut<[i32]>,
     {
         let mut embed_offset = 0;
-        let mut layer_offsets: EnumMap<Layer, usize> = EnumMap::new();
+        let mut layer_offsets: BTreeMap<&Layer, usize> = BTreeMap::new();
+
+        self.for_each_lookup(state, |_, input_layer, _, result| {
+            let offset = layer_offsets.entry(input_layer).or_insert(0);
+            let lookup_slice = match lookup_slices.get_mut(input_layer) {
+                Some(slice) => slice.as_mut(),
+                None => &mut [],
+            };
+
+            copy_lookup_result(result, embed_layer, &mut embed_offset, lookup_slice, offset);
+        });
+    }
+
+    /// Look up the addressed values of a parser state.
+    ///
+    /// Returns for every addressed value the looked up values and the
+    /// lookup results that are used to vectorize the parser state. Absent
+    /// values are `None`. Character layers have a value and result for
+    /// every (padding) character.
+    pub fn lookup_results(&self, state: &ParserState) -> Vec<Vec<(Option<String>, LookupResult)>> {
+        let mut results: Vec<_> = self
+            .input_layer_addrs
+            .0
+            .iter()
+            .map(|_| Vec::new())
+            .collect();
+        self.for_each_lookup(state, |idx, _, value, result| {
+            results[idx].push((value.map(ToOwned::to_owned), result))
+        });
+        results
+    }
 
-        for layer in &self.input_layer_addrs.0 {
+    /// Look up the addressed values of a parser state.
+    ///
+    /// The function is called with the index of the addressed value, its
+    /// input layer, the looked up value, and the lookup result, in the
+    /// order of vectorization.
+    fn for_each_lookup<'a, F>(&'a self, state: &ParserState, mut f: F)
+    where
+        F: FnMut(usize, &'a Layer, Option<&str>, LookupResult<'a>),
+    {
+        for (idx, (layer, input_layer)) in self
+            .input_layer_addrs
+            .0
+            .iter()
+            .zip(&self.input_layers)
+            .enumerate()
+        {
             let val = layer.get(state);
-            let offset = &mut layer_offsets[(&layer.layer).into()];
-
-            let layer = &layer.layer;
-
-            match lookup_value(
-                self.layer_lookups
-                    .layer_lookup(layer.into())
-                    .unwrap_or_else(|| panic!("Missing layer lookup for: {:?}", layer)),
-                val,
-            ) {
-                LookupResult::Embedding(embed) => {
-                    let embed_view = embed.as_view();
-                    embed_layer[embed_offset..embed_offset + embed_view.len()].copy_from_slice(
-                        embed_view.as_slice().expect("Embedding is not contiguous"),
-                    );
-                    embed_offset += embed_view.len();
+            let val = val.as_ref().map(Cow::as_ref);
+
+            let lookup = self
+                .layer_lookups
+                .layer_lookup(input_layer)
+                .expect("Missing layer lookup");
+
+            match layer.layer {
+                addr::Layer::Char(len) => {
+                    // Vectorize the characters, padding with null values.
+                    let mut char_buf = [0; 4];
+                    for c in padded_chars(val, len) {
+                        let c = c.map(|c| &*c.encode_utf8(&mut char_buf));
+                        f(idx, input_layer, c, lookup_value(lookup, c));
+                    }
+                }
+                _ => f(idx, input_layer, val, lookup_value(lookup, val)),
+            }
+        }
+    }
+
+    /// Get the values of a parser state that were not seen before in
+    /// the sentence.
+    ///
+    /// Returns the input layer and value of every value of the parser
+    /// state that does not occur in `seen` and adds these values to
+    /// `seen`. Absent values are not returned. Characters are returned
+    /// as separate values.
+    pub fn occurrences(
+        &self,
+        state: &ParserState,
+        seen: &mut SentenceOccurrences,
+    ) -> Vec<(&Layer, String)> {
+        let mut occurrences = Vec::new();
+
+        for (addr_value, input_layer) in self.input_layer_addrs.0.iter().zip(&self.input_layers) {
+            let val = ok_or!(addr_value.get(state), continue);
+            let kind = value_kind(&addr_value.layer);
+            let tokens = addr_value.tokens(state);
+
+            match addr_value.layer {
+                addr::Layer::Char(len) => {
+                    for (idx, c) in padded_chars(Some(&val), len).enumerate() {
+                        let c = ok_or!(c, break);
+                        if seen
+                            .0
+                            .insert((input_layer.clone(), kind.clone(), tokens.clone(), idx))
+                        {
+                            occurrences.push((input_layer, c.to_string()));
+                        }
+                    }
                 }
-                LookupResult::Index(idx) => {
-                    lookup_slices[layer.into()].as_mut()[*offset] = idx as i32;
-                    *offset += 1;
+                _ => {
+                    if seen.0.insert((input_layer.clone(), kind, tokens, 0)) {
+                        occurrences.push((input_layer, val.into_owned()));
+                    }
                 }
             }
         }
+
+        occurrences
+    }
+}
+
+/// Values that were seen in the parser states of a sentence.
+///
+/// A value is identified by its input layer, the tokens that it is
+/// a value of and, for character layers, its position. This is used
+/// to count every value once per occurrence in the corpus, rather than
+/// once for every parser state in which it is looked up.
+#[derive(Default)]
+pub struct SentenceOccurrences(HashSet<(Layer, String, Vec<Option<usize>>, usize)>);
+
+impl SentenceOccurrences {
+    pub fn new() -> Self {
+        SentenceOccurrences::default()
+    }
+
+    /// Forget the values of the previous sentence.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Get the kind of value that a parser state layer provides.
+///
+/// Character layers of different lengths provide the same characters.
+fn value_kind(layer: &addr::Layer) -> String {
+    match *layer {
+        addr::Layer::Char(_) => "CHARS".to_owned(),
+        addr::Layer::Conjunction(ref conjuncts) => {
+            let kinds: Vec<_> = conjuncts.iter().map(|c| value_kind(&c.layer)).collect();
+            kinds.join(" + ")
+        }
+        _ => layer.to_string(),
+    }
+}
+
+/// Get the character sequence of a value.
+///
+/// The sequence has length `len`. Longer values are truncated, shorter
+/// and absent values are padded with `None`.
+pub fn padded_chars(value: Option<&str>, len: usize) -> impl Iterator<Item = Option<char>> + '_ {
+    let mut chars = value.map(str::chars);
+    (0..len).map(move |_| chars.as_mut().and_then(Iterator::next))
+}
+
+/// Get the input layer of an addressed value.
+///
+/// Features and feature bundles are only assigned to their own input
+/// layers when there is a lookup for the layer.
+fn input_layer(layer_lookups: &LayerLookups, addr_value: &addr::AddressedValue) -> Layer {
+    if addr_value.input.is_none() {
+        let feature_layer = match addr_value.layer {
+            addr::Layer::Feature(ref name) => Some(Layer::feature(name)),
+            addr::Layer::FeatureBundle => Some(Layer::FEATURE_BUNDLE),
+            _ => None,
+        };
+
+        if let Some(layer) = feature_layer {
+            if layer_lookups.layer_lookup(&layer).is_some() {
+                return layer;
+            }
+        }
     }
+
+    addr_value.into()
 }
 
-fn lookup_value<'a>(lookup: &'a Lookup, feature: Option<Cow<str>>) -> LookupResult<'a> {
+/// Copy a lookup result to the embedding layer or a lookup layer.
+fn copy_lookup_result(
+    result: LookupResult,
+    embed_layer: &mut [f32],
+    embed_offset: &mut usize,
+    lookup_slice: &mut [i32],
+    lookup_offset: &mut usize,
+) {
+    match result {
+        LookupResult::Embedding(embed) => {
+            let embed_view = embed.as_view();
+            embed_layer[*embed_offset..*embed_offset + embed_view.len()]
+                .copy_from_slice(embed_view.as_slice().expect("Embedding is not contiguous"));
+            *embed_offset += embed_view.len();
+        }
+        LookupResult::Index(idx) => {
+            lookup_slice[*lookup_offset] = idx as i32;
+            *lookup_offset += 1;
+        }
+    }
+}
+
+fn lookup_value<'a>(lookup: &'a Lookup, feature: Option<&str>) -> LookupResult<'a> {
     match feature {
-        Some(f) => lookup
-            .lookup(f.as_ref())
-            .unwrap_or_else(|| lookup.unknown()),
+        Some(f) => lookup.lookup(f).unwrap_or_else(|| lookup.unknown()),
         None => lookup.null(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use conllx::Token;
+
+    use super::{AddressedValues, InputVectorizer, Layer, LayerLookups};
+    use crate::features::addr::{self, AddressedValue, Source};
+    use crate::features::{Lookup, MutableLookupTable};
+    use crate::system::ParserState;
+
+    fn test_char_vectorizer() -> InputVectorizer {
+        let value = |source| AddressedValue {
+            address: vec![source],
+            layer: addr::Layer::Char(4),
+            input: None,
+        };
+
+        let mut lookups = LayerLookups::new();
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(Layer::CHAR, table);
+        InputVectorizer::new(
+            lookups,
+            AddressedValues(vec![value(Source::Stack(0)), value(Source::Buffer(0))]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn lookup_results_match_realize() {
+        let sent = vec![Token::new("een"), Token::new("test")];
+        let state = ParserState::new(&sent);
+
+        let vectorizer = test_char_vectorizer();
+        let results = vectorizer.lookup_results(&state);
+        assert_eq!(results.len(), 2);
+
+        let indices: Vec<_> = results
+            .iter()
+            .flatten()
+            .map(|(_, result)| result.index().unwrap() as i32)
+            .collect();
+        assert_eq!(
+            indices,
+            vectorizer.realize(&state).lookup_layers[&Layer::CHAR]
+        );
+
+        // The padding of the second value is null.
+        assert_eq!(results[1][2].0, Some("n".to_owned()));
+        assert_eq!(results[1][3].0, None);
+        assert_eq!(results[1][3].1.index(), Some(0));
+    }
+
+    #[test]
+    fn missing_named_input_lookup() {
+        let addrs = vec![AddressedValue {
+            address: vec![Source::Buffer(0)],
+            layer: addr::Layer::Token,
+            input: Some("next_tokens".to_owned()),
+        }];
+
+        let mut lookups = LayerLookups::new();
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(Layer::TOKEN, table);
+
+        let err = InputVectorizer::new(lookups, AddressedValues(addrs))
+            .err()
+            .expect("Vectorizer without a lookup for next_tokens");
+        assert!(err.to_string().contains("next_tokens"));
+    }
+}
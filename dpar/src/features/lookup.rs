@@ -1,10 +1,14 @@
 use std::cell::RefCell;
 use std::ops::Deref;
 
-use ndarray::Array1;
+use failure::{format_err, Error};
+use ndarray::{s, Array1, Array2, ArrayView2};
 use rust2vec::embeddings::Embeddings as R2VEmbeddings;
-use rust2vec::storage::{CowArray, CowArray1, StorageWrap};
-use rust2vec::vocab::{Vocab, VocabWrap};
+use rust2vec::storage::{CowArray, CowArray1, NdArray, Storage, StorageWrap};
+use rust2vec::vocab::{Vocab, VocabWrap, WordIndex};
+use serde::de::Error as DeError;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_derive::{Deserialize, Serialize};
 
 use crate::Numberer;
@@ -64,14 +68,246 @@ pub trait Lookup {
 
     // Unknown value.
     fn unknown(&self) -> LookupResult;
+
+    /// Embedding matrix for the indices of the lookup.
+    ///
+    /// This matrix is used to initialize the embeddings of a graph for
+    /// lookups that return indices into pretrained embeddings. Returns
+    /// `None` for other lookups.
+    fn embedding_matrix(&self) -> Option<Array2<f32>> {
+        None
+    }
+
+    /// Frequency of the feature with the given index.
+    ///
+    /// Returns `None` if the lookup does not store frequencies.
+    fn frequency(&self, _index: usize) -> Option<usize> {
+        None
+    }
+
+    /// Count an occurrence of a feature in the training data.
+    ///
+    /// Lookups that do not store frequencies ignore occurrences.
+    fn add_occurrence(&self, _feature: &str) {}
+}
+
+/// Vector for special values, such as unknown and null values.
+///
+/// In configuration files, the vectors are written as `"average"`,
+/// `"zero"`, and `{ word = "<unk>" }`. Since TOML cannot represent
+/// newtype variants, (de)serialization is implemented by hand.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SpecialVector {
+    /// The normalized average of all embeddings.
+    Average,
+
+    /// A vector of zeros.
+    Zero,
+
+    /// The embedding of a word in the vocabulary, such as `<unk>`.
+    Word(String),
+}
+
+impl Serialize for SpecialVector {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            SpecialVector::Average => serializer.serialize_str("average"),
+            SpecialVector::Zero => serializer.serialize_str("zero"),
+            SpecialVector::Word(word) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("word", word)?;
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SpecialVector {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum SpecialVectorFormat {
+            Name(String),
+            Word { word: String },
+        }
+
+        match SpecialVectorFormat::deserialize(deserializer)? {
+            SpecialVectorFormat::Name(ref name) if name == "average" => Ok(SpecialVector::Average),
+            SpecialVectorFormat::Name(ref name) if name == "zero" => Ok(SpecialVector::Zero),
+            SpecialVectorFormat::Name(name) => Err(D::Error::custom(format!(
+                "unknown special vector: {}",
+                name
+            ))),
+            SpecialVectorFormat::Word { word } => Ok(SpecialVector::Word(word)),
+        }
+    }
+}
+
+/// Options for embedding lookups.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EmbeddingOptions {
+    /// L2-normalize the embedding matrix.
+    #[serde(default)]
+    pub normalize: bool,
+
+    /// The vector for unknown values.
+    #[serde(default = "default_unknown_vector")]
+    pub unknown: SpecialVector,
+
+    /// The vector for null values.
+    #[serde(default = "default_null_vector")]
+    pub null: SpecialVector,
+
+    /// Return indices into an embedding matrix that is trained with
+    /// the model, rather than frozen embeddings.
+    #[serde(default)]
+    pub fine_tune: bool,
+}
+
+fn default_unknown_vector() -> SpecialVector {
+    SpecialVector::Average
+}
+
+fn default_null_vector() -> SpecialVector {
+    SpecialVector::Zero
+}
+
+impl Default for EmbeddingOptions {
+    fn default() -> Self {
+        EmbeddingOptions {
+            normalize: false,
+            unknown: default_unknown_vector(),
+            null: default_null_vector(),
+            fine_tune: false,
+        }
+    }
 }
 
+/// Embedding lookup.
+///
+/// If the vocabulary of the embeddings has subword information, the
+/// embedding of a word that is not in the vocabulary is the average
+/// of the embeddings of its character n-grams. The unknown vector is
+/// only used for words that do not have any n-gram embeddings.
+///
+/// When the embeddings are fine-tuned, the lookup returns indices
+/// rather than embeddings. Index *0* is used for null values, index *1*
+/// for unknown values, and the following indices for the words in the
+/// vocabulary. Subword embeddings are not used in this case.
 pub struct Embeddings {
     inner: R2VEmbeddings<VocabWrap, StorageWrap>,
+    normalize: bool,
+    fine_tune: bool,
     null: Array1<f32>,
     unknown: Array1<f32>,
 }
 
+impl Embeddings {
+    /// Construct an embedding lookup with the given options.
+    ///
+    /// Returns an error when a special vector refers to a word that is
+    /// not in the vocabulary.
+    pub fn with_options(
+        embeddings: R2VEmbeddings<VocabWrap, StorageWrap>,
+        options: &EmbeddingOptions,
+    ) -> Result<Self, Error> {
+        let inner = if options.normalize {
+            l2_normalize(embeddings)
+        } else {
+            embeddings
+        };
+
+        let unknown = special_vector(&inner, &options.unknown)?;
+        let null = special_vector(&inner, &options.null)?;
+
+        Ok(Embeddings {
+            inner,
+            normalize: options.normalize,
+            fine_tune: options.fine_tune,
+            null,
+            unknown,
+        })
+    }
+
+    /// Returns `true` if the lookup returns indices for fine-tuning.
+    pub fn fine_tune(&self) -> bool {
+        self.fine_tune
+    }
+
+    /// Get the values of the fine-tuning indices.
+    ///
+    /// The null and unknown indices are represented by `<null>` and
+    /// `<unk>`.
+    pub fn index_vocab(&self) -> Vec<&str> {
+        let mut vocab = vec!["<null>", "<unk>"];
+        vocab.extend(self.vocab().words().iter().map(String::as_str));
+        vocab
+    }
+
+    /// Construct embeddings with fine-tuned word embeddings.
+    ///
+    /// The fine-tuned matrix should be ordered by the indices that the
+    /// lookup returns. Rows for subword n-grams are not fine-tuned and
+    /// copied from the pretrained embeddings.
+    pub fn fine_tuned(
+        &self,
+        fine_tuned: ArrayView2<f32>,
+    ) -> Result<R2VEmbeddings<VocabWrap, StorageWrap>, Error> {
+        let n_words = self.vocab().len();
+        let (rows, dims) = self.storage().shape();
+        if fine_tuned.shape() != [n_words + UNKNOWN_INDEX + 1, dims] {
+            return Err(format_err!(
+                "Fine-tuned matrix has shape {:?}, expected [{}, {}]",
+                fine_tuned.shape(),
+                n_words + UNKNOWN_INDEX + 1,
+                dims
+            ));
+        }
+
+        let mut matrix = Array2::zeros((rows, dims));
+        for (idx, mut row) in matrix.outer_iter_mut().enumerate() {
+            row.assign(&self.storage().embedding(idx).as_view());
+        }
+        matrix
+            .slice_mut(s![..n_words, ..])
+            .assign(&fine_tuned.slice(s![UNKNOWN_INDEX + 1.., ..]));
+
+        Ok(R2VEmbeddings::new(
+            None,
+            self.vocab().clone(),
+            NdArray(matrix).into(),
+        ))
+    }
+
+    /// Compute the embedding of a word from its n-gram embeddings.
+    fn subword_embedding(&self, indices: &[usize]) -> Option<Array1<f32>> {
+        if indices.is_empty() {
+            return None;
+        }
+
+        let mut embed = Array1::zeros(self.dims());
+        for &idx in indices {
+            embed += &self.storage().embedding(idx).as_view();
+        }
+        embed /= indices.len() as f32;
+
+        if self.normalize {
+            let l2norm = embed.dot(&embed).sqrt();
+            if l2norm != 0f32 {
+                embed /= l2norm;
+            }
+        }
+
+        Some(embed)
+    }
+}
+
 impl Deref for Embeddings {
     type Target = R2VEmbeddings<VocabWrap, StorageWrap>;
 
@@ -82,53 +318,174 @@ impl Deref for Embeddings {
 
 impl From<R2VEmbeddings<VocabWrap, StorageWrap>> for Embeddings {
     fn from(embeddings: R2VEmbeddings<VocabWrap, StorageWrap>) -> Self {
-        let null = Array1::zeros(embeddings.dims());
+        Embeddings::with_options(embeddings, &EmbeddingOptions::default())
+            .expect("Default embedding options do not use the vocabulary")
+    }
+}
 
-        // Compute the average embedding.
-        let mut unknown = Array1::zeros(embeddings.dims());
-        for (_, embed) in &embeddings {
-            unknown += &embed.as_view();
-        }
-        let l2norm = unknown.dot(&unknown).sqrt();
+/// L2-normalize every embedding in the embedding matrix.
+fn l2_normalize(
+    embeddings: R2VEmbeddings<VocabWrap, StorageWrap>,
+) -> R2VEmbeddings<VocabWrap, StorageWrap> {
+    let (metadata, vocab, storage) = embeddings.into_parts();
+
+    let (rows, dims) = storage.shape();
+    let mut matrix = Array2::zeros((rows, dims));
+    for (idx, mut row) in matrix.outer_iter_mut().enumerate() {
+        row.assign(&storage.embedding(idx).as_view());
+        let l2norm = row.dot(&row).sqrt();
         if l2norm != 0f32 {
-            unknown /= l2norm;
+            row /= l2norm;
         }
+    }
 
-        Embeddings {
-            inner: embeddings,
-            null,
-            unknown,
+    R2VEmbeddings::new(metadata, vocab, NdArray(matrix).into())
+}
+
+/// Construct a special vector.
+fn special_vector(
+    embeddings: &R2VEmbeddings<VocabWrap, StorageWrap>,
+    special: &SpecialVector,
+) -> Result<Array1<f32>, Error> {
+    match special {
+        SpecialVector::Average => {
+            let mut average = Array1::zeros(embeddings.dims());
+            for (_, embed) in embeddings {
+                average += &embed.as_view();
+            }
+            let l2norm = average.dot(&average).sqrt();
+            if l2norm != 0f32 {
+                average /= l2norm;
+            }
+
+            Ok(average)
         }
+        SpecialVector::Zero => Ok(Array1::zeros(embeddings.dims())),
+        SpecialVector::Word(word) => embeddings
+            .embedding(word)
+            .map(CowArray::into_owned)
+            .ok_or_else(|| format_err!("Word is not in the embedding vocabulary: {}", word)),
     }
 }
 
 impl Lookup for Embeddings {
     fn len(&self) -> usize {
-        self.vocab().len()
+        if self.fine_tune {
+            self.vocab().len() + UNKNOWN_INDEX + 1
+        } else {
+            self.vocab().len()
+        }
     }
 
     fn lookup(&self, feature: &str) -> Option<LookupResult> {
-        self.embedding(feature).map(LookupResult::Embedding)
+        if self.fine_tune {
+            return match self.vocab().idx(feature)? {
+                WordIndex::Word(idx) => Some(LookupResult::Index(idx + UNKNOWN_INDEX + 1)),
+                WordIndex::Subword(_) => None,
+            };
+        }
+
+        let embed = match self.vocab().idx(feature)? {
+            WordIndex::Word(idx) => self.storage().embedding(idx),
+            WordIndex::Subword(indices) => CowArray::Owned(self.subword_embedding(&indices)?),
+        };
+
+        Some(LookupResult::Embedding(embed))
     }
 
     fn lookup_type(&self) -> LookupType {
-        LookupType::Embedding(self.dims())
+        if self.fine_tune {
+            LookupType::Index
+        } else {
+            LookupType::Embedding(self.dims())
+        }
     }
 
     fn null(&self) -> LookupResult {
-        LookupResult::Embedding(CowArray::Borrowed(self.null.view()))
+        if self.fine_tune {
+            LookupResult::Index(NULL_INDEX)
+        } else {
+            LookupResult::Embedding(CowArray::Borrowed(self.null.view()))
+        }
     }
 
     fn unknown(&self) -> LookupResult {
-        LookupResult::Embedding(CowArray::Borrowed(self.unknown.view()))
+        if self.fine_tune {
+            LookupResult::Index(UNKNOWN_INDEX)
+        } else {
+            LookupResult::Embedding(CowArray::Borrowed(self.unknown.view()))
+        }
+    }
+
+    fn embedding_matrix(&self) -> Option<Array2<f32>> {
+        if !self.fine_tune {
+            return None;
+        }
+
+        let mut matrix = Array2::zeros((self.len(), self.dims()));
+        matrix.row_mut(NULL_INDEX).assign(&self.null);
+        matrix.row_mut(UNKNOWN_INDEX).assign(&self.unknown);
+        for (idx, (_, embed)) in self.iter().enumerate() {
+            matrix
+                .row_mut(idx + UNKNOWN_INDEX + 1)
+                .assign(&embed.as_view());
+        }
+
+        Some(matrix)
     }
 }
 
-pub struct MutableLookupTable(RefCell<Numberer<String>>);
+/// Index of the null value in lookup tables.
+const NULL_INDEX: usize = 0;
+
+/// Index of the unknown value in lookup tables.
+const UNKNOWN_INDEX: usize = 1;
+
+/// Lookup table that numbers and counts the features that it sees.
+///
+/// This table is used to construct a lookup table while preparing the
+/// training data. The indices that are returned are provisional, since
+/// features with a frequency below the minimum count are pruned when the
+/// table is converted into a `LookupTable`. Looking up a feature does
+/// not change its count, the count of a feature is the number of
+/// occurrences that were added with `Lookup::add_occurrence`.
+pub struct MutableLookupTable {
+    numberer: RefCell<Numberer<String>>,
+    counts: RefCell<Vec<usize>>,
+    min_count: usize,
+}
 
 impl MutableLookupTable {
     pub fn new() -> Self {
-        MutableLookupTable(RefCell::new(Numberer::new(1)))
+        Self::with_min_count(1)
+    }
+
+    /// Construct a table that prunes features that were seen less
+    /// than `min_count` times.
+    pub fn with_min_count(min_count: usize) -> Self {
+        MutableLookupTable {
+            numberer: RefCell::new(Numberer::new(UNKNOWN_INDEX + 1)),
+            counts: RefCell::new(Vec::new()),
+            min_count,
+        }
+    }
+
+    /// Get the minimum count of features.
+    pub fn min_count(&self) -> usize {
+        self.min_count
+    }
+
+    /// Number a feature, returning its provisional index.
+    fn add(&self, feature: &str) -> usize {
+        let mut numberer = self.numberer.borrow_mut();
+        let idx = numberer.add(feature.to_owned());
+
+        let mut counts = self.counts.borrow_mut();
+        if idx - numberer.start_at() == counts.len() {
+            counts.push(0);
+        }
+
+        idx
     }
 }
 
@@ -139,13 +496,19 @@ impl Default for MutableLookupTable {
 }
 
 impl Lookup for MutableLookupTable {
+    /// Size of the table after pruning.
     fn len(&self) -> usize {
-        self.0.borrow().len() + 1
+        let n_retained = self
+            .counts
+            .borrow()
+            .iter()
+            .filter(|&&count| count >= self.min_count)
+            .count();
+        n_retained + UNKNOWN_INDEX + 1
     }
 
     fn lookup(&self, feature: &str) -> Option<LookupResult> {
-        let mut numberer = self.0.borrow_mut();
-        Some(LookupResult::Index(numberer.add(feature.to_owned())))
+        Some(LookupResult::Index(self.add(feature)))
     }
 
     fn lookup_type(&self) -> LookupType {
@@ -153,30 +516,106 @@ impl Lookup for MutableLookupTable {
     }
 
     fn null(&self) -> LookupResult {
-        LookupResult::Index(0)
+        LookupResult::Index(NULL_INDEX)
     }
 
     fn unknown(&self) -> LookupResult {
-        LookupResult::Index(0)
+        LookupResult::Index(UNKNOWN_INDEX)
+    }
+
+    fn frequency(&self, index: usize) -> Option<usize> {
+        index
+            .checked_sub(UNKNOWN_INDEX + 1)
+            .and_then(|idx| self.counts.borrow().get(idx).cloned())
+    }
+
+    fn add_occurrence(&self, feature: &str) {
+        let idx = self.add(feature);
+        self.counts.borrow_mut()[idx - (UNKNOWN_INDEX + 1)] += 1;
+    }
+}
+
+/// Lookup table with feature counts.
+///
+/// Index *0* is used for null values and index *1* for unknown values.
+/// Tables in the older format, which did not store counts, use index
+/// *0* for both null and unknown values.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct LookupTable {
+    numberer: Numberer<String>,
+    counts: Vec<usize>,
+}
+
+impl LookupTable {
+    /// Get the count of a feature.
+    ///
+    /// Returns `None` if the feature is not in the table. Tables in the
+    /// older format return a count of *0* for all features.
+    pub fn count(&self, feature: &str) -> Option<usize> {
+        self.numberer.number(feature).map(|idx| {
+            self.counts
+                .get(idx - self.numberer.start_at())
+                .cloned()
+                .unwrap_or(0)
+        })
     }
 }
 
-#[derive(Eq, PartialEq, Serialize, Deserialize)]
-pub struct LookupTable(Numberer<String>);
+impl<'de> Deserialize<'de> for LookupTable {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum LookupTableFormat {
+            Counts {
+                numberer: Numberer<String>,
+                counts: Vec<usize>,
+            },
+            Legacy(Numberer<String>),
+        }
+
+        Ok(match LookupTableFormat::deserialize(deserializer)? {
+            LookupTableFormat::Counts { numberer, counts } => LookupTable { numberer, counts },
+            LookupTableFormat::Legacy(numberer) => LookupTable {
+                numberer,
+                counts: Vec::new(),
+            },
+        })
+    }
+}
 
 impl From<MutableLookupTable> for LookupTable {
     fn from(t: MutableLookupTable) -> Self {
-        LookupTable(t.0.into_inner())
+        let old_numberer = t.numberer.into_inner();
+        let old_counts = t.counts.into_inner();
+
+        let mut numberer = Numberer::new(UNKNOWN_INDEX + 1);
+        let mut counts = Vec::new();
+        for (idx, count) in old_counts.into_iter().enumerate() {
+            if count < t.min_count {
+                continue;
+            }
+
+            let feature = old_numberer
+                .value(idx + old_numberer.start_at())
+                .expect("Counted feature without a number");
+            numberer.add(feature.clone());
+            counts.push(count);
+        }
+
+        LookupTable { numberer, counts }
     }
 }
 
 impl Lookup for LookupTable {
     fn len(&self) -> usize {
-        self.0.len() + 1
+        self.numberer.len() + self.numberer.start_at()
     }
 
     fn lookup(&self, feature: &str) -> Option<LookupResult> {
-        self.0.number(feature).map(LookupResult::Index)
+        self.numberer.number(feature).map(LookupResult::Index)
     }
 
     fn lookup_type(&self) -> LookupType {
@@ -184,63 +623,344 @@ impl Lookup for LookupTable {
     }
 
     fn null(&self) -> LookupResult {
-        LookupResult::Index(0)
+        LookupResult::Index(NULL_INDEX)
     }
 
     fn unknown(&self) -> LookupResult {
-        LookupResult::Index(0)
+        // The unknown index directly precedes the first feature. In
+        // tables in the older format, this is the null index.
+        LookupResult::Index(self.numberer.start_at() - 1)
+    }
+
+    fn frequency(&self, index: usize) -> Option<usize> {
+        index
+            .checked_sub(self.numberer.start_at())
+            .and_then(|idx| self.counts.get(idx))
+            .cloned()
     }
 }
 
-pub struct BoxedLookup(Option<Box<Lookup>>);
+/// Lookup that hashes features into a fixed number of buckets.
+///
+/// This lookup does not store a vocabulary, so it can be used for
+/// layers with an open vocabulary. Every feature is mapped to one of
+/// the buckets, so features that were not seen while preparing the
+/// training data still get a (shared) index. Index *0* is used for
+/// null values and index *1* for unknown values, the buckets start at
+/// index *2*.
+///
+/// Features are hashed using 64-bit FNV-1a, so that the indices are
+/// stable across platforms and Rust versions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HashedLookup {
+    buckets: usize,
+    seed: u64,
+}
 
-impl BoxedLookup {
-    /// Construct a boxed lookup from a lookup.
-    pub fn new<L>(lookup: L) -> Self
-    where
-        L: Into<Box<Lookup>>,
-    {
-        BoxedLookup(Some(lookup.into()))
+impl HashedLookup {
+    /// Construct a hashed lookup.
+    ///
+    /// Returns an error if the number of buckets is zero.
+    pub fn new(buckets: usize, seed: u64) -> Result<Self, Error> {
+        if buckets == 0 {
+            return Err(format_err!(
+                "The number of buckets of a hashed lookup should be at least 1"
+            ));
+        }
+
+        Ok(HashedLookup { buckets, seed })
+    }
+
+    /// Get the number of buckets.
+    pub fn buckets(&self) -> usize {
+        self.buckets
     }
 
-    /// Get the lookup as a reference.
-    pub fn as_ref(&self) -> Option<&Lookup> {
-        self.0.as_ref().map(AsRef::as_ref)
+    /// Get the seed of the hash function.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn hash(&self, feature: &str) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        (0..8)
+            .map(|i| (self.seed >> (i * 8)) as u8)
+            .chain(feature.bytes())
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+            })
     }
 }
 
-impl Default for BoxedLookup {
-    fn default() -> Self {
-        BoxedLookup(None)
+impl Lookup for HashedLookup {
+    fn len(&self) -> usize {
+        self.buckets + UNKNOWN_INDEX + 1
+    }
+
+    fn lookup(&self, feature: &str) -> Option<LookupResult> {
+        let bucket = (self.hash(feature) % self.buckets as u64) as usize;
+        Some(LookupResult::Index(bucket + UNKNOWN_INDEX + 1))
+    }
+
+    fn lookup_type(&self) -> LookupType {
+        LookupType::Index
+    }
+
+    fn null(&self) -> LookupResult {
+        LookupResult::Index(NULL_INDEX)
+    }
+
+    fn unknown(&self) -> LookupResult {
+        LookupResult::Index(UNKNOWN_INDEX)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Lookup, LookupTable, MutableLookupTable};
+    use ndarray::{arr1, arr2, s};
+    use rust2vec::embeddings::Embeddings as R2VEmbeddings;
+    use rust2vec::storage::{NdArray, StorageWrap};
+    use rust2vec::vocab::{SimpleVocab, SubwordVocab, VocabWrap};
+
+    use super::{
+        EmbeddingOptions, Embeddings, HashedLookup, Lookup, LookupResult, LookupTable,
+        MutableLookupTable, SpecialVector,
+    };
+    use crate::Numberer;
+
+    fn test_embeddings() -> R2VEmbeddings<VocabWrap, StorageWrap> {
+        let vocab = SimpleVocab::new(vec!["a".to_owned(), "b".to_owned(), "<unk>".to_owned()]);
+        let storage = NdArray(arr2(&[[3., 4.], [0., 2.], [1., 0.]]));
+        R2VEmbeddings::new(None, vocab.into(), storage.into())
+    }
+
+    /// Embeddings with a subword vocabulary, all n-gram buckets have
+    /// the same embedding.
+    fn test_subword_embeddings() -> R2VEmbeddings<VocabWrap, StorageWrap> {
+        let vocab = SubwordVocab::new(vec!["a".to_owned()], 3, 6, 2);
+        let storage = NdArray(arr2(&[[1., 0.], [0., 2.], [0., 2.], [0., 2.], [0., 2.]]));
+        R2VEmbeddings::new(None, vocab.into(), storage.into())
+    }
+
+    fn embedding(result: Option<LookupResult>) -> Vec<f32> {
+        match result {
+            Some(LookupResult::Embedding(embed)) => embed.as_view().to_vec(),
+            _ => panic!("Expected an embedding"),
+        }
+    }
 
     #[test]
     fn mutable_lookup_table_test() {
         let table = MutableLookupTable::new();
-        assert_eq!(table.lookup("a").unwrap().index(), Some(1));
-        assert_eq!(table.lookup("b").unwrap().index(), Some(2));
-        assert_eq!(table.lookup("a").unwrap().index(), Some(1));
+        assert_eq!(table.lookup("a").unwrap().index(), Some(2));
+        assert_eq!(table.lookup("b").unwrap().index(), Some(3));
+        assert_eq!(table.lookup("a").unwrap().index(), Some(2));
         assert_eq!(table.null().index(), Some(0));
-        assert_eq!(table.unknown().index(), Some(0));
+        assert_eq!(table.unknown().index(), Some(1));
+
+        // Lookups do not count as occurrences.
+        assert_eq!(table.frequency(2), Some(0));
+        assert_eq!(table.len(), 2);
+
+        table.add_occurrence("a");
+        table.add_occurrence("b");
+        table.add_occurrence("b");
+        assert_eq!(table.frequency(2), Some(1));
+        assert_eq!(table.frequency(3), Some(2));
+        assert_eq!(table.len(), 4);
     }
 
     #[test]
     fn lookup_table_test() {
         let table = MutableLookupTable::new();
-        assert_eq!(table.lookup("a").unwrap().index(), Some(1));
-        assert_eq!(table.lookup("b").unwrap().index(), Some(2));
+        assert_eq!(table.lookup("a").unwrap().index(), Some(2));
+        assert_eq!(table.lookup("b").unwrap().index(), Some(3));
+        table.add_occurrence("a");
+        table.add_occurrence("b");
 
         let table: LookupTable = table.into();
-        assert_eq!(table.lookup("a").unwrap().index(), Some(1));
-        assert_eq!(table.lookup("a").unwrap().index(), Some(1));
-        assert_eq!(table.lookup("b").unwrap().index(), Some(2));
+        assert_eq!(table.lookup("a").unwrap().index(), Some(2));
+        assert_eq!(table.lookup("a").unwrap().index(), Some(2));
+        assert_eq!(table.lookup("b").unwrap().index(), Some(3));
         assert!(table.lookup("c").is_none());
         assert_eq!(table.null().index(), Some(0));
+        assert_eq!(table.unknown().index(), Some(1));
+        assert_eq!(table.len(), 4);
+    }
+
+    #[test]
+    fn lookup_table_pruning_test() {
+        let table = MutableLookupTable::with_min_count(2);
+        for feature in &["a", "b", "c", "b", "c", "c"] {
+            table.add_occurrence(feature);
+        }
+        assert_eq!(table.len(), 4);
+
+        let table: LookupTable = table.into();
+        assert!(table.lookup("a").is_none());
+        assert_eq!(table.lookup("b").unwrap().index(), Some(2));
+        assert_eq!(table.lookup("c").unwrap().index(), Some(3));
+        assert_eq!(table.count("a"), None);
+        assert_eq!(table.count("b"), Some(2));
+        assert_eq!(table.count("c"), Some(3));
+        assert_eq!(table.frequency(1), None);
+        assert_eq!(table.frequency(3), Some(3));
+        assert_eq!(table.len(), 4);
+    }
+
+    #[test]
+    fn embeddings_default_test() {
+        let embeds: Embeddings = test_embeddings().into();
+        assert_eq!(embedding(embeds.lookup("a")), vec![3., 4.]);
+        assert!(embeds.lookup("c").is_none());
+        assert_eq!(embedding(Some(embeds.null())), vec![0., 0.]);
+
+        // The unknown vector is the normalized average.
+        let unknown = embedding(Some(embeds.unknown()));
+        let norm = 52f32.sqrt();
+        assert!((unknown[0] - 4. / norm).abs() < 1e-6);
+        assert!((unknown[1] - 6. / norm).abs() < 1e-6);
+    }
+
+    #[test]
+    fn embeddings_options_test() {
+        let embeds = Embeddings::with_options(
+            test_embeddings(),
+            &EmbeddingOptions {
+                normalize: true,
+                unknown: SpecialVector::Word("<unk>".to_owned()),
+                null: SpecialVector::Average,
+                fine_tune: false,
+            },
+        )
+        .unwrap();
+        assert_eq!(embedding(embeds.lookup("a")), vec![0.6, 0.8]);
+        assert_eq!(embedding(embeds.lookup("b")), vec![0., 1.]);
+        assert_eq!(embedding(Some(embeds.unknown())), vec![1., 0.]);
+
+        let null = arr1(&embedding(Some(embeds.null())));
+        assert!((null.dot(&null) - 1.).abs() < 1e-6);
+        assert!(null[0] > 0. && null[1] > null[0]);
+
+        let embeds = Embeddings::with_options(
+            test_embeddings(),
+            &EmbeddingOptions {
+                unknown: SpecialVector::Zero,
+                ..EmbeddingOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(embedding(embeds.lookup("b")), vec![0., 2.]);
+        assert_eq!(embedding(Some(embeds.unknown())), vec![0., 0.]);
+    }
+
+    #[test]
+    fn embeddings_subword_test() {
+        let embeds: Embeddings = test_subword_embeddings().into();
+        assert_eq!(embedding(embeds.lookup("a")), vec![1., 0.]);
+        assert_eq!(embedding(embeds.lookup("haus")), vec![0., 2.]);
+
+        let embeds = Embeddings::with_options(
+            test_subword_embeddings(),
+            &EmbeddingOptions {
+                normalize: true,
+                ..EmbeddingOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(embedding(embeds.lookup("haus")), vec![0., 1.]);
+    }
+
+    #[test]
+    fn embeddings_fine_tune_test() {
+        let embeds = Embeddings::with_options(
+            test_embeddings(),
+            &EmbeddingOptions {
+                unknown: SpecialVector::Zero,
+                fine_tune: true,
+                ..EmbeddingOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(embeds.len(), 5);
+        assert_eq!(embeds.lookup("a").unwrap().index(), Some(2));
+        assert_eq!(embeds.lookup("<unk>").unwrap().index(), Some(4));
+        assert!(embeds.lookup("c").is_none());
+        assert_eq!(embeds.null().index(), Some(0));
+        assert_eq!(embeds.unknown().index(), Some(1));
+        assert_eq!(
+            embeds.index_vocab(),
+            vec!["<null>", "<unk>", "a", "b", "<unk>"]
+        );
+
+        let matrix = embeds.embedding_matrix().unwrap();
+        assert_eq!(
+            matrix,
+            arr2(&[[0., 0.], [0., 0.], [3., 4.], [0., 2.], [1., 0.]])
+        );
+
+        let fine_tuned = embeds.fine_tuned((&matrix * 2.).view()).unwrap();
+        assert_eq!(
+            fine_tuned.embedding("b").unwrap().as_view().to_vec(),
+            vec![0., 4.]
+        );
+
+        assert!(embeds.fine_tuned(matrix.slice(s![1.., ..])).is_err());
+    }
+
+    #[test]
+    fn embeddings_missing_word_test() {
+        assert!(Embeddings::with_options(
+            test_embeddings(),
+            &EmbeddingOptions {
+                unknown: SpecialVector::Word("<oov>".to_owned()),
+                ..EmbeddingOptions::default()
+            },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn hashed_lookup_test() {
+        let lookup = HashedLookup::new(10, 42).unwrap();
+        assert_eq!(lookup.len(), 12);
+        assert_eq!(lookup.null().index(), Some(0));
+        assert_eq!(lookup.unknown().index(), Some(1));
+
+        for feature in &["a", "b", "case:nom", "Haus"] {
+            let idx = lookup.lookup(feature).unwrap().index().unwrap();
+            assert!(idx >= 2 && idx < 12);
+            assert_eq!(lookup.lookup(feature).unwrap().index(), Some(idx));
+        }
+
+        // Hashes should be stable, since models store indices.
+        let lookup = HashedLookup::new(1 << 20, 0).unwrap();
+        assert_eq!(lookup.hash(""), 0xa8c7_f832_281a_39c5);
+        assert_eq!(
+            lookup.lookup("").unwrap().index(),
+            Some((0xa8c7_f832_281a_39c5u64 % (1 << 20)) as usize + 2)
+        );
+
+        // The seed changes the hash function.
+        let other = HashedLookup::new(1 << 20, 1).unwrap();
+        assert_ne!(lookup.hash("Haus"), other.hash("Haus"));
+
+        assert!(HashedLookup::new(0, 42).is_err());
+    }
+
+    #[test]
+    fn legacy_lookup_table_test() {
+        let mut numberer = Numberer::new(1);
+        numberer.add("a".to_owned());
+        let data = serde_yaml::to_string(&numberer).unwrap();
+
+        let table: LookupTable = serde_yaml::from_str(&data).unwrap();
+        assert_eq!(table.lookup("a").unwrap().index(), Some(1));
+        assert_eq!(table.count("a"), Some(0));
+        assert_eq!(table.null().index(), Some(0));
         assert_eq!(table.unknown().index(), Some(0));
+        assert_eq!(table.len(), 2);
     }
 }
@@ -11,11 +11,20 @@
 pub mod addr;
 
 mod input_layers;
-pub use self::input_layers::{AddressedValues, InputVector, InputVectorizer, Layer, LayerLookups};
+pub use self::input_layers::{
+    padded_chars, AddressedValues, InputVector, InputVectorizer, Layer, LayerLookups,
+    SentenceOccurrences,
+};
 
 mod lookup;
 pub use self::lookup::{
-    BoxedLookup, Embeddings, Lookup, LookupResult, LookupTable, LookupType, MutableLookupTable,
+    EmbeddingOptions, Embeddings, HashedLookup, Lookup, LookupResult, LookupTable, LookupType,
+    MutableLookupTable, SpecialVector,
+};
+
+mod normalize;
+pub use self::normalize::{
+    Normalization, NormalizedLookup, Normalizer, EMAIL_PLACEHOLDER, URL_PLACEHOLDER,
 };
 
 pub(crate) mod parse_addr;
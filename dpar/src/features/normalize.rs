@@ -0,0 +1,298 @@
+use std::borrow::Cow;
+
+use ndarray::Array2;
+use serde_derive::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::features::{Lookup, LookupResult, LookupType};
+
+/// Placeholder for values that are URLs.
+pub const URL_PLACEHOLDER: &str = "<url>";
+
+/// Placeholder for values that are e-mail addresses.
+pub const EMAIL_PLACEHOLDER: &str = "<email>";
+
+/// Normalization of layer values.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Normalization {
+    /// Convert the value to lowercase.
+    Lowercase,
+
+    /// Replace every ASCII digit by *0*.
+    FoldDigits,
+
+    /// Unicode normalization form KC.
+    Nfkc,
+
+    /// Replace URLs by a placeholder.
+    Url,
+
+    /// Replace e-mail addresses by a placeholder.
+    Email,
+}
+
+impl Normalization {
+    fn apply<'a>(self, value: Cow<'a, str>) -> Cow<'a, str> {
+        use self::Normalization::*;
+
+        match self {
+            Lowercase => {
+                if value.chars().all(is_lowercase_invariant) {
+                    value
+                } else {
+                    Cow::Owned(value.to_lowercase())
+                }
+            }
+            FoldDigits => {
+                if value.chars().any(|c| c.is_ascii_digit()) {
+                    Cow::Owned(
+                        value
+                            .chars()
+                            .map(|c| if c.is_ascii_digit() { '0' } else { c })
+                            .collect(),
+                    )
+                } else {
+                    value
+                }
+            }
+            Nfkc => {
+                let normalized: String = value.nfkc().collect();
+                if normalized == value {
+                    value
+                } else {
+                    Cow::Owned(normalized)
+                }
+            }
+            Url if is_url(&value) => Cow::Borrowed(URL_PLACEHOLDER),
+            Email if is_email(&value) => Cow::Borrowed(EMAIL_PLACEHOLDER),
+            Url | Email => value,
+        }
+    }
+}
+
+fn is_lowercase_invariant(c: char) -> bool {
+    let mut lower = c.to_lowercase();
+    lower.next() == Some(c) && lower.next().is_none()
+}
+
+fn is_url(value: &str) -> bool {
+    let lower = value.to_ascii_lowercase();
+    ["http://", "https://", "ftp://", "www."]
+        .iter()
+        .any(|prefix| lower.starts_with(prefix) && lower.len() > prefix.len())
+}
+
+fn is_email(value: &str) -> bool {
+    if value.chars().any(char::is_whitespace) {
+        return false;
+    }
+
+    let mut parts = value.split('@');
+    let (local, domain) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(local), Some(domain), None) => (local, domain),
+        _ => return false,
+    };
+
+    !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.')
+}
+
+/// Normalizer for layer values.
+///
+/// A normalizer applies a sequence of normalizations to a value, in
+/// the order in which they were specified.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Normalizer(Vec<Normalization>);
+
+impl Normalizer {
+    pub fn new(normalizations: Vec<Normalization>) -> Self {
+        Normalizer(normalizations)
+    }
+
+    /// Returns `true` if the normalizer does not change values.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get the normalizations of the normalizer.
+    pub fn normalizations(&self) -> &[Normalization] {
+        &self.0
+    }
+
+    /// Normalize a value.
+    pub fn normalize<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        self.0
+            .iter()
+            .fold(Cow::Borrowed(value), |value, normalization| {
+                normalization.apply(value)
+            })
+    }
+}
+
+/// Lookup that normalizes features.
+///
+/// This lookup wraps another lookup and normalizes features before
+/// looking them up in the wrapped lookup. Since the same lookup is
+/// used to vectorize parser states while preparing training data,
+/// training, and parsing, values are normalized identically in every
+/// stage.
+pub struct NormalizedLookup {
+    normalizer: Normalizer,
+    inner: Box<Lookup>,
+}
+
+impl NormalizedLookup {
+    pub fn new<L>(normalizer: Normalizer, lookup: L) -> Self
+    where
+        L: Into<Box<Lookup>>,
+    {
+        NormalizedLookup {
+            normalizer,
+            inner: lookup.into(),
+        }
+    }
+
+    /// Get the normalizer of the lookup.
+    pub fn normalizer(&self) -> &Normalizer {
+        &self.normalizer
+    }
+}
+
+impl Lookup for NormalizedLookup {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn lookup(&self, feature: &str) -> Option<LookupResult> {
+        self.inner.lookup(&self.normalizer.normalize(feature))
+    }
+
+    fn lookup_type(&self) -> LookupType {
+        self.inner.lookup_type()
+    }
+
+    fn null(&self) -> LookupResult {
+        self.inner.null()
+    }
+
+    fn unknown(&self) -> LookupResult {
+        self.inner.unknown()
+    }
+
+    fn frequency(&self, index: usize) -> Option<usize> {
+        self.inner.frequency(index)
+    }
+
+    fn add_occurrence(&self, feature: &str) {
+        self.inner
+            .add_occurrence(&self.normalizer.normalize(feature))
+    }
+
+    fn embedding_matrix(&self) -> Option<Array2<f32>> {
+        self.inner.embedding_matrix()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::Normalization::*;
+    use super::{Normalization, NormalizedLookup, Normalizer};
+    use crate::features::{Lookup, LookupTable, MutableLookupTable};
+
+    fn normalize(normalizations: &[Normalization], value: &str) -> String {
+        Normalizer::new(normalizations.to_vec())
+            .normalize(value)
+            .into_owned()
+    }
+
+    #[test]
+    fn lowercase() {
+        assert_eq!(normalize(&[Lowercase], "Haus"), "haus");
+        assert_eq!(normalize(&[Lowercase], "ÄRGER"), "ärger");
+        assert!(match Normalizer::new(vec![Lowercase]).normalize("haus") {
+            Cow::Borrowed(_) => true,
+            Cow::Owned(_) => false,
+        });
+    }
+
+    #[test]
+    fn fold_digits() {
+        assert_eq!(normalize(&[FoldDigits], "1984"), "0000");
+        assert_eq!(normalize(&[FoldDigits], "3,14"), "0,00");
+        assert_eq!(normalize(&[FoldDigits], "Haus"), "Haus");
+
+        // Only ASCII digits are folded.
+        assert_eq!(normalize(&[FoldDigits], "m²"), "m²");
+        assert_eq!(normalize(&[FoldDigits], "½"), "½");
+        assert_eq!(normalize(&[FoldDigits], "٣1"), "٣0");
+    }
+
+    #[test]
+    fn nfkc() {
+        assert_eq!(normalize(&[Nfkc], "ﬁnden"), "finden");
+        assert_eq!(normalize(&[Nfkc], "Ｈａｕｓ"), "Haus");
+        assert_eq!(normalize(&[Nfkc], "A\u{308}rger"), "Ärger");
+    }
+
+    #[test]
+    fn url() {
+        assert_eq!(normalize(&[Url], "https://www.rust-lang.org/"), "<url>");
+        assert_eq!(normalize(&[Url], "WWW.example.com"), "<url>");
+        assert_eq!(normalize(&[Url], "www."), "www.");
+        assert_eq!(normalize(&[Url], "Haus"), "Haus");
+    }
+
+    #[test]
+    fn email() {
+        assert_eq!(normalize(&[Email], "me@example.com"), "<email>");
+        assert_eq!(normalize(&[Email], "@example.com"), "@example.com");
+        assert_eq!(normalize(&[Email], "me@localhost"), "me@localhost");
+        assert_eq!(normalize(&[Email], "a@b@c.de"), "a@b@c.de");
+    }
+
+    #[test]
+    fn normalizations_are_applied_in_order() {
+        assert_eq!(normalize(&[Url, Lowercase], "HTTP://EXAMPLE.COM"), "<url>");
+        assert_eq!(
+            normalize(&[Lowercase, FoldDigits, Nfkc], "Ｆｏｏ１"),
+            "foo0"
+        );
+        assert_eq!(
+            normalize(&[Lowercase, Nfkc, FoldDigits], "Ｆｏｏ１"),
+            "foo0"
+        );
+    }
+
+    #[test]
+    fn normalized_lookup() {
+        let table = MutableLookupTable::new();
+        let lookup = NormalizedLookup::new(
+            Normalizer::new(vec![Lowercase, FoldDigits]),
+            Box::new(table) as Box<Lookup>,
+        );
+        assert_eq!(lookup.lookup("Haus").unwrap().index(), Some(2));
+        assert_eq!(lookup.lookup("haus").unwrap().index(), Some(2));
+        assert_eq!(lookup.lookup("1984").unwrap().index(), Some(3));
+        assert_eq!(lookup.lookup("2019").unwrap().index(), Some(3));
+        lookup.add_occurrence("HAUS");
+        lookup.add_occurrence("1984");
+        assert_eq!(lookup.frequency(2), Some(1));
+        assert_eq!(lookup.len(), 4);
+
+        let table: LookupTable = {
+            let table = MutableLookupTable::new();
+            table.add_occurrence("haus");
+            table.add_occurrence("haus");
+            table.into()
+        };
+        let lookup = NormalizedLookup::new(
+            Normalizer::new(vec![Lowercase]),
+            Box::new(table) as Box<Lookup>,
+        );
+        assert_eq!(lookup.lookup("HAUS").unwrap().index(), Some(2));
+        assert_eq!(lookup.frequency(2), Some(2));
+        assert!(lookup.lookup("Garten").is_none());
+    }
+}
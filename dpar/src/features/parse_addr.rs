@@ -1,14 +1,19 @@
 use crate::features::addr::{AddressedValue, Layer, Source};
 
-use pest::error::Error;
+use pest::error::{Error, ErrorVariant};
 use pest::iterators::{Pair, Pairs};
-use pest::Parser;
+use pest::{Parser, Span};
 use pest_derive::Parser;
 
 #[derive(Parser)]
 #[grammar = "features/addr.pest"]
 pub struct AddressedValueParser;
 
+/// Parse a specification of addressed values.
+///
+/// Source ranges, conjunctions, and groups are expanded into plain
+/// addressed values. Errors provide the line and column of the part
+/// of the specification that could not be processed.
 pub fn parse_addressed_values(data: &str) -> Result<Vec<AddressedValue>, Error<Rule>> {
     let file = AddressedValueParser::parse(Rule::file, &data)?
         .next()
@@ -16,11 +21,10 @@ pub fn parse_addressed_values(data: &str) -> Result<Vec<AddressedValue>, Error<R
 
     let mut addr_values = Vec::new();
 
-    for addr_value_pair in file.into_inner() {
-        match addr_value_pair.as_rule() {
-            Rule::addr_value => {
-                addr_values.push(process_addressed_value(addr_value_pair.into_inner()))
-            }
+    for pair in file.into_inner() {
+        match pair.as_rule() {
+            Rule::addr_value => addr_values.extend(process_addressed_value(pair.into_inner())?),
+            Rule::group => addr_values.extend(process_group(pair.into_inner())?),
             Rule::EOI => (),
             _ => unreachable!(),
         }
@@ -29,70 +33,235 @@ pub fn parse_addressed_values(data: &str) -> Result<Vec<AddressedValue>, Error<R
     Ok(addr_values)
 }
 
-fn process_addressed_value(mut pairs: Pairs<Rule>) -> AddressedValue {
-    let address_pair = pairs
-        .next()
-        .expect("Address components missing")
-        .into_inner();
-    let layer_pair = pairs
-        .next()
-        .expect("Layer missing")
-        .into_inner()
-        .next()
-        .expect("Layer missing");
+/// Extend each prefix with each of the given elements.
+fn cartesian_extend<T>(prefixes: Vec<Vec<T>>, elems: &[T]) -> Vec<Vec<T>>
+where
+    T: Clone,
+{
+    prefixes
+        .into_iter()
+        .flat_map(|prefix| {
+            elems.iter().map(move |elem| {
+                let mut extended = prefix.clone();
+                extended.push(elem.clone());
+                extended
+            })
+        })
+        .collect()
+}
+
+fn custom_error<S>(message: S, span: Span) -> Error<Rule>
+where
+    S: Into<String>,
+{
+    Error::new_from_span(
+        ErrorVariant::CustomError {
+            message: message.into(),
+        },
+        span,
+    )
+}
 
-    let address = process_addresses(address_pair);
-    let layer = process_layer(layer_pair);
+fn process_addressed_value(mut pairs: Pairs<Rule>) -> Result<Vec<AddressedValue>, Error<Rule>> {
+    let value_pair = pairs.next().expect("Value missing");
+    let input = pairs.next().map(process_input_name);
 
-    AddressedValue { address, layer }
+    process_value(value_pair, input)
 }
 
-fn process_address(pair: Pair<Rule>) -> Source {
+fn process_address(pair: Pair<Rule>) -> Result<Vec<Source>, Error<Rule>> {
     let rule = pair.as_rule();
 
-    let source_idx = pair
-        .into_inner()
-        .next()
-        .expect("Missing source index")
-        .as_str()
-        .parse()
-        .expect("Invalid source index");
+    let idx_pair = pair.into_inner().next().expect("Missing source index");
+    let indices = match idx_pair.as_rule() {
+        Rule::source_idx => vec![process_source_idx(idx_pair)?],
+        Rule::source_range => {
+            let span = idx_pair.as_span();
+            let mut range = idx_pair.into_inner();
+            let start = process_source_idx(range.next().expect("Missing range start"))?;
+            let end = process_source_idx(range.next().expect("Missing range end"))?;
 
-    match rule {
-        Rule::stack => Source::Stack(source_idx),
-        Rule::buffer => Source::Buffer(source_idx),
-        Rule::ldep => Source::LDep(source_idx),
-        Rule::rdep => Source::RDep(source_idx),
+            if start >= end {
+                return Err(custom_error("Empty source index range", span));
+            }
+
+            (start..end).collect()
+        }
         _ => unreachable!(),
-    }
+    };
+
+    Ok(indices
+        .into_iter()
+        .map(|idx| match rule {
+            Rule::stack => Source::Stack(idx),
+            Rule::buffer => Source::Buffer(idx),
+            Rule::ldep => Source::LDep(idx),
+            Rule::rdep => Source::RDep(idx),
+            _ => unreachable!(),
+        })
+        .collect())
 }
 
-fn process_addresses(pairs: Pairs<Rule>) -> Vec<Source> {
-    let mut address = Vec::new();
+/// Process the components of an address.
+///
+/// Since a component can be a range of sources, this returns all
+/// addresses that the components expand to.
+fn process_addresses(pairs: Pairs<Rule>) -> Result<Vec<Vec<Source>>, Error<Rule>> {
+    let mut addresses = vec![Vec::new()];
 
     for source in pairs {
         match source.as_rule() {
             Rule::initial_source | Rule::dep_source => {
-                address.push(process_address(
-                    source.into_inner().next().expect("Cannot get component"),
-                ));
+                let sources =
+                    process_address(source.into_inner().next().expect("Cannot get component"))?;
+                addresses = cartesian_extend(addresses, &sources);
             }
             _ => unreachable!(),
         }
     }
 
-    address
+    Ok(addresses)
 }
 
-fn process_layer(pair: Pair<Rule>) -> Layer {
-    match pair.as_rule() {
+/// Process a conjunction.
+///
+/// A conjunction is created for every combination of the expansions
+/// of its conjuncts.
+fn process_conjunction(
+    pair: Pair<Rule>,
+    input: Option<String>,
+) -> Result<Vec<AddressedValue>, Error<Rule>> {
+    let mut conjunctions = vec![Vec::new()];
+
+    for value_pair in pair.into_inner() {
+        let span = value_pair.as_span();
+        let conjuncts = process_single_value(value_pair)?;
+
+        if let Some((_, Layer::Char(_))) = conjuncts.first() {
+            return Err(custom_error(
+                "Characters cannot be part of a conjunction",
+                span,
+            ));
+        }
+
+        let conjuncts: Vec<_> = conjuncts
+            .into_iter()
+            .map(|(address, layer)| AddressedValue {
+                address,
+                layer,
+                input: None,
+            })
+            .collect();
+        conjunctions = cartesian_extend(conjunctions, &conjuncts);
+    }
+
+    Ok(conjunctions
+        .into_iter()
+        .map(|conjuncts| AddressedValue {
+            address: Vec::new(),
+            layer: Layer::Conjunction(conjuncts),
+            input: input.clone(),
+        })
+        .collect())
+}
+
+fn process_group(mut pairs: Pairs<Rule>) -> Result<Vec<AddressedValue>, Error<Rule>> {
+    let group_name_pair = pairs.next().expect("Group name missing");
+    let input = process_input_name(group_name_pair);
+
+    let mut addr_values = Vec::new();
+    for group_value_pair in pairs {
+        let value_pair = group_value_pair.into_inner().next().expect("Value missing");
+        addr_values.extend(process_value(value_pair, Some(input.clone()))?);
+    }
+
+    Ok(addr_values)
+}
+
+fn process_input_name(pair: Pair<Rule>) -> String {
+    pair.into_inner()
+        .next()
+        .expect("Missing input name")
+        .as_str()
+        .to_owned()
+}
+
+fn process_layer(pair: Pair<Rule>) -> Result<Layer, Error<Rule>> {
+    let layer = match pair.as_rule() {
+        Rule::char_layer => {
+            let len_pair = pair
+                .into_inner()
+                .next()
+                .expect("Missing character sequence length");
+            let len = len_pair.as_str().parse().map_err(|_| {
+                custom_error("Invalid character sequence length", len_pair.as_span())
+            })?;
+            Layer::Char(len)
+        }
         Rule::deprel_layer => Layer::DepRel,
         Rule::feature_layer => {
             let name = pair.into_inner().next().expect("Missing feature name");
-            Layer::Feature(name.as_str().to_string())
+            match name.as_rule() {
+                Rule::feature_bundle => Layer::FeatureBundle,
+                Rule::feature_name => Layer::Feature(name.as_str().to_string()),
+                _ => unreachable!(),
+            }
         }
         Rule::tag_layer => Layer::Tag,
         Rule::token_layer => Layer::Token,
         _ => unreachable!(),
+    };
+
+    Ok(layer)
+}
+
+/// Process an address and layer.
+///
+/// Returns the layer with every address that the address expands to.
+fn process_single_value(pair: Pair<Rule>) -> Result<Vec<(Vec<Source>, Layer)>, Error<Rule>> {
+    let mut pairs = pair.into_inner();
+
+    let address_pair = pairs
+        .next()
+        .expect("Address components missing")
+        .into_inner();
+    let layer_pair = pairs
+        .next()
+        .expect("Layer missing")
+        .into_inner()
+        .next()
+        .expect("Layer missing");
+
+    let addresses = process_addresses(address_pair)?;
+    let layer = process_layer(layer_pair)?;
+
+    Ok(addresses
+        .into_iter()
+        .map(|address| (address, layer.clone()))
+        .collect())
+}
+
+fn process_source_idx(pair: Pair<Rule>) -> Result<usize, Error<Rule>> {
+    pair.as_str()
+        .parse()
+        .map_err(|_| custom_error("Invalid source index", pair.as_span()))
+}
+
+/// Process a value or conjunction.
+fn process_value(
+    pair: Pair<Rule>,
+    input: Option<String>,
+) -> Result<Vec<AddressedValue>, Error<Rule>> {
+    match pair.as_rule() {
+        Rule::value => Ok(process_single_value(pair)?
+            .into_iter()
+            .map(|(address, layer)| AddressedValue {
+                address,
+                layer,
+                input: input.clone(),
+            })
+            .collect()),
+        Rule::conjunction => process_conjunction(pair, input),
+        _ => unreachable!(),
     }
 }
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use lazy_static::*;
 use maplit::hashmap;
+use pest::error::LineColLocation;
 
 use crate::features::addr::{AddressedValue, Layer, Source};
 use crate::features::parse_addr::parse_addressed_values;
@@ -13,34 +14,142 @@ static CORRECT_STRING4: &'static str = "[STACK 0, LDEP 0] DEPREL [STACK 0, RDEP
 static CORRECT_STRING5: &'static str = "[STACK 0] FEATURE num";
 static CORRECT_STRING6: &'static str =
     "[STACK 0,\n  LDEP 0]\n  DEPREL\n  [STACK 0,\n RDEP 0] DEPREL";
+static CORRECT_STRING7: &'static str = "[BUFFER 0] CHARS 10";
+static CORRECT_STRING8: &'static str = "[STACK 0] TAG AS coarse_tags [STACK 1] TAG";
+static CORRECT_STRING9: &'static str = "[STACK 0] FEATURE *";
+static CORRECT_STRING10: &'static str = "# Tokens.\n[STACK 0] TOKEN # First token on the stack.\n";
+static CORRECT_STRING11: &'static str = "[STACK 0..2] TAG";
+static CORRECT_STRING12: &'static str = "[STACK 0, LDEP 0..2] DEPREL";
+static CORRECT_STRING13: &'static str =
+    "GROUP coarse_tags {\n  # Stack and buffer.\n  [STACK 0] TAG\n  [BUFFER 0] TAG\n}";
+static CORRECT_STRING14: &'static str = "[STACK 0] TAG + [BUFFER 0] TAG AS tag_pairs";
+static CORRECT_STRING15: &'static str = "[STACK 0..2] TAG + [BUFFER 0] TAG";
 
 lazy_static! {
     static ref CORRECT1: Vec<AddressedValue> = vec![AddressedValue {
         address: vec![Source::Stack(0)],
         layer: Layer::Token,
+        input: None,
     }];
     static ref CORRECT2: Vec<AddressedValue> = vec![AddressedValue {
         address: vec![Source::Buffer(1)],
         layer: Layer::Tag,
+        input: None,
     }];
     static ref CORRECT3: Vec<AddressedValue> = vec![AddressedValue {
         address: vec![Source::Stack(0), Source::LDep(0)],
         layer: Layer::DepRel,
+        input: None,
     }];
     static ref CORRECT4: Vec<AddressedValue> = vec![
         AddressedValue {
             address: vec![Source::Stack(0), Source::LDep(0)],
             layer: Layer::DepRel,
+            input: None,
         },
         AddressedValue {
             address: vec![Source::Stack(0), Source::RDep(0)],
             layer: Layer::DepRel,
+            input: None,
         },
     ];
     static ref CORRECT5: Vec<AddressedValue> = vec![AddressedValue {
         address: vec![Source::Stack(0)],
         layer: Layer::Feature("num".to_owned()),
+        input: None,
     }];
+    static ref CORRECT7: Vec<AddressedValue> = vec![AddressedValue {
+        address: vec![Source::Buffer(0)],
+        layer: Layer::Char(10),
+        input: None,
+    }];
+    static ref CORRECT8: Vec<AddressedValue> = vec![
+        AddressedValue {
+            address: vec![Source::Stack(0)],
+            layer: Layer::Tag,
+            input: Some("coarse_tags".to_owned()),
+        },
+        AddressedValue {
+            address: vec![Source::Stack(1)],
+            layer: Layer::Tag,
+            input: None,
+        },
+    ];
+    static ref CORRECT9: Vec<AddressedValue> = vec![AddressedValue {
+        address: vec![Source::Stack(0)],
+        layer: Layer::FeatureBundle,
+        input: None,
+    }];
+    static ref CORRECT11: Vec<AddressedValue> = vec![
+        AddressedValue {
+            address: vec![Source::Stack(0)],
+            layer: Layer::Tag,
+            input: None,
+        },
+        AddressedValue {
+            address: vec![Source::Stack(1)],
+            layer: Layer::Tag,
+            input: None,
+        },
+    ];
+    static ref CORRECT12: Vec<AddressedValue> = vec![
+        AddressedValue {
+            address: vec![Source::Stack(0), Source::LDep(0)],
+            layer: Layer::DepRel,
+            input: None,
+        },
+        AddressedValue {
+            address: vec![Source::Stack(0), Source::LDep(1)],
+            layer: Layer::DepRel,
+            input: None,
+        },
+    ];
+    static ref CORRECT13: Vec<AddressedValue> = vec![
+        AddressedValue {
+            address: vec![Source::Stack(0)],
+            layer: Layer::Tag,
+            input: Some("coarse_tags".to_owned()),
+        },
+        AddressedValue {
+            address: vec![Source::Buffer(0)],
+            layer: Layer::Tag,
+            input: Some("coarse_tags".to_owned()),
+        },
+    ];
+    static ref CORRECT14: Vec<AddressedValue> = vec![AddressedValue {
+        address: vec![],
+        layer: Layer::Conjunction(vec![
+            AddressedValue {
+                address: vec![Source::Stack(0)],
+                layer: Layer::Tag,
+                input: None,
+            },
+            AddressedValue {
+                address: vec![Source::Buffer(0)],
+                layer: Layer::Tag,
+                input: None,
+            },
+        ]),
+        input: Some("tag_pairs".to_owned()),
+    }];
+    static ref CORRECT15: Vec<AddressedValue> = (0..2)
+        .map(|idx| AddressedValue {
+            address: vec![],
+            layer: Layer::Conjunction(vec![
+                AddressedValue {
+                    address: vec![Source::Stack(idx)],
+                    layer: Layer::Tag,
+                    input: None,
+                },
+                AddressedValue {
+                    address: vec![Source::Buffer(0)],
+                    layer: Layer::Tag,
+                    input: None,
+                },
+            ]),
+            input: None,
+        })
+        .collect();
     static ref CORRECT_CASES: HashMap<&'static str, Vec<AddressedValue>> = hashmap! {
         CORRECT_STRING1 => CORRECT1.clone(),
         CORRECT_STRING2 => CORRECT2.clone(),
@@ -48,6 +157,15 @@ lazy_static! {
         CORRECT_STRING4 => CORRECT4.clone(),
         CORRECT_STRING5 => CORRECT5.clone(),
         CORRECT_STRING6 => CORRECT4.clone(),
+        CORRECT_STRING7 => CORRECT7.clone(),
+        CORRECT_STRING8 => CORRECT8.clone(),
+        CORRECT_STRING9 => CORRECT9.clone(),
+        CORRECT_STRING10 => CORRECT1.clone(),
+        CORRECT_STRING11 => CORRECT11.clone(),
+        CORRECT_STRING12 => CORRECT12.clone(),
+        CORRECT_STRING13 => CORRECT13.clone(),
+        CORRECT_STRING14 => CORRECT14.clone(),
+        CORRECT_STRING15 => CORRECT15.clone(),
     };
     static ref INCORRECT_CASES: Vec<&'static str> = vec![
         "[] TOKEN",
@@ -64,6 +182,9 @@ lazy_static! {
         "[LDEP 0] TOKEN",
         "[RDEP 0] TOKEN",
         "[STACK 0] TOKEN num",
+        "[STACK 0] TOKEN AS",
+        "[STACK 0] TOKEN AS lower-tokens",
+        "[STACK 0] AS tokens",
         "[STACK 0] TAG num",
         "[STACK 0] DEPREL num",
         "[STACK 0, STACK 0] TOKEN",
@@ -72,6 +193,25 @@ lazy_static! {
         "[STACK 0, LDEP 0, BUFFER 0] TOKEN",
         "[STACK\n0, LDEP 0] DEPREL",
         "[STACK 0, LDEP 0] FEATURE\ntf",
+        "[STACK 0] CHARS",
+        "[STACK 0] CHARS\n10",
+        "[STACK 0] FEATURE",
+        "[STACK 0] FEATURE **",
+        "[STACK 0] FEATURE *num",
+        "[STACK 0] TOKEN # Comment\n TAG",
+        "[STACK 2..2] TAG",
+        "[STACK 2..1] TAG",
+        "[STACK 0..] TAG",
+        "[STACK ..2] TAG",
+        "[STACK 99999999999999999999999] TAG",
+        "[STACK 0] CHARS 3 + [BUFFER 0] TAG",
+        "[STACK 0] TAG +",
+        "[STACK 0] TAG + AS tag_pairs",
+        "GROUP { [STACK 0] TAG }",
+        "GROUP tags [STACK 0] TAG",
+        "GROUP tags { [STACK 0] TAG AS coarse_tags }",
+        "GROUP tags { GROUP inner { [STACK 0] TAG } }",
+        "GROUP tags { [STACK 0] TAG } AS coarse_tags",
     ];
 }
 
@@ -89,3 +229,25 @@ fn incorrect_cases() {
         assert!(parse_addressed_values(&data).is_err());
     }
 }
+
+#[test]
+fn error_line_col() {
+    let err = parse_addressed_values("[STACK 0] TAG\n[STACK 0] CANDY").unwrap_err();
+    assert_eq!(err.line_col, LineColLocation::Pos((2, 11)));
+
+    let err = parse_addressed_values("[STACK 0] TAG\n[STACK 3..1] TAG").unwrap_err();
+    assert_eq!(err.line_col, LineColLocation::Span((2, 8), (2, 12)));
+
+    let err =
+        parse_addressed_values("# Characters\n\n[STACK 0] TAG + [BUFFER 0] CHARS 3").unwrap_err();
+    assert_eq!(err.line_col, LineColLocation::Span((3, 17), (3, 35)));
+}
+
+#[test]
+fn display_roundtrip() {
+    for correct in CORRECT_CASES.values() {
+        let data: Vec<_> = correct.iter().map(ToString::to_string).collect();
+        let result = parse_addressed_values(&data.join("\n")).unwrap();
+        assert_eq!(*correct, result);
+    }
+}
@@ -5,6 +5,8 @@
 //! `BatchGuide`, and provides an implementation using Tensorflow in the
 //! `Tensorflow` submodule.
 
+use failure::Error;
+
 use crate::system::{ParserState, Transition};
 
 /// Guide for parsers without batch processing
@@ -13,7 +15,10 @@ pub trait Guide {
 
     /// Returns the best (permissible) transition given the current parser
     /// state.
-    fn best_transition(&mut self, state: &ParserState) -> Self::Transition;
+    ///
+    /// If the parser state has constraints, the transition must satisfy
+    /// them (see `Transition::satisfies_constraints`).
+    fn best_transition(&mut self, state: &ParserState) -> Result<Self::Transition, Error>;
 }
 
 /// Guide for parsers with batch processing
@@ -24,5 +29,20 @@ pub trait BatchGuide {
     /// states. The transitions are returned in the same order as the
     /// parser states. So, the *0th* transition is the best transition
     /// for the *0th* parser state.
-    fn best_transitions(&mut self, states: &[&ParserState]) -> Vec<Self::Transition>;
+    ///
+    /// If a parser state has constraints, its transition must satisfy
+    /// them (see `Transition::satisfies_constraints`).
+    fn best_transitions(&mut self, states: &[&ParserState])
+        -> Result<Vec<Self::Transition>, Error>;
+}
+
+/// Batch guide that also scores transitions
+pub trait ScoredBatchGuide: BatchGuide {
+    /// Returns the best (permissible) transitions for a slice of parser
+    /// states, together with their probabilities. The transitions are
+    /// returned in the same order as the parser states.
+    fn best_scored_transitions(
+        &mut self,
+        states: &[&ParserState],
+    ) -> Result<Vec<(Self::Transition, f32)>, Error>;
 }
@@ -16,4 +16,6 @@ pub mod systems;
 
 pub mod parser;
 
+pub mod pproj;
+
 pub mod train;
@@ -1,7 +1,6 @@
-use enum_map::EnumMap;
 use tensorflow::Tensor;
 
-use crate::features::{InputVectorizer, Layer};
+use crate::features::InputVectorizer;
 use crate::models::tensorflow::{CopyBatches, InstanceSlices, LayerTensors, TensorWrap};
 use crate::system::ParserState;
 use crate::system::TransitionSystem;
@@ -93,14 +92,16 @@ impl<'a, T> TensorCollector<'a, T> {
     ///
     /// Each tensor has shape `[batch_size, layer_size]`.
     fn new_layer_tensors(&self, batch_size: usize) -> LayerTensors<i32> {
-        let layer_sizes = self.vectorizer.lookup_layer_sizes();
-
-        let mut layers: EnumMap<Layer, TensorWrap<i32>> = EnumMap::new();
-        for (layer, tensor) in &mut layers {
-            *tensor = TensorWrap(Tensor::new(&[batch_size as u64, layer_sizes[layer] as u64]));
-        }
-
-        layers
+        self.vectorizer
+            .lookup_layer_sizes()
+            .into_iter()
+            .map(|(layer, size)| {
+                (
+                    layer,
+                    TensorWrap(Tensor::new(&[batch_size as u64, size as u64])),
+                )
+            })
+            .collect()
     }
 }
 
@@ -149,7 +150,7 @@ pub struct TensorCollectorParts {
 
 #[cfg(test)]
 mod tests {
-    use conllx::Token;
+    use conllx::{Features, Token, TokenBuilder};
 
     use crate::features::addr::{AddressedValue, Layer, Source};
     use crate::features::{
@@ -194,13 +195,13 @@ mod tests {
 
         // Check batch shapes.
         assert_eq!(parts.labels[0].dims(), &[2]);
-        assert_eq!(parts.inputs[0][features::Layer::Token].dims(), &[2, 2]);
+        assert_eq!(parts.inputs[0][&features::Layer::TOKEN].dims(), &[2, 2]);
 
         // Check batch contents.
         assert_eq!(&*parts.labels[0], &[1, 2]);
         assert_eq!(
-            parts.inputs[0][features::Layer::Token].as_ref(),
-            &[1, 2, 2, 3]
+            parts.inputs[0][&features::Layer::TOKEN].as_ref(),
+            &[2, 3, 3, 4]
         );
     }
 
@@ -235,35 +236,185 @@ mod tests {
 
         // Check batch shapes.
         assert_eq!(parts.labels[0].dims(), &[2]);
-        assert_eq!(parts.inputs[0][features::Layer::Token].dims(), &[2, 2]);
+        assert_eq!(parts.inputs[0][&features::Layer::TOKEN].dims(), &[2, 2]);
         assert_eq!(parts.labels[1].dims(), &[1]);
-        assert_eq!(parts.inputs[1][features::Layer::Token].dims(), &[1, 2]);
+        assert_eq!(parts.inputs[1][&features::Layer::TOKEN].dims(), &[1, 2]);
 
         // Check batch contents.
         assert_eq!(&*parts.labels[0], &[1, 1]);
         assert_eq!(
-            parts.inputs[0][features::Layer::Token].as_ref(),
-            &[1, 2, 2, 3]
+            parts.inputs[0][&features::Layer::TOKEN].as_ref(),
+            &[2, 3, 3, 4]
         );
         assert_eq!(&*parts.labels[1], &[2]);
-        assert_eq!(parts.inputs[1][features::Layer::Token].as_ref(), &[3, 4]);
+        assert_eq!(parts.inputs[1][&features::Layer::TOKEN].as_ref(), &[4, 5]);
+    }
+
+    #[test]
+    fn collect_chars() {
+        let sent = vec![Token::new("een"), Token::new("tests")];
+        let mut state = ParserState::new(&sent);
+
+        let vectorizer = test_char_vectorizer();
+        let mut collector = test_collector(&vectorizer);
+        collector
+            .collect(&StackProjectiveTransition::Shift, &state)
+            .unwrap();
+        StackProjectiveTransition::Shift.apply(&mut state);
+        collector
+            .collect(&StackProjectiveTransition::LeftArc("FOO".into()), &state)
+            .unwrap();
+        let parts = collector.into_parts();
+
+        // Check batch shapes.
+        assert_eq!(parts.inputs[0][&features::Layer::CHAR].dims(), &[2, 8]);
+
+        // Short tokens are padded with the null index, long tokens
+        // are truncated.
+        assert_eq!(
+            parts.inputs[0][&features::Layer::CHAR].as_ref(),
+            &[2, 3, 3, 4, 5, 5, 6, 0, 5, 5, 6, 0, 7, 5, 8, 7]
+        );
+    }
+
+    #[test]
+    fn collect_named_input() {
+        let sent = vec![Token::new("een"), Token::new("test")];
+        let state = ParserState::new(&sent);
+
+        let vectorizer = test_named_vectorizer();
+        let mut collector = test_collector(&vectorizer);
+        collector
+            .collect(&StackProjectiveTransition::Shift, &state)
+            .unwrap();
+        let parts = collector.into_parts();
+
+        // The named input layer gets its own tensor and lookup.
+        let next = features::Layer::new("next_tokens");
+        assert_eq!(parts.inputs[0][&features::Layer::TOKEN].dims(), &[1, 1]);
+        assert_eq!(parts.inputs[0][&next].dims(), &[1, 2]);
+        assert_eq!(parts.inputs[0][&features::Layer::TOKEN].as_ref(), &[2]);
+        assert_eq!(parts.inputs[0][&next].as_ref(), &[2, 3]);
+    }
+
+    #[test]
+    fn collect_feature_inputs() {
+        let sent = vec![
+            TokenBuilder::new("een")
+                .features(Features::from_string("case:nom|num:sg"))
+                .token(),
+            TokenBuilder::new("test")
+                .features(Features::from_string("case:acc"))
+                .token(),
+        ];
+        let state = ParserState::new(&sent);
+
+        let vectorizer = test_feature_vectorizer();
+        let mut collector = test_collector(&vectorizer);
+        collector
+            .collect(&StackProjectiveTransition::Shift, &state)
+            .unwrap();
+        let parts = collector.into_parts();
+
+        // Attributes and bundles with a lookup get their own input layer,
+        // other attributes share the feature layer.
+        let case = features::Layer::feature("case");
+        assert_eq!(parts.inputs[0][&case].as_ref(), &[2, 3]);
+        assert_eq!(parts.inputs[0][&features::Layer::FEATURE].as_ref(), &[2, 0]);
+        assert_eq!(
+            parts.inputs[0][&features::Layer::FEATURE_BUNDLE].as_ref(),
+            &[2]
+        );
+    }
+
+    fn test_feature_vectorizer() -> InputVectorizer {
+        let buffer = |idx, layer| AddressedValue {
+            address: vec![Source::Buffer(idx)],
+            layer,
+            input: None,
+        };
+
+        let addrs = vec![
+            buffer(0, Layer::Feature("case".to_owned())),
+            buffer(1, Layer::Feature("case".to_owned())),
+            buffer(0, Layer::Feature("num".to_owned())),
+            buffer(1, Layer::Feature("num".to_owned())),
+            buffer(0, Layer::FeatureBundle),
+        ];
+
+        let mut lookups = LayerLookups::new();
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(features::Layer::FEATURE, table);
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(features::Layer::feature("case"), table);
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(features::Layer::FEATURE_BUNDLE, table);
+        InputVectorizer::new(lookups, AddressedValues(addrs)).unwrap()
+    }
+
+    fn test_named_vectorizer() -> InputVectorizer {
+        let addrs = vec![
+            AddressedValue {
+                address: vec![Source::Stack(0)],
+                layer: Layer::Token,
+                input: None,
+            },
+            AddressedValue {
+                address: vec![Source::Buffer(0)],
+                layer: Layer::Token,
+                input: Some("next_tokens".to_owned()),
+            },
+            AddressedValue {
+                address: vec![Source::Buffer(1)],
+                layer: Layer::Token,
+                input: Some("next_tokens".to_owned()),
+            },
+        ];
+
+        let mut lookups = LayerLookups::new();
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(features::Layer::TOKEN, table);
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(features::Layer::new("next_tokens"), table);
+        InputVectorizer::new(lookups, AddressedValues(addrs)).unwrap()
+    }
+
+    fn test_char_vectorizer() -> InputVectorizer {
+        let stack0 = AddressedValue {
+            address: vec![Source::Stack(0)],
+            layer: Layer::Char(4),
+            input: None,
+        };
+
+        let buffer0 = AddressedValue {
+            address: vec![Source::Buffer(0)],
+            layer: Layer::Char(4),
+            input: None,
+        };
+
+        let mut lookups = LayerLookups::new();
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(features::Layer::CHAR, table);
+        InputVectorizer::new(lookups, AddressedValues(vec![stack0, buffer0])).unwrap()
     }
 
     fn test_vectorizer() -> InputVectorizer {
         let stack0 = AddressedValue {
             address: vec![Source::Stack(0)],
             layer: Layer::Token,
+            input: None,
         };
 
         let buffer0 = AddressedValue {
             address: vec![Source::Buffer(0)],
             layer: Layer::Token,
+            input: None,
         };
 
         let mut lookups = LayerLookups::new();
         let table: Box<Lookup> = Box::new(MutableLookupTable::new());
-        lookups.insert(features::Layer::Token, table);
-        InputVectorizer::new(lookups, AddressedValues(vec![stack0, buffer0]))
+        lookups.insert(features::Layer::TOKEN, table);
+        InputVectorizer::new(lookups, AddressedValues(vec![stack0, buffer0])).unwrap()
     }
 
     fn test_collector(vectorizer: &InputVectorizer) -> TensorCollector<StackProjectiveSystem> {
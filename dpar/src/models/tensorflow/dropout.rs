@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+
+use failure::{format_err, Error};
+use rand::Rng;
+
+use crate::features::{Layer, Lookup};
+use crate::models::tensorflow::{CopyBatches, LayerTensors};
+
+/// Frequency-based dropout of lookup indices.
+///
+/// Since training data is vectorized once, a model would never see the
+/// index of unknown values. Index dropout replaces the indices of
+/// infrequent values by the unknown index while batches are fed during
+/// training. The index of a value with frequency *f* is replaced with
+/// probability *α / (α + f)*, where *f* is the number of occurrences of
+/// the value in the training data (see `Lookup::add_occurrence`), as in:
+///
+/// Eliyahu Kiperwasser and Yoav Goldberg, Simple and accurate dependency
+/// parsing using bidirectional LSTM feature representations, 2016
+#[derive(Default)]
+pub struct IndexDropout {
+    layers: BTreeMap<Layer, LayerDropout>,
+}
+
+struct LayerDropout {
+    probs: Vec<f32>,
+    unknown: i32,
+}
+
+impl IndexDropout {
+    /// Construct index dropout without any layers.
+    pub fn new() -> Self {
+        IndexDropout::default()
+    }
+
+    /// Apply dropout to a layer with the given α.
+    ///
+    /// The lookup of the layer must be a table that stores frequencies.
+    pub fn add_layer(&mut self, layer: Layer, lookup: &Lookup, alpha: f32) -> Result<(), Error> {
+        let null = lookup.null().index();
+        let unknown = lookup.unknown().index().ok_or_else(|| {
+            format_err!("Dropout requires a lookup table for the {} layer", layer)
+        })?;
+
+        let mut has_frequencies = false;
+        let probs = (0..lookup.len())
+            .map(|idx| {
+                if Some(idx) == null || idx == unknown {
+                    return 0.;
+                }
+
+                match lookup.frequency(idx) {
+                    Some(freq) => {
+                        has_frequencies = true;
+                        alpha / (alpha + freq as f32)
+                    }
+                    None => 0.,
+                }
+            })
+            .collect();
+
+        if !has_frequencies {
+            return Err(format_err!(
+                "Dropout requires frequencies in the lookup table for the {} layer",
+                layer
+            ));
+        }
+
+        self.layers.insert(
+            layer,
+            LayerDropout {
+                probs,
+                unknown: unknown as i32,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Returns `true` if dropout is not applied to any layer.
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Apply dropout to the input tensors of a batch.
+    ///
+    /// Returns a copy of the input tensors, in which indices are replaced
+    /// by the unknown index.
+    pub fn apply<R>(&self, inputs: &LayerTensors<i32>, rng: &mut R) -> LayerTensors<i32>
+    where
+        R: Rng,
+    {
+        let batch_size = inputs.values().next().map_or(0, |tensor| tensor.dims()[0]);
+        let mut inputs = inputs.copy_batches(batch_size);
+
+        for (layer, dropout) in &self.layers {
+            let tensor = ok_or!(inputs.get_mut(layer), continue);
+
+            for idx in tensor.iter_mut() {
+                let prob = dropout.probs.get(*idx as usize).cloned().unwrap_or(0.);
+                if prob > 0. && rng.gen::<f32>() < prob {
+                    *idx = dropout.unknown;
+                }
+            }
+        }
+
+        inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use conllx::TokenBuilder;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use tensorflow::Tensor;
+
+    use super::IndexDropout;
+    use crate::features::addr::{self, AddressedValue, Source};
+    use crate::features::{
+        AddressedValues, InputVectorizer, Layer, LayerLookups, Lookup, LookupTable,
+        MutableLookupTable,
+    };
+    use crate::models::tensorflow::{LayerTensors, TensorWrap};
+    use crate::system::{sentence_to_dependencies, ParserState};
+    use crate::systems::StackProjectiveSystem;
+    use crate::train::{GreedyTrainer, NoopCollector};
+
+    fn test_table() -> LookupTable {
+        let table = MutableLookupTable::new();
+        table.add_occurrence("rare");
+        for _ in 0..1000 {
+            table.add_occurrence("frequent");
+        }
+
+        table.into()
+    }
+
+    fn test_inputs() -> LayerTensors<i32> {
+        let mut inputs = LayerTensors::new();
+        inputs.insert(Layer::TAG, TensorWrap(Tensor::new(&[100, 0])));
+
+        let mut tokens = Tensor::new(&[100, 4]);
+        for instance in tokens.chunks_mut(4) {
+            instance.copy_from_slice(&[0, 1, 2, 3]);
+        }
+        inputs.insert(Layer::TOKEN, TensorWrap(tokens));
+
+        inputs
+    }
+
+    #[test]
+    fn index_dropout() {
+        let table = test_table();
+        let mut dropout = IndexDropout::new();
+        assert!(dropout.is_empty());
+        dropout.add_layer(Layer::TOKEN, &table, 1.).unwrap();
+        assert!(!dropout.is_empty());
+
+        let inputs = test_inputs();
+        let mut rng = StdRng::seed_from_u64(42);
+        let dropped = dropout.apply(&inputs, &mut rng);
+
+        let mut n_rare_dropped = 0;
+        let mut n_frequent_dropped = 0;
+        for instance in dropped[&Layer::TOKEN].chunks(4) {
+            // Null and unknown indices are never replaced.
+            assert_eq!(&instance[..2], &[0, 1]);
+
+            if instance[2] == 1 {
+                n_rare_dropped += 1;
+            }
+
+            if instance[3] == 1 {
+                n_frequent_dropped += 1;
+            }
+        }
+
+        // The rare feature is dropped with probability 0.5, the frequent
+        // feature with probability ~0.001.
+        assert!(n_rare_dropped > 25 && n_rare_dropped < 75);
+        assert!(n_frequent_dropped < 5);
+
+        // The original inputs are unchanged.
+        assert!(inputs[&Layer::TOKEN]
+            .chunks(4)
+            .all(|instance| instance == [0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn index_dropout_uses_corpus_frequencies() {
+        let value = |source| AddressedValue {
+            address: vec![source],
+            layer: addr::Layer::Token,
+            input: None,
+        };
+
+        let mut lookups = LayerLookups::new();
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(Layer::TOKEN, table);
+        let vectorizer = InputVectorizer::new(
+            lookups,
+            AddressedValues(vec![value(Source::Stack(0)), value(Source::Buffer(0))]),
+        )
+        .unwrap();
+
+        let sent = vec![
+            TokenBuilder::new("a").head(2).head_rel("x").token(),
+            TokenBuilder::new("b").head(0).head_rel("root").token(),
+            TokenBuilder::new("a").head(2).head_rel("y").token(),
+        ];
+        let dependencies = sentence_to_dependencies(&sent).unwrap();
+        let collector = NoopCollector::new(StackProjectiveSystem::new(), vectorizer).unwrap();
+        let mut trainer = GreedyTrainer::new(collector);
+        trainer
+            .parse_state(&dependencies, &mut ParserState::new(&sent))
+            .unwrap();
+
+        let table = trainer
+            .collector()
+            .input_vectorizer()
+            .layer_lookups()
+            .layer_lookup(&Layer::TOKEN)
+            .unwrap();
+        let mut dropout = IndexDropout::new();
+        dropout.add_layer(Layer::TOKEN, table, 1.).unwrap();
+
+        // Tokens are looked up in several parser states, but the
+        // probabilities are based on their frequencies in the sentence.
+        let probs = &dropout.layers[&Layer::TOKEN].probs;
+        let prob = |token: &str| probs[table.lookup(token).unwrap().index().unwrap()];
+        assert_eq!(prob("ROOT"), 0.5);
+        assert_eq!(prob("a"), 1. / 3.);
+        assert_eq!(prob("b"), 0.5);
+    }
+
+    #[test]
+    fn index_dropout_requires_frequencies() {
+        let mut dropout = IndexDropout::new();
+        assert!(dropout
+            .add_layer(Layer::TOKEN, &MutableLookupTable::new(), 1.)
+            .is_err());
+    }
+}
@@ -1,6 +1,7 @@
+use failure::Error;
 use tensorflow::Tensor;
 
-use crate::guide::{BatchGuide, Guide};
+use crate::guide::{BatchGuide, Guide, ScoredBatchGuide};
 use crate::models::tensorflow::{InstanceSlices, LayerTensors, TensorflowModel};
 use crate::system::{ParserState, TransitionSystem};
 
@@ -10,8 +11,8 @@ where
 {
     type Transition = T::Transition;
 
-    fn best_transition(&mut self, state: &ParserState) -> Self::Transition {
-        self.best_transitions(&[state]).remove(0)
+    fn best_transition(&mut self, state: &ParserState) -> Result<Self::Transition, Error> {
+        Ok(self.best_transitions(&[state])?.remove(0))
     }
 }
 
@@ -21,30 +22,65 @@ where
 {
     type Transition = T::Transition;
 
-    fn best_transitions(&mut self, states: &[&ParserState]) -> Vec<Self::Transition> {
+    fn best_transitions(
+        &mut self,
+        states: &[&ParserState],
+    ) -> Result<Vec<Self::Transition>, Error> {
         if states.is_empty() {
-            return Vec::new();
+            return Ok(Vec::new());
         }
 
-        // Allocate batch tensors.
-        let embed_size = self.vectorizer().embedding_layer_size();
-        let mut embed_tensors = Tensor::new(&[states.len() as u64, embed_size as u64]);
+        let (embed_tensors, input_tensors) = batch_tensors(self, states);
+        self.predict(states, &embed_tensors, &input_tensors)
+    }
+}
 
-        let mut input_tensors = LayerTensors::new();
-        for (layer, size) in self.vectorizer().lookup_layer_sizes() {
-            input_tensors[layer] = Tensor::new(&[states.len() as u64, size as u64]).into();
+impl<T> ScoredBatchGuide for TensorflowModel<T>
+where
+    T: TransitionSystem,
+{
+    fn best_scored_transitions(
+        &mut self,
+        states: &[&ParserState],
+    ) -> Result<Vec<(Self::Transition, f32)>, Error> {
+        if states.is_empty() {
+            return Ok(Vec::new());
         }
 
-        // Fill tensors.
-        for (idx, state) in states.iter().enumerate() {
-            let embed_offset = embed_size * idx;
-            self.vectorizer().realize_into(
-                state,
-                &mut embed_tensors[embed_offset..embed_offset + embed_size],
-                &mut input_tensors.to_instance_slices(idx),
-            );
-        }
+        let (embed_tensors, input_tensors) = batch_tensors(self, states);
+        self.predict_scored(states, &embed_tensors, &input_tensors)
+    }
+}
 
-        self.predict(states, &embed_tensors, &input_tensors)
+/// Vectorize a batch of parser states.
+fn batch_tensors<T>(
+    model: &TensorflowModel<T>,
+    states: &[&ParserState],
+) -> (Tensor<f32>, LayerTensors<i32>)
+where
+    T: TransitionSystem,
+{
+    // Allocate batch tensors.
+    let embed_size = model.vectorizer().embedding_layer_size();
+    let mut embed_tensors = Tensor::new(&[states.len() as u64, embed_size as u64]);
+
+    let mut input_tensors = LayerTensors::new();
+    for (layer, size) in model.vectorizer().lookup_layer_sizes() {
+        input_tensors.insert(
+            layer,
+            Tensor::new(&[states.len() as u64, size as u64]).into(),
+        );
+    }
+
+    // Fill tensors.
+    for (idx, state) in states.iter().enumerate() {
+        let embed_offset = embed_size * idx;
+        model.vectorizer().realize_into(
+            state,
+            &mut embed_tensors[embed_offset..embed_offset + embed_size],
+            &mut input_tensors.to_instance_slices(idx),
+        );
     }
+
+    (embed_tensors, input_tensors)
 }
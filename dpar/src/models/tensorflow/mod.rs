@@ -3,6 +3,9 @@
 mod collector;
 pub use self::collector::*;
 
+mod dropout;
+pub use self::dropout::IndexDropout;
+
 mod guide;
 pub use self::guide::*;
 
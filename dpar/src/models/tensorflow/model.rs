@@ -1,14 +1,16 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::f32;
 use std::path::Path;
 
-use enum_map::EnumMap;
 use failure::{err_msg, format_err, Error};
+use ndarray::Array2;
 use tensorflow::{
-    Graph, ImportGraphDefOptions, Operation, Session, SessionOptions, SessionRunArgs, Status,
-    Tensor,
+    Graph, ImportGraphDefOptions, Operation, Output, Session, SessionOptions, SessionRunArgs,
+    Status, Tensor,
 };
 
-use crate::features::{InputVectorizer, Layer};
+use crate::features::{InputVectorizer, Layer, Lookup};
 use crate::models::tensorflow::LayerTensors;
 use crate::models::ModelPerformance;
 use crate::system::{ParserState, Transition, TransitionSystem};
@@ -41,6 +43,12 @@ mod opnames {
 
     /// Training.
     pub static TRAIN: &str = "model/train";
+
+    /// Suffixes of the ops that initialize a fine-tuned embedding matrix.
+    /// The initial matrix is fed to the placeholder `<embeds>_init` and
+    /// assigned by running `<embeds>_assign`.
+    pub static EMBEDS_INIT_SUFFIX: &str = "_init";
+    pub static EMBEDS_ASSIGN_SUFFIX: &str = "_assign";
 }
 
 /// Layer op in the parsing model
@@ -67,8 +75,14 @@ where
 }
 
 /// A bundling of ops for the input layers.
+///
+/// Besides the placeholder op of a layer, the embedding matrix op can be
+/// set for layers with fine-tuned pretrained embeddings.
 #[derive(Default)]
-pub struct LayerOps<S>(EnumMap<Layer, Option<LayerOp<S>>>);
+pub struct LayerOps<S> {
+    ops: BTreeMap<Layer, LayerOp<S>>,
+    embed_ops: BTreeMap<Layer, LayerOp<S>>,
+}
 
 impl<S> LayerOps<S>
 where
@@ -76,14 +90,22 @@ where
 {
     /// Convert a graph op identifiers for all layers to a graph ops.
     fn to_graph_ops(&self, graph: &Graph) -> Result<LayerOps<Operation>, Error> {
-        let mut graph_ops = EnumMap::new();
+        let mut graph_ops = BTreeMap::new();
 
-        for (layer, op_name) in &self.0 {
-            let op_name = ok_or!(op_name.as_ref(), continue);
-            graph_ops[layer] = Some(op_name.to_graph_op(graph)?);
+        for (layer, op_name) in &self.ops {
+            graph_ops.insert(layer.clone(), op_name.to_graph_op(graph)?);
         }
 
-        Ok(LayerOps(graph_ops))
+        let mut graph_embed_ops = BTreeMap::new();
+
+        for (layer, op_name) in &self.embed_ops {
+            graph_embed_ops.insert(layer.clone(), op_name.to_graph_op(graph)?);
+        }
+
+        Ok(LayerOps {
+            ops: graph_ops,
+            embed_ops: graph_embed_ops,
+        })
     }
 }
 
@@ -92,17 +114,55 @@ impl<S> LayerOps<S> {
     ///
     /// By default, the op for every layer is set to `None`.
     pub fn new() -> Self {
-        LayerOps(EnumMap::new())
+        LayerOps {
+            ops: BTreeMap::new(),
+            embed_ops: BTreeMap::new(),
+        }
     }
 
     /// Set the op for a layer.
     pub fn insert(&mut self, layer: Layer, op: LayerOp<S>) {
-        self.0[layer] = Some(op);
+        self.ops.insert(layer, op);
     }
 
     /// Get the op for a layer.
-    pub fn layer_lookup(&self, layer: Layer) -> Option<&LayerOp<S>> {
-        self.0[layer].as_ref()
+    pub fn layer_lookup(&self, layer: &Layer) -> Option<&LayerOp<S>> {
+        self.ops.get(layer)
+    }
+
+    /// Set the embedding matrix op for a layer.
+    ///
+    /// The embedding matrix of the layer is initialized with the
+    /// embedding matrix of the layer lookup.
+    pub fn insert_embeds(&mut self, layer: Layer, op: LayerOp<S>) {
+        self.embed_ops.insert(layer, op);
+    }
+
+    /// Get the embedding matrix op for a layer.
+    pub fn layer_embeds(&self, layer: &Layer) -> Option<&LayerOp<S>> {
+        self.embed_ops.get(layer)
+    }
+}
+
+/// Ops to initialize a fine-tuned embedding matrix.
+struct EmbedInitOps {
+    init: Operation,
+    assign: Operation,
+}
+
+impl EmbedInitOps {
+    /// Look up the initialization ops of an embedding matrix op.
+    fn from_graph(graph: &Graph, embeds_op_name: &str) -> Result<Self, Error> {
+        let op = |suffix| {
+            graph
+                .operation_by_name_required(&format!("{}{}", embeds_op_name, suffix))
+                .map_err(status_to_error)
+        };
+
+        Ok(EmbedInitOps {
+            init: op(opnames::EMBEDS_INIT_SUFFIX)?,
+            assign: op(opnames::EMBEDS_ASSIGN_SUFFIX)?,
+        })
     }
 }
 
@@ -121,6 +181,7 @@ where
     system: T,
     vectorizer: InputVectorizer,
     layer_ops: LayerOps<Operation>,
+    embed_init_ops: BTreeMap<Layer, EmbedInitOps>,
     init_op: Operation,
     restore_op: Operation,
     save_op: Operation,
@@ -169,9 +230,43 @@ where
             .run(&mut args)
             .expect("Cannot initialize parameters");
 
+        model.init_embeddings()?;
+
         Ok(model)
     }
 
+    /// Initialize fine-tuned embedding matrices from the layer lookups.
+    fn init_embeddings(&mut self) -> Result<(), Error> {
+        for (layer, init_ops) in &self.embed_init_ops {
+            let matrix = self
+                .vectorizer
+                .layer_lookups()
+                .layer_lookup(layer)
+                .and_then(Lookup::embedding_matrix)
+                .ok_or_else(|| {
+                    format_err!(
+                        "The lookup for the {} layer does not provide an embedding matrix",
+                        layer
+                    )
+                })?;
+            let (rows, dims) = matrix.dim();
+            let matrix = Tensor::new(&[rows as u64, dims as u64])
+                .with_values(
+                    matrix
+                        .as_slice()
+                        .expect("Embedding matrix is not contiguous"),
+                )
+                .map_err(status_to_error)?;
+
+            let mut args = SessionRunArgs::new();
+            args.add_feed(&init_ops.init, 0, &matrix);
+            args.add_target(&init_ops.assign);
+            self.session.run(&mut args).map_err(status_to_error)?;
+        }
+
+        Ok(())
+    }
+
     /// Load a Tensorflow graph.
     ///
     /// This constructor will load the model parameters (such as weights) from
@@ -196,12 +291,7 @@ where
             op_names,
         )?;
 
-        // Restore parameters.
-        let path_tensor = prepare_path(parameters_path)?.into();
-        let mut args = SessionRunArgs::new();
-        args.add_feed(&model.save_file_path_op, 0, &path_tensor);
-        args.add_target(&model.restore_op);
-        model.session.run(&mut args).map_err(status_to_error)?;
+        model.restore(parameters_path)?;
 
         Ok(model)
     }
@@ -215,7 +305,8 @@ where
     /// Load a Tensorflow graph.
     ///
     /// This function should be used by a wrapping constructor that initializes
-    /// the graph parameters.
+    /// the graph parameters. The shapes of the input placeholders and logits
+    /// are checked against the vectorizer and the transition system.
     fn load_graph_<S>(
         config_protobuf: &[u8],
         model_protobuf: &[u8],
@@ -240,6 +331,14 @@ where
 
         let layer_ops = op_names.to_graph_ops(&graph)?;
 
+        let mut embed_init_ops = BTreeMap::new();
+        for (layer, op_name) in &op_names.embed_ops {
+            embed_init_ops.insert(
+                layer.clone(),
+                EmbedInitOps::from_graph(&graph, op_name.0.as_ref())?,
+            );
+        }
+
         let init_op = Self::add_op(&graph, opnames::INIT)?;
         let restore_op = Self::add_op(&graph, opnames::RESTORE)?;
         let save_op = Self::add_op(&graph, opnames::SAVE)?;
@@ -257,11 +356,21 @@ where
 
         let train_op = Self::add_op(&graph, opnames::TRAIN)?;
 
+        check_input_shapes(&graph, &vectorizer, op_names, &layer_ops, &embeds_op)?;
+        check_op_shape(
+            &graph,
+            &logits_op,
+            opnames::LOGITS,
+            system.transitions().len(),
+            "transitions in the transition system",
+        )?;
+
         Ok(TensorflowModel {
             system,
             session,
             vectorizer,
             layer_ops,
+            embed_init_ops,
             init_op,
             restore_op,
             save_op,
@@ -281,25 +390,76 @@ where
     ///
     /// Both the parser states and the feature representations of the parser
     /// states should be provided. Returns the best (possible) transition for
-    /// each parser state.
+    /// each parser state. An error is returned when the graph cannot be run.
     pub fn predict(
         &mut self,
         states: &[&ParserState],
         embeds_tensor: &Tensor<f32>,
         input_tensors: &LayerTensors<i32>,
-    ) -> Vec<T::Transition> {
-        let logits = self.logits(embeds_tensor, input_tensors);
+    ) -> Result<Vec<T::Transition>, Error> {
+        let logits = self.logits(embeds_tensor, input_tensors)?;
 
         let n_labels = logits.dims()[1] as usize;
 
-        states
+        Ok(states
             .iter()
             .enumerate()
             .map(|(idx, state)| {
                 let offset = idx * n_labels;
                 self.logits_best_transition(state, &logits[offset..offset + n_labels])
             })
-            .collect()
+            .collect())
+    }
+
+    /// Predict the best transitions and their probabilities for a batch of
+    /// parser states.
+    ///
+    /// This method is like `predict`, but also returns the probability of
+    /// each transition. The probability is the softmax of the transition's
+    /// logit over the transitions that are possible in the parser state.
+    pub fn predict_scored(
+        &mut self,
+        states: &[&ParserState],
+        embeds_tensor: &Tensor<f32>,
+        input_tensors: &LayerTensors<i32>,
+    ) -> Result<Vec<(T::Transition, f32)>, Error> {
+        let logits = self.logits(embeds_tensor, input_tensors)?;
+
+        let n_labels = logits.dims()[1] as usize;
+
+        Ok(states
+            .iter()
+            .enumerate()
+            .map(|(idx, state)| {
+                let offset = idx * n_labels;
+                self.logits_best_scored_transition(state, &logits[offset..offset + n_labels])
+            })
+            .collect())
+    }
+
+    /// Return the best transition for a parser state with its probability.
+    fn logits_best_scored_transition(
+        &self,
+        state: &ParserState,
+        logits: &[f32],
+    ) -> (T::Transition, f32) {
+        let (best, constrained) = self.logits_best_transition_(state, logits);
+        let best_logit = logits[self.system.transitions().lookup(best.clone())];
+
+        // Normalize over the transitions that are permitted in this state.
+        let mut partition = 0f32;
+        for (idx, logit) in logits.iter().enumerate().skip(1) {
+            let transition = self
+                .system
+                .transitions()
+                .value(idx)
+                .expect("Invalid transition index.");
+            if is_permitted(&*transition, state, constrained) {
+                partition += (logit - best_logit).exp();
+            }
+        }
+
+        (best, 1. / partition)
     }
 
     /// Return the best transition for a parser state.
@@ -307,6 +467,20 @@ where
     /// This method finds the best transition (largest logit) that is possible given the
     /// current parser state.
     fn logits_best_transition(&self, state: &ParserState, logits: &[f32]) -> T::Transition {
+        self.logits_best_transition_(state, logits).0
+    }
+
+    /// Return the best transition for a parser state.
+    ///
+    /// If the parser state has constraints, the best transition that keeps
+    /// the constraints satisfiable is returned. If there is no such transition,
+    /// the constraints are ignored. The second element of the returned pair
+    /// indicates whether the constraints were used.
+    fn logits_best_transition_(
+        &self,
+        state: &ParserState,
+        logits: &[f32],
+    ) -> (T::Transition, bool) {
         // Invariant: we should have as many predictions as transitions.
         let n_predictions = logits.len();
         let n_transitions = self.system.transitions().len();
@@ -316,10 +490,29 @@ where
             n_transitions, n_predictions
         );
 
-        let mut best = self.system.transitions().value(1).unwrap();
+        if state.has_constraints() {
+            if let Some(best) = self.logits_best_permitted_transition(state, logits, true) {
+                return (best, true);
+            }
+        }
+
+        let best = self
+            .logits_best_permitted_transition(state, logits, false)
+            .unwrap_or_else(|| self.system.transitions().value(1).unwrap().into_owned());
+
+        (best, false)
+    }
+
+    fn logits_best_permitted_transition(
+        &self,
+        state: &ParserState,
+        logits: &[f32],
+        constrained: bool,
+    ) -> Option<T::Transition> {
+        let mut best = None;
         let mut best_score = f32::NEG_INFINITY;
 
-        for (idx, logit) in logits.as_ref().iter().enumerate() {
+        for (idx, logit) in logits.iter().enumerate() {
             // The special transition 0 is used for unknown transitions
             // (e.g. in validation). Must be skipped in prediction.
             if idx == 0 {
@@ -332,14 +525,14 @@ where
                     .transitions()
                     .value(idx)
                     .expect("Invalid transition index.");
-                if transition.is_possible(state) {
-                    best = transition;
+                if is_permitted(&*transition, state, constrained) {
+                    best = Some(transition);
                     best_score = *logit;
                 }
             }
         }
 
-        best.into_owned()
+        best.map(Cow::into_owned)
     }
 
     /// Compute transition logits from the feature representations of the
@@ -351,7 +544,7 @@ where
         &mut self,
         embeds_tensor: &Tensor<f32>,
         input_tensors: &LayerTensors<i32>,
-    ) -> Tensor<f32> {
+    ) -> Result<Tensor<f32>, Error> {
         let mut is_training = Tensor::new(&[]);
         is_training[0] = false;
 
@@ -360,9 +553,9 @@ where
         args.add_feed(&self.embeds_op, 0, embeds_tensor);
         add_to_args(&mut args, &self.layer_ops, &input_tensors);
         let logits_token = args.request_fetch(&self.logits_op, 0);
-        self.session.run(&mut args).expect("Cannot run graph");
+        self.session.run(&mut args).map_err(status_to_error)?;
 
-        args.fetch(logits_token).expect("Unable to retrieve output")
+        args.fetch(logits_token).map_err(status_to_error)
     }
 
     /// Save the model parameters.
@@ -382,6 +575,38 @@ where
         self.session.run(&mut args).map_err(status_to_error)
     }
 
+    /// Restore the model parameters.
+    ///
+    /// The model parameters are read from the given path.
+    pub fn restore<P>(&mut self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path_tensor = prepare_path(path)?.into();
+        let mut args = SessionRunArgs::new();
+        args.add_feed(&self.save_file_path_op, 0, &path_tensor);
+        args.add_target(&self.restore_op);
+        self.session.run(&mut args).map_err(status_to_error)
+    }
+
+    /// Get the embedding matrix of a layer with fine-tuned embeddings.
+    ///
+    /// Returns an error if no embedding matrix op was set for the layer.
+    pub fn embedding_matrix(&mut self, layer: &Layer) -> Result<Array2<f32>, Error> {
+        let embeds_op = self
+            .layer_ops
+            .layer_embeds(layer)
+            .ok_or_else(|| format_err!("No embedding matrix op for the {} layer", layer))?;
+
+        let mut args = SessionRunArgs::new();
+        let embeds_token = args.request_fetch(&embeds_op.0, 0);
+        self.session.run(&mut args).map_err(status_to_error)?;
+        let embeds: Tensor<f32> = args.fetch(embeds_token).map_err(status_to_error)?;
+
+        let shape = (embeds.dims()[0] as usize, embeds.dims()[1] as usize);
+        Ok(Array2::from_shape_vec(shape, embeds.to_vec())?)
+    }
+
     /// Perform a training step.
     ///
     /// This method updates the model parameters using a batch of parser
@@ -480,13 +705,106 @@ fn add_to_args<'l>(
     layer_ops: &LayerOps<Operation>,
     input_tensors: &'l LayerTensors<i32>,
 ) {
-    for (layer, layer_op) in &layer_ops.0 {
-        let layer_op = ok_or!(layer_op.as_ref(), continue);
-        // Fill the layer vector placeholder.
-        args.add_feed(&layer_op.0, 0, &input_tensors[layer]);
+    for (layer, layer_op) in &layer_ops.ops {
+        // Fill the layer vector placeholder. Layers without inputs are
+        // not fed, Tensorflow reports the missing placeholder value.
+        let input_tensor = ok_or!(input_tensors.get(layer), continue);
+        args.add_feed(&layer_op.0, 0, input_tensor);
     }
 }
 
+/// Check whether a transition is permitted in a parser state.
+fn is_permitted<T>(transition: &T, state: &ParserState, constrained: bool) -> bool
+where
+    T: Transition,
+{
+    transition.is_possible(state) && (!constrained || transition.satisfies_constraints(state))
+}
+
+/// Check the shapes of the input placeholders against the vectorizer.
+fn check_input_shapes<S>(
+    graph: &Graph,
+    vectorizer: &InputVectorizer,
+    op_names: &LayerOps<S>,
+    layer_ops: &LayerOps<Operation>,
+    embeds_op: &Operation,
+) -> Result<(), Error>
+where
+    S: AsRef<str>,
+{
+    check_op_shape(
+        graph,
+        embeds_op,
+        opnames::EMBEDS,
+        vectorizer.embedding_layer_size(),
+        "embedding inputs in the vectorizer",
+    )?;
+
+    for (layer, &size) in &vectorizer.lookup_layer_sizes() {
+        match (op_names.layer_lookup(layer), layer_ops.layer_lookup(layer)) {
+            (Some(op_name), Some(op)) => check_op_shape(
+                graph,
+                &op.0,
+                op_name.0.as_ref(),
+                size,
+                &format!("{} inputs in the vectorizer", layer),
+            )?,
+            _ => {
+                if size != 0 {
+                    return Err(format_err!(
+                        "The vectorizer has {} {} inputs, but no graph op is specified for the layer",
+                        size,
+                        layer
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the second dimension of an op's output has the expected size.
+///
+/// The check is skipped when the graph does not provide the size of the
+/// dimension.
+fn check_op_shape(
+    graph: &Graph,
+    op: &Operation,
+    op_name: &str,
+    expected: usize,
+    expected_desc: &str,
+) -> Result<(), Error> {
+    let shape = graph
+        .tensor_shape(Output {
+            operation: op.clone(),
+            index: 0,
+        })
+        .map_err(status_to_error)?;
+
+    let rank = ok_or!(shape.dims(), return Ok(()));
+    if rank != 2 {
+        return Err(format_err!(
+            "Graph op {} has rank {}, expected a rank of 2",
+            op_name,
+            rank
+        ));
+    }
+
+    let size = ok_or!(shape[1], return Ok(()));
+    if size != expected as i64 {
+        return Err(format_err!(
+            "Shape mismatch for graph op {}: the graph expects size {}, but there are {} {}",
+            op_name,
+            size,
+            expected,
+            expected_desc
+        ));
+    }
+
+    Ok(())
+}
+
 /// Tensorflow requires a path that contains a directory component.
 fn prepare_path<P>(path: P) -> Result<String, Error>
 where
@@ -518,30 +836,132 @@ mod tests {
 
     use flate2::read::GzDecoder;
 
-    use crate::features::{AddressedValues, InputVectorizer, Layer, LayerLookups};
-    use crate::systems::StackProjectiveSystem;
+    use crate::features::addr::{self, AddressedValue, Source};
+    use crate::features::{
+        AddressedValues, InputVectorizer, Layer, LayerLookups, Lookup, LookupResult, LookupType,
+    };
+    use crate::system::TransitionSystem;
+    use crate::systems::stack_projective::{StackProjectiveSystem, StackProjectiveTransition};
 
     use super::{LayerOp, LayerOps, TensorflowModel};
 
-    #[test]
-    fn load_graph_test() {
+    /// Lookup that only provides a lookup type, sufficient for shape checks.
+    struct ShapeLookup(Option<usize>);
+
+    impl Lookup for ShapeLookup {
+        fn len(&self) -> usize {
+            0
+        }
+
+        fn lookup(&self, _feature: &str) -> Option<LookupResult> {
+            None
+        }
+
+        fn lookup_type(&self) -> LookupType {
+            match self.0 {
+                Some(dims) => LookupType::Embedding(dims),
+                None => LookupType::Index,
+            }
+        }
+
+        fn null(&self) -> LookupResult {
+            LookupResult::Index(0)
+        }
+
+        fn unknown(&self) -> LookupResult {
+            LookupResult::Index(0)
+        }
+    }
+
+    fn read_graph() -> Vec<u8> {
         let f = File::open("testdata/parser.graph.gz").expect("Cannot open test graph.");
         let mut decoder = GzDecoder::new(BufReader::new(f));
         let mut data = Vec::new();
         decoder
             .read_to_end(&mut data)
             .expect("Cannot decompress test graph.");
+        data
+    }
 
+    fn addrs(layer: addr::Layer, n: usize) -> Vec<AddressedValue> {
+        (0..n)
+            .map(|idx| AddressedValue {
+                address: vec![Source::Stack(idx)],
+                layer: layer.clone(),
+                input: None,
+            })
+            .collect()
+    }
+
+    /// Construct a vectorizer that matches the shapes of the test graph.
+    fn test_vectorizer(n_deprels: usize) -> InputVectorizer {
+        let mut lookups = LayerLookups::new();
+        lookups.insert(
+            Layer::TOKEN,
+            Box::new(ShapeLookup(Some(3850))) as Box<Lookup>,
+        );
+        lookups.insert(Layer::DEPREL, Box::new(ShapeLookup(None)) as Box<Lookup>);
+        lookups.insert(Layer::FEATURE, Box::new(ShapeLookup(None)) as Box<Lookup>);
+
+        let mut inputs = addrs(addr::Layer::Token, 1);
+        inputs.extend(addrs(addr::Layer::DepRel, n_deprels));
+        inputs.extend(addrs(addr::Layer::Feature("f".to_owned()), 7));
+
+        InputVectorizer::new(lookups, AddressedValues(inputs)).unwrap()
+    }
+
+    fn test_system(n_transitions: usize) -> StackProjectiveSystem {
         let system = StackProjectiveSystem::new();
-        let vectorizer = InputVectorizer::new(LayerLookups::new(), AddressedValues(Vec::new()));
+        for idx in system.transitions().len()..n_transitions {
+            system
+                .transitions()
+                .lookup(StackProjectiveTransition::LeftArc(format!("rel{}", idx)));
+        }
+        system
+    }
 
+    fn test_op_names() -> LayerOps<&'static str> {
         let mut op_names = LayerOps::new();
-        op_names.insert(Layer::Token, LayerOp("model/tokens"));
-        op_names.insert(Layer::Tag, LayerOp("model/tags"));
-        op_names.insert(Layer::DepRel, LayerOp("model/deprels"));
-        op_names.insert(Layer::Feature, LayerOp("model/features"));
+        op_names.insert(Layer::TOKEN, LayerOp("model/tokens"));
+        op_names.insert(Layer::TAG, LayerOp("model/tags"));
+        op_names.insert(Layer::DEPREL, LayerOp("model/deprels"));
+        op_names.insert(Layer::FEATURE, LayerOp("model/features"));
+        op_names
+    }
 
-        TensorflowModel::load_graph(&[], &data, system, vectorizer, &op_names)
-            .expect("Cannot load graph.");
+    #[test]
+    fn load_graph_test() {
+        TensorflowModel::load_graph(
+            &[],
+            &read_graph(),
+            test_system(400),
+            test_vectorizer(5),
+            &test_op_names(),
+        )
+        .expect("Cannot load graph.");
+    }
+
+    #[test]
+    fn load_graph_input_shape_mismatch_test() {
+        assert!(TensorflowModel::load_graph(
+            &[],
+            &read_graph(),
+            test_system(400),
+            test_vectorizer(4),
+            &test_op_names(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn load_graph_transitions_mismatch_test() {
+        assert!(TensorflowModel::load_graph(
+            &[],
+            &read_graph(),
+            test_system(399),
+            test_vectorizer(5),
+            &test_op_names(),
+        )
+        .is_err());
     }
 }
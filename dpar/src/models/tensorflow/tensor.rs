@@ -1,7 +1,6 @@
+use std::collections::BTreeMap;
 use std::ops::{Deref, DerefMut};
 
-use enum_map::EnumMap;
-
 use crate::features::Layer;
 use tensorflow::{Tensor, TensorType};
 
@@ -41,14 +40,9 @@ where
     T: Copy + TensorType,
 {
     fn copy_batches(&self, n_batches: u64) -> Self {
-        let mut copy = LayerTensors::new();
-
-        // Note: EnumMap does not support FromIterator.
-        for (layer, tensor) in self.iter() {
-            copy[layer] = tensor.copy_batches(n_batches);
-        }
-
-        copy
+        self.iter()
+            .map(|(layer, tensor)| (layer.clone(), tensor.copy_batches(n_batches)))
+            .collect()
     }
 }
 
@@ -56,27 +50,27 @@ where
 pub trait InstanceSlices<T> {
     /// Extract for each layer the slice corresponding to the `idx`-th
     /// instance from the batch.
-    fn to_instance_slices(&mut self, idx: usize) -> EnumMap<Layer, &mut [T]>;
+    fn to_instance_slices(&mut self, idx: usize) -> BTreeMap<Layer, &mut [T]>;
 }
 
 impl<T> InstanceSlices<T> for LayerTensors<T>
 where
     T: TensorType,
 {
-    fn to_instance_slices(&mut self, idx: usize) -> EnumMap<Layer, &mut [T]> {
-        let mut slices = EnumMap::new();
+    fn to_instance_slices(&mut self, idx: usize) -> BTreeMap<Layer, &mut [T]> {
+        let mut slices = BTreeMap::new();
 
         for (layer, tensor) in self.iter_mut() {
             let layer_size = tensor.dims()[1] as usize;
             let offset = idx * layer_size;
-            slices[layer] = &mut tensor[offset..offset + layer_size];
+            slices.insert(layer.clone(), &mut tensor[offset..offset + layer_size]);
         }
 
         slices
     }
 }
 
-pub type LayerTensors<T> = EnumMap<Layer, TensorWrap<T>>;
+pub type LayerTensors<T> = BTreeMap<Layer, TensorWrap<T>>;
 
 /// Simple wrapper for `Tensor` that implements the `Default`
 /// trait.
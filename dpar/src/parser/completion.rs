@@ -0,0 +1,218 @@
+use crate::system::{Dependency, DependencySet};
+
+/// Completion of partial parses to well-formed dependency trees.
+///
+/// Depending on the transition system, a terminal parser state can have
+/// tokens without a head. Tree completion turns a set of dependencies
+/// into a complete, acyclic tree:
+///
+/// * Cycles are broken by detaching one token of each cycle.
+/// * Tokens without a head are attached to the root using the default
+///   relation.
+///
+/// When the single-root constraint is enabled, only one token is attached
+/// to the root. This is the first root dependent or, if the root does not
+/// have dependents, the first token without a head. All other tokens that
+/// would be attached to the root are attached to this token using the
+/// default relation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TreeCompletion {
+    relation: String,
+    single_root: bool,
+}
+
+impl TreeCompletion {
+    /// Construct a tree completion.
+    ///
+    /// `relation` is the relation of attachments added during completion.
+    pub fn new(relation: impl Into<String>, single_root: bool) -> Self {
+        TreeCompletion {
+            relation: relation.into(),
+            single_root,
+        }
+    }
+
+    /// Get the relation that is used for attachments added by completion.
+    pub fn relation(&self) -> &str {
+        &self.relation
+    }
+
+    /// Returns `true` if the completed trees have a single root dependent.
+    pub fn single_root(&self) -> bool {
+        self.single_root
+    }
+
+    /// Complete the dependencies of a sentence with `n_tokens` tokens
+    /// (excluding the root) to a well-formed tree.
+    pub fn complete(&self, dependencies: DependencySet, n_tokens: usize) -> DependencySet {
+        let mut heads = vec![None; n_tokens + 1];
+        for dependency in dependencies {
+            let dependent = dependency.dependent;
+            heads[dependent] = Some(dependency);
+        }
+
+        break_cycles(&mut heads);
+
+        let root = if self.single_root {
+            (1..heads.len())
+                .find(|&token| heads[token].as_ref().map(|dep| dep.head) == Some(0))
+                .or_else(|| (1..heads.len()).find(|&token| heads[token].is_none()))
+        } else {
+            None
+        };
+
+        if let Some(root) = root {
+            if heads[root].is_none() {
+                heads[root] = Some(self.dependency(0, root));
+            }
+        }
+
+        for token in 1..heads.len() {
+            if Some(token) == root {
+                continue;
+            }
+
+            let attach = match heads[token] {
+                Some(ref dep) => root.is_some() && dep.head == 0,
+                None => true,
+            };
+
+            if attach {
+                heads[token] = Some(self.dependency(root.unwrap_or(0), token));
+            }
+        }
+
+        heads.into_iter().filter_map(|dep| dep).collect()
+    }
+
+    fn dependency(&self, head: usize, dependent: usize) -> Dependency {
+        Dependency {
+            head,
+            relation: self.relation.clone(),
+            dependent,
+        }
+    }
+}
+
+/// Break cycles by detaching the token that closes each cycle.
+fn break_cycles(heads: &mut [Option<Dependency>]) {
+    #[derive(Clone, Copy, Eq, PartialEq)]
+    enum Mark {
+        Unvisited,
+        Visiting,
+        Visited,
+    }
+
+    let mut marks = vec![Mark::Unvisited; heads.len()];
+
+    for token in 1..heads.len() {
+        let mut path = Vec::new();
+        let mut current = token;
+
+        while current != 0 && marks[current] != Mark::Visited {
+            if marks[current] == Mark::Visiting {
+                heads[current] = None;
+                break;
+            }
+
+            marks[current] = Mark::Visiting;
+            path.push(current);
+
+            current = ok_or!(heads[current].as_ref(), break).head;
+        }
+
+        for token in path {
+            marks[token] = Mark::Visited;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::TreeCompletion;
+    use crate::system::{Dependency, DependencySet};
+
+    fn dep(head: usize, relation: &str, dependent: usize) -> Dependency {
+        Dependency {
+            head,
+            relation: relation.to_owned(),
+            dependent,
+        }
+    }
+
+    fn complete(
+        completion: &TreeCompletion,
+        deps: Vec<Dependency>,
+        n_tokens: usize,
+    ) -> BTreeSet<Dependency> {
+        let deps: DependencySet = deps.into_iter().collect();
+        completion.complete(deps, n_tokens).into_iter().collect()
+    }
+
+    #[test]
+    fn complete_tree_is_unchanged() {
+        let completion = TreeCompletion::new("dep", true);
+        let deps = vec![dep(0, "root", 2), dep(2, "nsubj", 1), dep(2, "obj", 3)];
+        assert_eq!(
+            complete(&completion, deps.clone(), 3),
+            deps.into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn attaches_unattached_tokens_to_root() {
+        let completion = TreeCompletion::new("dep", false);
+        let deps = vec![dep(0, "root", 2), dep(2, "nsubj", 1)];
+        assert_eq!(
+            complete(&completion, deps, 4),
+            vec![
+                dep(0, "root", 2),
+                dep(2, "nsubj", 1),
+                dep(0, "dep", 3),
+                dep(0, "dep", 4)
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn enforces_single_root() {
+        let completion = TreeCompletion::new("dep", true);
+        let deps = vec![dep(0, "root", 2), dep(2, "nsubj", 1), dep(0, "root", 4)];
+        assert_eq!(
+            complete(&completion, deps, 4),
+            vec![
+                dep(0, "root", 2),
+                dep(2, "nsubj", 1),
+                dep(2, "dep", 3),
+                dep(2, "dep", 4)
+            ]
+            .into_iter()
+            .collect()
+        );
+
+        // Without root dependents, the first unattached token is the root.
+        let deps = vec![dep(3, "nsubj", 1)];
+        assert_eq!(
+            complete(&completion, deps, 3),
+            vec![dep(3, "nsubj", 1), dep(0, "dep", 2), dep(2, "dep", 3)]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn breaks_cycles() {
+        let completion = TreeCompletion::new("dep", false);
+        let deps = vec![dep(2, "a", 1), dep(3, "b", 2), dep(1, "c", 3)];
+        assert_eq!(
+            complete(&completion, deps, 3),
+            vec![dep(0, "dep", 1), dep(3, "b", 2), dep(1, "c", 3)]
+                .into_iter()
+                .collect()
+        );
+    }
+}
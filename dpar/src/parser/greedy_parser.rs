@@ -1,12 +1,14 @@
 use conllx::Token;
-use failure::Error;
+use failure::{format_err, Error};
 
-use crate::guide::{BatchGuide, Guide};
-use crate::parser::{Parse, ParseBatch};
-use crate::system::{DependencySet, ParserState, Transition, TransitionSystem};
+use crate::guide::{BatchGuide, Guide, ScoredBatchGuide};
+use crate::parser::{Parse, ParseBatch, ScoredParse, ScoredParseBatch, TreeCompletion};
+use crate::system::{DependencySet, ParserState, ScoredDependency, Transition, TransitionSystem};
 
 pub struct GreedyParser<G> {
     guide: G,
+    constrained: bool,
+    completion: Option<TreeCompletion>,
 }
 
 /// A greedy dependency parser.
@@ -19,7 +21,77 @@ where
     G: Guide,
 {
     pub fn new(guide: G) -> Self {
-        GreedyParser { guide }
+        GreedyParser {
+            guide,
+            constrained: false,
+            completion: None,
+        }
+    }
+
+    /// Construct a parser that uses heads in the input as constraints.
+    ///
+    /// The heads and relations of the input tokens are used as constraints
+    /// (see `ParserState::new_constrained`). The guide is responsible for
+    /// only returning transitions that keep the constraints satisfiable.
+    /// Parsing fails when the guide returns a transition that does not.
+    pub fn new_constrained(guide: G) -> Self {
+        GreedyParser {
+            guide,
+            constrained: true,
+            completion: None,
+        }
+    }
+
+    /// Complete parses to well-formed trees.
+    ///
+    /// If the tree completion uses the single-root constraint, the
+    /// constraint is also used during decoding. The guide is responsible
+    /// for only returning transitions that satisfy it. Parsing fails when
+    /// the guide returns a transition that does not.
+    pub fn with_completion(mut self, completion: TreeCompletion) -> Self {
+        self.completion = Some(completion);
+        self
+    }
+}
+
+impl<G> GreedyParser<G> {
+    fn parser_state<'a>(&self, sentence: &'a [Token]) -> ParserState<'a> {
+        let mut state = if self.constrained {
+            ParserState::new_constrained(sentence)
+        } else {
+            ParserState::new(sentence)
+        };
+
+        if let Some(ref completion) = self.completion {
+            state.set_single_root(completion.single_root());
+        }
+
+        state
+    }
+
+    /// Get the dependencies of a terminal parser state.
+    fn dependencies(&self, state: &ParserState) -> DependencySet {
+        match self.completion {
+            Some(ref completion) => {
+                completion.complete(state.dependencies(), state.tokens().len() - 1)
+            }
+            None => state.dependencies(),
+        }
+    }
+}
+
+/// Check that a transition returned by a guide satisfies the constraints.
+fn check_constraints<T>(transition: &T, state: &ParserState) -> Result<(), Error>
+where
+    T: Transition,
+{
+    if transition.satisfies_constraints(state) {
+        Ok(())
+    } else {
+        Err(format_err!(
+            "Guide returned transition that violates the constraints: {:?}",
+            transition
+        ))
     }
 }
 
@@ -28,13 +100,15 @@ where
     G: Guide,
 {
     fn parse(&mut self, sentence: &[Token]) -> Result<DependencySet, Error> {
-        let mut state = ParserState::new(sentence);
+        let mut state = self.parser_state(sentence);
 
         while !<<G as Guide>::Transition as Transition>::S::is_terminal(&state) {
-            self.guide.best_transition(&state).apply(&mut state);
+            let transition = self.guide.best_transition(&state)?;
+            check_constraints(&transition, &state)?;
+            transition.apply(&mut state);
         }
 
-        Ok(state.dependencies())
+        Ok(self.dependencies(&state))
     }
 }
 
@@ -48,7 +122,7 @@ where
     {
         let mut states: Vec<_> = sentences
             .iter()
-            .map(|s| ParserState::new(s.as_ref()))
+            .map(|s| self.parser_state(s.as_ref()))
             .collect();
 
         loop {
@@ -68,15 +142,210 @@ where
                     break;
                 }
 
-                (self.guide.best_transitions(&active_states), mapping)
+                (self.guide.best_transitions(&active_states)?, mapping)
             };
 
             // Apply transitions.
             for (idx, transition) in mapping.into_iter().zip(transitions) {
+                check_constraints(&transition, &states[idx])?;
                 transition.apply(&mut states[idx]);
             }
         }
 
-        Ok(states.iter().map(ParserState::dependencies).collect())
+        Ok(states
+            .iter()
+            .map(|state| self.dependencies(state))
+            .collect())
+    }
+}
+
+impl<G> ScoredParseBatch for GreedyParser<G>
+where
+    G: ScoredBatchGuide,
+{
+    fn parse_batch_scored<S>(&mut self, sentences: &[S]) -> Result<Vec<ScoredParse>, Error>
+    where
+        S: AsRef<[Token]>,
+    {
+        let mut states: Vec<_> = sentences
+            .iter()
+            .map(|s| self.parser_state(s.as_ref()))
+            .collect();
+
+        // Probabilities of the transitions that attached each dependent and
+        // the log-probabilities of all transitions.
+        let mut arc_scores: Vec<_> = sentences
+            .iter()
+            .map(|s| vec![0f32; s.as_ref().len() + 1])
+            .collect();
+        let mut log_probs = vec![(0f32, 0usize); sentences.len()];
+
+        loop {
+            let (transitions, mapping) = {
+                let mut active_states = Vec::new();
+                let mut mapping = Vec::new();
+
+                for (idx, state) in states.iter().enumerate() {
+                    if !<<G as BatchGuide>::Transition as Transition>::S::is_terminal(state) {
+                        active_states.push(state);
+                        mapping.push(idx);
+                    }
+                }
+
+                // We are done when all parser states are terminal.
+                if active_states.is_empty() {
+                    break;
+                }
+
+                (self.guide.best_scored_transitions(&active_states)?, mapping)
+            };
+
+            // Apply transitions and record their probabilities.
+            for (idx, (transition, prob)) in mapping.into_iter().zip(transitions) {
+                let state = &mut states[idx];
+                check_constraints(&transition, state)?;
+                let n_attached = state.attachment_order().len();
+                transition.apply(state);
+
+                for &dependent in &state.attachment_order()[n_attached..] {
+                    arc_scores[idx][dependent] = prob;
+                }
+
+                log_probs[idx].0 += prob.ln();
+                log_probs[idx].1 += 1;
+            }
+        }
+
+        Ok(states
+            .iter()
+            .zip(arc_scores)
+            .zip(log_probs)
+            .map(|((state, arc_scores), (log_prob, n_transitions))| {
+                // Dependencies that were added by tree completion get score 0.
+                let mut dependencies: Vec<_> = self
+                    .dependencies(state)
+                    .into_iter()
+                    .map(|dependency| {
+                        let score = match state.head(dependency.dependent) {
+                            Some(dep) if *dep == dependency => arc_scores[dependency.dependent],
+                            _ => 0.,
+                        };
+
+                        ScoredDependency { score, dependency }
+                    })
+                    .collect();
+                dependencies.sort_by_key(|d| d.dependency.dependent);
+
+                let score = if n_transitions == 0 {
+                    1.
+                } else {
+                    (log_prob / n_transitions as f32).exp()
+                };
+
+                ScoredParse {
+                    dependencies,
+                    score,
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use conllx::{ReadSentence, Reader};
+    use failure::Error;
+
+    use crate::guide::{BatchGuide, Guide, ScoredBatchGuide};
+    use crate::parser::{GreedyParser, Parse, ParseBatch, ScoredParseBatch};
+    use crate::system::{sentence_to_dependencies, ParserState, TransitionSystem};
+    use crate::systems::stack_projective::{StackProjectiveOracle, StackProjectiveTransition};
+    use crate::systems::StackProjectiveSystem;
+
+    /// Guide that follows an oracle with a fixed transition probability.
+    struct FixedProbOracle(StackProjectiveOracle);
+
+    impl Guide for FixedProbOracle {
+        type Transition = StackProjectiveTransition;
+
+        fn best_transition(&mut self, state: &ParserState) -> Result<Self::Transition, Error> {
+            self.0.best_transition(state)
+        }
+    }
+
+    impl BatchGuide for FixedProbOracle {
+        type Transition = StackProjectiveTransition;
+
+        fn best_transitions(
+            &mut self,
+            states: &[&ParserState],
+        ) -> Result<Vec<Self::Transition>, Error> {
+            states
+                .iter()
+                .map(|state| self.0.best_transition(state))
+                .collect()
+        }
+    }
+
+    impl ScoredBatchGuide for FixedProbOracle {
+        fn best_scored_transitions(
+            &mut self,
+            states: &[&ParserState],
+        ) -> Result<Vec<(Self::Transition, f32)>, Error> {
+            Ok(self
+                .best_transitions(states)?
+                .into_iter()
+                .map(|t| (t, 0.5))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn parse_batch_scored_test() {
+        let f = File::open("testdata/cdb-test.conll").unwrap();
+        let sentence = Reader::new(BufReader::new(f))
+            .read_sentence()
+            .unwrap()
+            .unwrap();
+        let gold = sentence_to_dependencies(&sentence).unwrap();
+
+        let oracle = StackProjectiveSystem::oracle(&gold);
+        let mut parser = GreedyParser::new(FixedProbOracle(oracle));
+        let parses = parser.parse_batch_scored(&[&sentence]).unwrap();
+
+        assert_eq!(parses.len(), 1);
+        let parse = &parses[0];
+        assert_eq!(parse.dependencies.len(), sentence.len());
+        for (idx, scored) in parse.dependencies.iter().enumerate() {
+            assert_eq!(scored.dependency.dependent, idx + 1);
+            assert!(gold.contains(&scored.dependency));
+            assert!((scored.score - 0.5).abs() < 1e-6);
+        }
+        assert!((parse.score - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn constraint_violation_test() {
+        let f = File::open("testdata/cdb-test.conll").unwrap();
+        let mut sentence = Reader::new(BufReader::new(f))
+            .read_sentence()
+            .unwrap()
+            .unwrap();
+        let gold = sentence_to_dependencies(&sentence).unwrap();
+
+        // Constrain the first token to a relation that the oracle does
+        // not attach it with.
+        sentence[0].set_head_rel(Some("mod".to_owned()));
+
+        let oracle = StackProjectiveSystem::oracle(&gold);
+        let mut parser = GreedyParser::new_constrained(FixedProbOracle(oracle));
+        assert!(parser.parse(&sentence).is_err());
+
+        let oracle = StackProjectiveSystem::oracle(&gold);
+        let mut parser = GreedyParser::new_constrained(FixedProbOracle(oracle));
+        assert!(parser.parse_batch(&[&sentence]).is_err());
     }
 }
@@ -1,11 +1,12 @@
 //! Dependency parsers
 //!
-//! This module defines the `Parse` and `ParseBatch` traits for dependency
-//! parsers. A greedy (linear-time) parser is also provided.
+//! This module defines the `Parse`, `ParseBatch`, and `ScoredParseBatch`
+//! traits for dependency parsers. A greedy (linear-time) parser is also
+//! provided, as well as completion of parser output to well-formed trees.
 
 use conllx::Token;
 
-use crate::system::DependencySet;
+use crate::system::{DependencySet, ScoredDependency};
 use failure::Error;
 
 /// A dependency parser without batch processing.
@@ -22,5 +23,29 @@ pub trait ParseBatch {
         S: AsRef<[Token]>;
 }
 
+/// A scored dependency parse.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoredParse {
+    /// The scored dependencies, ordered by dependent.
+    pub dependencies: Vec<ScoredDependency>,
+
+    /// The sentence score.
+    ///
+    /// This is the geometric mean of the probabilities of all transitions
+    /// that were used to parse the sentence.
+    pub score: f32,
+}
+
+/// A dependency parser with batch processing that scores dependencies.
+pub trait ScoredParseBatch {
+    /// Parse a batch of sentences, returning scored dependency relations.
+    fn parse_batch_scored<S>(&mut self, sentences: &[S]) -> Result<Vec<ScoredParse>, Error>
+    where
+        S: AsRef<[Token]>;
+}
+
+mod completion;
+pub use self::completion::TreeCompletion;
+
 mod greedy_parser;
 pub use self::greedy_parser::GreedyParser;
@@ -0,0 +1,598 @@
+//! Pseudo-projective parsing
+//!
+//! Pseudo-projective parsing makes it possible to produce non-projective
+//! trees with a projective transition system. Before training, the
+//! treebank is projectivized by lifting non-projective arcs. Information
+//! about the lifts is encoded in the dependency relations, so that the
+//! lifts can be undone after parsing.
+//!
+//! This module implements the encoding schemes of:
+//!
+//! Joakim Nivre and Jens Nilsson, Pseudo-projective dependency parsing, 2005
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+use conllx::{Deprojectivize, Projectivize, Sentence, Token};
+use failure::{format_err, Error};
+use serde_derive::{Deserialize, Serialize};
+
+/// Separates the relation of a lifted token from the encoded head relation.
+const LIFT_MARKER: char = '|';
+
+/// Marks arcs on the path of a lift.
+const PATH_MARKER: char = '%';
+
+/// Encoding scheme of lifted arcs.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum Encoding {
+    /// The relation of a lifted token encodes the relation of its
+    /// syntactic head.
+    #[serde(rename = "head")]
+    Head,
+
+    /// The relation of a lifted token encodes the relation of its
+    /// syntactic head, arcs on the path of the lift are marked.
+    #[serde(rename = "head+path")]
+    HeadPath,
+
+    /// The relation of a lifted token is marked as lifted, arcs on the
+    /// path of the lift are marked.
+    #[serde(rename = "path")]
+    Path,
+}
+
+impl Encoding {
+    /// All encoding schemes.
+    pub fn all() -> &'static [Encoding] {
+        &[Encoding::Head, Encoding::HeadPath, Encoding::Path]
+    }
+
+    fn encodes_head(self) -> bool {
+        self != Encoding::Path
+    }
+
+    fn encodes_path(self) -> bool {
+        self != Encoding::Head
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Encoding::Head => "head",
+            Encoding::HeadPath => "head+path",
+            Encoding::Path => "path",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "head" => Ok(Encoding::Head),
+            "head+path" => Ok(Encoding::HeadPath),
+            "path" => Ok(Encoding::Path),
+            _ => Err(format_err!("Unknown pseudo-projective encoding: {}", s)),
+        }
+    }
+}
+
+/// Projectivization statistics of a treebank.
+#[derive(Clone, Debug, Default)]
+pub struct ProjectivizationStats {
+    /// Number of sentences.
+    pub n_sentences: usize,
+
+    /// Number of non-projective sentences.
+    pub n_nonprojective_sentences: usize,
+
+    /// Number of arcs.
+    pub n_arcs: usize,
+
+    /// Number of arcs that were lifted.
+    pub n_lifted_arcs: usize,
+
+    /// Number of lift operations. An arc can be lifted more than once.
+    pub n_lifts: usize,
+
+    relations: HashSet<String>,
+    encoded_relations: HashSet<String>,
+}
+
+impl ProjectivizationStats {
+    /// Number of relations in the original treebank.
+    pub fn n_relations(&self) -> usize {
+        self.relations.len()
+    }
+
+    /// Number of relations in the projectivized treebank.
+    pub fn n_encoded_relations(&self) -> usize {
+        self.encoded_relations.len()
+    }
+
+    /// Number of relations that were added by the encoding.
+    pub fn n_added_relations(&self) -> usize {
+        self.encoded_relations.difference(&self.relations).count()
+    }
+}
+
+/// Pseudo-projectivizer.
+///
+/// This projectivizer lifts the smallest non-projective arc until a
+/// tree is projective. The lifts are encoded in the dependency relations
+/// using the given encoding scheme. Deprojectivization undoes the lifts
+/// by searching for the syntactic head of lifted tokens using the
+/// information in the encoded relations.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PseudoProjectivizer {
+    encoding: Encoding,
+}
+
+impl PseudoProjectivizer {
+    pub fn new(encoding: Encoding) -> Self {
+        PseudoProjectivizer { encoding }
+    }
+
+    /// Get the encoding scheme of the projectivizer.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Projectivize a sentence, updating the projectivization statistics.
+    pub fn projectivize_with_stats(
+        &self,
+        sentence: &[Token],
+        stats: &mut ProjectivizationStats,
+    ) -> Result<Sentence, Error> {
+        let mut tree = Tree::from_sentence(sentence)?;
+        let n_tokens = tree.heads.len();
+
+        // Relation of the syntactic head of lifted tokens.
+        let mut lifted: Vec<Option<String>> = vec![None; n_tokens];
+        // Tokens whose incoming arc is on the path of a lift.
+        let mut on_path = vec![false; n_tokens];
+
+        let mut n_lifts = 0;
+        while let Some(dependent) = tree.smallest_nonprojective_arc() {
+            let head = tree.heads[dependent];
+
+            if lifted[dependent].is_none() {
+                lifted[dependent] = Some(tree.relations[head].clone());
+            }
+            on_path[head] = true;
+
+            tree.heads[dependent] = tree.heads[head];
+            n_lifts += 1;
+        }
+
+        stats.n_sentences += 1;
+        stats.n_arcs += n_tokens - 1;
+        stats.n_lifts += n_lifts;
+        if n_lifts != 0 {
+            stats.n_nonprojective_sentences += 1;
+        }
+
+        for token in 1..n_tokens {
+            stats.relations.insert(tree.relations[token].clone());
+
+            if let Some(ref head_relation) = lifted[token] {
+                stats.n_lifted_arcs += 1;
+
+                tree.relations[token].push(LIFT_MARKER);
+                if self.encoding.encodes_head() {
+                    tree.relations[token].push_str(head_relation);
+                }
+            }
+
+            if on_path[token] && self.encoding.encodes_path() {
+                tree.relations[token].push(PATH_MARKER);
+            }
+
+            stats
+                .encoded_relations
+                .insert(tree.relations[token].clone());
+        }
+
+        let mut sentence = sentence.to_owned();
+        tree.update_sentence(&mut sentence);
+
+        Ok(sentence)
+    }
+
+    /// Find the syntactic head of a lifted token.
+    fn syntactic_head(
+        &self,
+        tree: &Tree,
+        relations: &[EncodedRelation],
+        dependent: usize,
+    ) -> Option<usize> {
+        let head = tree.heads[dependent];
+
+        match self.encoding {
+            Encoding::Head => {
+                let head_relation = relations[dependent].head_relation.as_ref()?;
+                tree.find_descendant(head, dependent, false, |token| {
+                    &relations[token].relation == head_relation
+                })
+            }
+            Encoding::HeadPath => {
+                let head_relation = relations[dependent].head_relation.as_ref()?;
+                tree.find_descendant(head, dependent, true, |token| {
+                    &relations[token].relation == head_relation
+                })
+            }
+            Encoding::Path => {
+                // Follow the marked path as far as possible.
+                let mut current = head;
+                while let Some(&next) = tree
+                    .dependents(current)
+                    .iter()
+                    .find(|&&token| token != dependent && relations[token].on_path)
+                {
+                    current = next;
+                }
+
+                if current == head {
+                    None
+                } else {
+                    Some(current)
+                }
+            }
+        }
+    }
+}
+
+impl Projectivize for PseudoProjectivizer {
+    fn projectivize(&self, sentence: &[Token]) -> Result<Sentence, Error> {
+        self.projectivize_with_stats(sentence, &mut ProjectivizationStats::default())
+    }
+}
+
+impl Deprojectivize for PseudoProjectivizer {
+    fn deprojectivize(&self, sentence: &[Token]) -> Result<Sentence, Error> {
+        let mut tree = Tree::from_sentence(sentence)?;
+        let relations: Vec<_> = tree
+            .relations
+            .iter()
+            .map(|relation| EncodedRelation::parse(relation, self.encoding))
+            .collect();
+
+        // Lifted tokens are processed top-down.
+        for token in tree.breadth_first() {
+            if !relations[token].lifted {
+                continue;
+            }
+
+            if let Some(head) = self.syntactic_head(&tree, &relations, token) {
+                tree.heads[token] = head;
+            }
+        }
+
+        for (relation, encoded) in tree.relations.iter_mut().zip(relations) {
+            *relation = encoded.relation;
+        }
+
+        let mut sentence = sentence.to_owned();
+        tree.update_sentence(&mut sentence);
+
+        Ok(sentence)
+    }
+}
+
+/// A dependency relation with pseudo-projective encoding.
+struct EncodedRelation {
+    relation: String,
+    head_relation: Option<String>,
+    lifted: bool,
+    on_path: bool,
+}
+
+impl EncodedRelation {
+    fn parse(relation: &str, encoding: Encoding) -> Self {
+        let (relation, on_path) = if encoding.encodes_path() && relation.ends_with(PATH_MARKER) {
+            (&relation[..relation.len() - PATH_MARKER.len_utf8()], true)
+        } else {
+            (relation, false)
+        };
+
+        let mut parts = relation.splitn(2, LIFT_MARKER);
+        let relation = parts.next().unwrap_or("").to_owned();
+        let head_relation = parts.next();
+
+        EncodedRelation {
+            relation,
+            lifted: head_relation.is_some(),
+            head_relation: head_relation
+                .filter(|_| encoding.encodes_head())
+                .map(ToOwned::to_owned),
+            on_path,
+        }
+    }
+}
+
+/// Dependency tree, where token 0 is the root.
+struct Tree {
+    heads: Vec<usize>,
+    relations: Vec<String>,
+}
+
+impl Tree {
+    fn from_sentence(sentence: &[Token]) -> Result<Self, Error> {
+        let mut heads = Vec::with_capacity(sentence.len() + 1);
+        let mut relations = Vec::with_capacity(sentence.len() + 1);
+        heads.push(0);
+        relations.push(String::new());
+
+        for (idx, token) in sentence.iter().enumerate() {
+            let head = token
+                .head()
+                .ok_or_else(|| format_err!("Token {} has no head", idx + 1))?;
+            if head > sentence.len() {
+                return Err(format_err!(
+                    "Token {} has an invalid head: {}",
+                    idx + 1,
+                    head
+                ));
+            }
+
+            heads.push(head);
+            relations.push(token.head_rel().unwrap_or("_").to_owned());
+        }
+
+        Ok(Tree { heads, relations })
+    }
+
+    fn update_sentence(&self, sentence: &mut [Token]) {
+        for (idx, token) in sentence.iter_mut().enumerate() {
+            token.set_head(Some(self.heads[idx + 1]));
+            token.set_head_rel(Some(self.relations[idx + 1].clone()));
+        }
+    }
+
+    /// Check whether `head` dominates `token`.
+    fn dominates(&self, head: usize, token: usize) -> bool {
+        let mut current = token;
+
+        // Bound the number of steps, in case the tree is cyclic.
+        for _ in 0..self.heads.len() {
+            if current == head {
+                return true;
+            }
+
+            if current == 0 {
+                return false;
+            }
+
+            current = self.heads[current];
+        }
+
+        false
+    }
+
+    /// The dependents of a token, from left to right.
+    fn dependents(&self, head: usize) -> Vec<usize> {
+        (1..self.heads.len())
+            .filter(|&token| self.heads[token] == head)
+            .collect()
+    }
+
+    /// Check whether the arc that attaches the token is projective.
+    fn is_projective(&self, dependent: usize) -> bool {
+        let head = self.heads[dependent];
+        let (left, right) = if head < dependent {
+            (head, dependent)
+        } else {
+            (dependent, head)
+        };
+
+        (left + 1..right).all(|token| self.dominates(head, token))
+    }
+
+    /// Find the smallest non-projective arc, returning its dependent.
+    fn smallest_nonprojective_arc(&self) -> Option<usize> {
+        (1..self.heads.len())
+            .filter(|&token| !self.is_projective(token))
+            .min_by_key(|&token| {
+                let head = self.heads[token];
+                if head < token {
+                    token - head
+                } else {
+                    head - token
+                }
+            })
+    }
+
+    /// The tokens in breadth-first order, starting at the root.
+    fn breadth_first(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.heads.len());
+        let mut queue: VecDeque<_> = self.dependents(0).into();
+
+        while let Some(token) = queue.pop_front() {
+            order.push(token);
+            queue.extend(self.dependents(token));
+        }
+
+        order
+    }
+
+    /// Breadth-first search for a descendant of `head` that satisfies
+    /// the predicate. The subtree of `dependent` is excluded from the
+    /// search. If `path_only` is true, only marked arcs are followed.
+    fn find_descendant<F>(
+        &self,
+        head: usize,
+        dependent: usize,
+        path_only: bool,
+        predicate: F,
+    ) -> Option<usize>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let mut queue = VecDeque::new();
+        queue.push_back(head);
+
+        while let Some(current) = queue.pop_front() {
+            for token in self.dependents(current) {
+                if token == dependent
+                    || (path_only && !self.relations[token].ends_with(PATH_MARKER))
+                {
+                    continue;
+                }
+
+                if predicate(token) {
+                    return Some(token);
+                }
+
+                queue.push_back(token);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use conllx::{Deprojectivize, Projectivize, Reader, Token, TokenBuilder};
+
+    use super::{Encoding, ProjectivizationStats, PseudoProjectivizer, Tree};
+
+    static NON_PROJECTIVE_DATA: &'static str = "testdata/cdb-test-np.conll";
+
+    fn read_sentences() -> Vec<Vec<Token>> {
+        let f = File::open(NON_PROJECTIVE_DATA).unwrap();
+        Reader::new(BufReader::new(f))
+            .into_iter()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    fn heads_relations(sentence: &[Token]) -> Vec<(Option<usize>, Option<String>)> {
+        sentence
+            .iter()
+            .map(|token| (token.head(), token.head_rel().map(ToOwned::to_owned)))
+            .collect()
+    }
+
+    #[test]
+    fn projectivized_trees_are_projective() {
+        for &encoding in Encoding::all() {
+            let projectivizer = PseudoProjectivizer::new(encoding);
+            let mut stats = ProjectivizationStats::default();
+
+            for sentence in read_sentences() {
+                let projective = projectivizer
+                    .projectivize_with_stats(&sentence, &mut stats)
+                    .unwrap();
+                let tree = Tree::from_sentence(&projective).unwrap();
+                assert!((1..tree.heads.len()).all(|token| tree.is_projective(token)));
+            }
+
+            assert!(stats.n_nonprojective_sentences > 0);
+            assert!(stats.n_lifted_arcs > 0);
+            assert!(stats.n_lifts >= stats.n_lifted_arcs);
+            assert!(stats.n_added_relations() > 0);
+        }
+    }
+
+    fn nonprojective_sentence() -> Vec<Token> {
+        vec![
+            TokenBuilder::new("w1").head(2).head_rel("x").token(),
+            TokenBuilder::new("w2").head(0).head_rel("root").token(),
+            TokenBuilder::new("w3").head(1).head_rel("y").token(),
+        ]
+    }
+
+    #[test]
+    fn encodes_lifts() {
+        let sentence = nonprojective_sentence();
+
+        let check = [
+            (Encoding::Head, ["x", "root", "y|x"]),
+            (Encoding::HeadPath, ["x%", "root", "y|x"]),
+            (Encoding::Path, ["x%", "root", "y|"]),
+        ];
+
+        for &(encoding, relations) in &check {
+            let projectivizer = PseudoProjectivizer::new(encoding);
+            let projective = projectivizer.projectivize(&sentence).unwrap();
+            assert_eq!(
+                heads_relations(&projective),
+                vec![
+                    (Some(2), Some(relations[0].to_owned())),
+                    (Some(0), Some(relations[1].to_owned())),
+                    (Some(2), Some(relations[2].to_owned())),
+                ]
+            );
+
+            let deprojectivized = projectivizer.deprojectivize(&projective).unwrap();
+            assert_eq!(
+                heads_relations(&sentence),
+                heads_relations(&deprojectivized)
+            );
+        }
+    }
+
+    #[test]
+    fn deprojectivize_restores_relations() {
+        for &encoding in Encoding::all() {
+            let projectivizer = PseudoProjectivizer::new(encoding);
+
+            for sentence in read_sentences() {
+                let projective = projectivizer.projectivize(&sentence).unwrap();
+                let deprojectivized = projectivizer.deprojectivize(&projective).unwrap();
+                let relations: Vec<_> = sentence.iter().map(Token::head_rel).collect();
+                let restored: Vec<_> = deprojectivized.iter().map(Token::head_rel).collect();
+                assert_eq!(relations, restored);
+            }
+        }
+    }
+
+    #[test]
+    fn deprojectivize_restores_most_heads() {
+        // The encodings are not always unambiguous, so not every lifted
+        // arc can be restored.
+        for &encoding in Encoding::all() {
+            let projectivizer = PseudoProjectivizer::new(encoding);
+            let mut n_lifted = 0;
+            let mut n_restored = 0;
+
+            for sentence in read_sentences() {
+                let projective = projectivizer.projectivize(&sentence).unwrap();
+                let deprojectivized = projectivizer.deprojectivize(&projective).unwrap();
+
+                for ((token, lifted), restored) in
+                    sentence.iter().zip(&projective).zip(&deprojectivized)
+                {
+                    if token.head() != lifted.head() {
+                        n_lifted += 1;
+                        if token.head() == restored.head() {
+                            n_restored += 1;
+                        }
+                    }
+                }
+            }
+
+            assert!(n_lifted > 0);
+            assert!(n_restored as f64 / n_lifted as f64 > 0.9);
+        }
+    }
+
+    #[test]
+    fn encoding_from_str() {
+        for &encoding in Encoding::all() {
+            assert_eq!(encoding.to_string().parse::<Encoding>().unwrap(), encoding);
+        }
+
+        assert!("lifted".parse::<Encoding>().is_err());
+    }
+}
@@ -8,3 +8,13 @@ pub struct Dependency {
 }
 
 pub type DependencySet = HashSet<Dependency>;
+
+/// A dependency with a score.
+///
+/// The score is the probability of the transition that introduced
+/// the dependency.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoredDependency {
+    pub dependency: Dependency,
+    pub score: f32,
+}
@@ -4,10 +4,10 @@ use conllx::Token;
 use failure::{format_err, Error};
 
 mod dependency;
-pub use self::dependency::{Dependency, DependencySet};
+pub use self::dependency::{Dependency, DependencySet, ScoredDependency};
 
 mod parser_state;
-pub use self::parser_state::ParserState;
+pub use self::parser_state::{HeadConstraint, ParserState};
 
 mod trans_system;
 pub use self::trans_system::{AttachmentAddr, Transition, TransitionLookup, TransitionSystem};
@@ -1,27 +1,36 @@
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
 
 use conllx::{Features, Token};
 
 use crate::system::{Dependency, DependencySet};
 
+/// A constraint on the head of a token.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HeadConstraint {
+    /// The head that the token must be attached to.
+    pub head: usize,
+
+    /// The relation of the attachment, if constrained.
+    pub relation: Option<String>,
+}
+
+/// Sentence data that does not change during parsing.
+///
+/// This data is shared between clones of a parser state.
 #[derive(Debug)]
-pub struct ParserState<'a> {
+struct SentenceData<'a> {
     tokens: Vec<&'a str>,
     tags: Vec<&'a str>,
     features: Vec<Option<&'a Features>>,
-    stack: Vec<usize>,
-    buffer: Vec<usize>,
-    token_heads: Vec<Option<Dependency>>,
-    head_deps: Vec<Vec<usize>>,
+    constraints: Vec<Option<HeadConstraint>>,
+    has_constraints: bool,
 }
 
-impl<'a> ParserState<'a> {
-    pub fn new(sentence: &[Token]) -> ParserState {
+impl<'a> SentenceData<'a> {
+    fn new(sentence: &'a [Token]) -> Self {
         let n_tokens = sentence.len() + 1;
 
-        let buffer = (1..n_tokens).collect();
-        let stack = vec![0];
-
         let mut tokens = Vec::with_capacity(n_tokens);
         tokens.push("ROOT");
         tokens.extend(sentence.iter().map(Token::form));
@@ -34,14 +43,90 @@ impl<'a> ParserState<'a> {
         features.push(None);
         features.extend(sentence.iter().map(Token::features));
 
-        ParserState {
+        SentenceData {
             tokens,
             tags,
             features,
-            stack,
-            buffer,
-            token_heads: vec![None; n_tokens],
-            head_deps: vec![Vec::new(); n_tokens],
+            constraints: vec![None; n_tokens],
+            has_constraints: false,
+        }
+    }
+}
+
+/// Attachments that were made in a parser state.
+#[derive(Clone, Debug)]
+struct Attachments {
+    token_heads: Vec<Option<Dependency>>,
+    head_deps: Vec<Vec<usize>>,
+    order: Vec<usize>,
+    pending_deps: Vec<usize>,
+}
+
+/// Parser state.
+///
+/// The sentence data (tokens, tags, features, and constraints) is shared
+/// between clones of a state. The attachments are shared as well and are
+/// only copied when a clone adds a dependency, so cloning a state only
+/// copies the stack and the buffer. The buffer is a double-ended queue,
+/// such that tokens can be removed from and added to the front of the
+/// buffer in constant time.
+#[derive(Clone, Debug)]
+pub struct ParserState<'a> {
+    sentence: Arc<SentenceData<'a>>,
+    stack: Vec<usize>,
+    buffer: VecDeque<usize>,
+    attachments: Arc<Attachments>,
+    single_root: bool,
+}
+
+impl<'a> ParserState<'a> {
+    pub fn new(sentence: &[Token]) -> ParserState {
+        ParserState::from_sentence_data(SentenceData::new(sentence))
+    }
+
+    /// Construct a parser state with head constraints.
+    ///
+    /// The heads and head relations of the tokens are used as constraints.
+    /// Tokens without a head are unconstrained. The relation of a token
+    /// is only constrained when its head is constrained.
+    pub fn new_constrained(sentence: &[Token]) -> ParserState {
+        let mut data = SentenceData::new(sentence);
+
+        for (idx, token) in sentence.iter().enumerate() {
+            if let Some(head) = token.head() {
+                data.constraints[idx + 1] = Some(HeadConstraint {
+                    head,
+                    relation: token.head_rel().map(ToOwned::to_owned),
+                });
+                data.has_constraints = true;
+            }
+        }
+
+        ParserState::from_sentence_data(data)
+    }
+
+    fn from_sentence_data(sentence: SentenceData<'a>) -> Self {
+        let n_tokens = sentence.tokens.len();
+
+        // Count the constrained dependents of every token.
+        let mut pending_deps = vec![0; n_tokens];
+        for constraint in sentence.constraints.iter().flatten() {
+            if let Some(count) = pending_deps.get_mut(constraint.head) {
+                *count += 1;
+            }
+        }
+
+        ParserState {
+            sentence: Arc::new(sentence),
+            stack: vec![0],
+            buffer: (1..n_tokens).collect(),
+            attachments: Arc::new(Attachments {
+                token_heads: vec![None; n_tokens],
+                head_deps: vec![Vec::new(); n_tokens],
+                order: Vec::new(),
+                pending_deps,
+            }),
+            single_root: false,
         }
     }
 
@@ -49,17 +134,35 @@ impl<'a> ParserState<'a> {
         let head_idx = d.head;
         let dep_idx = d.dependent;
 
-        if let Err(pos) = self.head_deps[head_idx].binary_search(&dep_idx) {
-            self.head_deps[head_idx].insert(pos, dep_idx)
+        // Copy the attachments if they are shared with another state.
+        let attachments = Arc::make_mut(&mut self.attachments);
+
+        if let Err(pos) = attachments.head_deps[head_idx].binary_search(&dep_idx) {
+            attachments.head_deps[head_idx].insert(pos, dep_idx)
         };
 
-        self.token_heads[dep_idx] = Some(d);
+        if attachments.token_heads[dep_idx].is_none() {
+            attachments.order.push(dep_idx);
+
+            if let Some(constraint) = self.sentence.constraints[dep_idx].as_ref() {
+                if let Some(count) = attachments.pending_deps.get_mut(constraint.head) {
+                    *count -= 1;
+                }
+            }
+        }
+
+        attachments.token_heads[dep_idx] = Some(d);
+    }
+
+    /// Get the dependents in the order in which they were attached.
+    pub fn attachment_order(&self) -> &[usize] {
+        &self.attachments.order
     }
 
     pub fn dependencies(&self) -> DependencySet {
         let mut deps = HashSet::new();
 
-        for dep in &self.token_heads {
+        for dep in &self.attachments.token_heads {
             if let Some(dep) = dep.as_ref() {
                 deps.insert(dep.clone());
             }
@@ -68,20 +171,67 @@ impl<'a> ParserState<'a> {
         deps
     }
 
+    /// Get the head constraint of a token.
+    pub fn constraint(&self, token: usize) -> Option<&HeadConstraint> {
+        self.sentence.constraints[token].as_ref()
+    }
+
+    /// Returns `true` if any token in the parser state is constrained or
+    /// if the single-root constraint is enabled.
+    pub fn has_constraints(&self) -> bool {
+        self.sentence.has_constraints || self.single_root
+    }
+
+    /// Enable or disable the single-root constraint.
+    ///
+    /// When this constraint is enabled, transitions should only be
+    /// permitted if the root has at most one dependent. This constraint is
+    /// checked by the transition systems in `Transition::satisfies_constraints`.
+    pub fn set_single_root(&mut self, single_root: bool) {
+        self.single_root = single_root;
+    }
+
+    /// Returns `true` if the single-root constraint is enabled.
+    pub fn single_root(&self) -> bool {
+        self.single_root
+    }
+
+    /// Check whether an arc is consistent with the constraint on the
+    /// dependent.
+    pub fn arc_satisfies_constraints(&self, head: usize, dependent: usize, relation: &str) -> bool {
+        match self.sentence.constraints[dependent] {
+            Some(ref constraint) => {
+                constraint.head == head
+                    && constraint
+                        .relation
+                        .as_ref()
+                        .map(|r| r == relation)
+                        .unwrap_or(true)
+            }
+            None => true,
+        }
+    }
+
+    /// Check whether a token has constrained dependents that are not
+    /// attached yet.
+    pub fn has_pending_dependents(&self, token: usize) -> bool {
+        self.attachments.pending_deps[token] != 0
+    }
+
     pub fn features(&self) -> &[Option<&Features>] {
-        &self.features
+        &self.sentence.features
     }
 
     pub fn head(&self, token: usize) -> Option<&Dependency> {
-        self.token_heads[token].as_ref()
+        self.attachments.token_heads[token].as_ref()
     }
 
     pub fn leftmost_dependent(&self, head: usize, idx: usize) -> Option<usize> {
-        self.head_deps[head].get(idx).cloned()
+        self.attachments.head_deps[head].get(idx).cloned()
     }
 
     pub fn rightmost_dependent(&self, head: usize, idx: usize) -> Option<usize> {
-        let deps = &self.head_deps[head];
+        let deps = &self.attachments.head_deps[head];
         let idx = deps.len().checked_sub(idx + 1)?;
         deps.get(idx).cloned()
     }
@@ -94,19 +244,19 @@ impl<'a> ParserState<'a> {
         &mut self.stack
     }
 
-    pub fn buffer(&self) -> &Vec<usize> {
+    pub fn buffer(&self) -> &VecDeque<usize> {
         &self.buffer
     }
 
-    pub fn buffer_mut(&mut self) -> &mut Vec<usize> {
+    pub fn buffer_mut(&mut self) -> &mut VecDeque<usize> {
         &mut self.buffer
     }
 
     pub fn tags(&self) -> &[&str] {
-        self.tags.as_slice()
+        self.sentence.tags.as_slice()
     }
 
     pub fn tokens(&self) -> &[&str] {
-        self.tokens.as_slice()
+        self.sentence.tokens.as_slice()
     }
 }
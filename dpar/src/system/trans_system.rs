@@ -56,6 +56,13 @@ pub trait Transition: Clone + Debug + Eq + Hash + Serialize + DeserializeOwned {
 
     fn is_possible(&self, state: &ParserState) -> bool;
     fn apply(&self, state: &mut ParserState);
+
+    /// Check that the head constraints of the parser state remain
+    /// satisfiable after applying the transition.
+    ///
+    /// This method only checks constraints; whether the transition is
+    /// possible at all should be checked with `is_possible`.
+    fn satisfies_constraints(&self, state: &ParserState) -> bool;
 }
 
 /// Transition lookup table.
@@ -188,6 +195,7 @@ mod tests {
     use std::borrow::Cow;
 
     use crate::systems::arc_standard::ArcStandardTransition;
+    use crate::systems::arc_swift::ArcSwiftTransition;
 
     use super::TransitionLookup;
 
@@ -241,4 +249,25 @@ mod tests {
         // Check that serialization/deserialization roundtrip preserved data.
         assert_eq!(fresh, frozen);
     }
+
+    #[test]
+    pub fn parameterized_transition_lookup_serialization_roundtrip() {
+        use self::ArcSwiftTransition::*;
+
+        let fresh = TransitionLookup::default();
+        assert_eq!(fresh.lookup(Shift), 1);
+        assert_eq!(fresh.lookup(LeftArc(0, "foo".to_owned())), 2);
+        assert_eq!(fresh.lookup(LeftArc(2, "foo".to_owned())), 3);
+        assert_eq!(fresh.lookup(RightArc(1, "bar".to_owned())), 4);
+
+        let serialized =
+            ::serde_yaml::to_string(&fresh).expect("Serialization of transition lookup failed");
+
+        let frozen: TransitionLookup<ArcSwiftTransition> = ::serde_yaml::from_str(&serialized)
+            .expect("Deserialization of transition lookup failed");
+
+        assert_eq!(fresh, frozen);
+        assert_eq!(frozen.lookup(LeftArc(2, "foo".to_owned())), 3);
+        assert_eq!(frozen.lookup(LeftArc(1, "foo".to_owned())), 0);
+    }
 }
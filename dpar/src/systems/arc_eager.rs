@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use failure::Error;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::guide::Guide;
@@ -9,7 +10,7 @@ use crate::system::{
 
 use crate::features::addr::Source;
 use crate::system::AttachmentAddr;
-use crate::systems::util::dep_head_mapping;
+use crate::systems::util::{dep_head_mapping, root_attachment_permitted, stack_waits_for};
 
 #[derive(Eq, PartialEq, Serialize, Deserialize)]
 pub struct ArcEagerSystem {
@@ -105,7 +106,7 @@ impl Transition for ArcEagerTransition {
                 });
             }
             ArcEagerTransition::RightArc(ref rel) => {
-                let dependent = state.buffer_mut().remove(0);
+                let dependent = state.buffer_mut().pop_front().unwrap();
                 let head = state.stack()[stack_len - 1];
 
                 state.add_dependency(Dependency {
@@ -117,7 +118,7 @@ impl Transition for ArcEagerTransition {
                 state.stack_mut().push(dependent);
             }
             ArcEagerTransition::Shift => {
-                let next = state.buffer_mut().remove(0);
+                let next = state.buffer_mut().pop_front().unwrap();
                 state.stack_mut().push(next);
             }
             ArcEagerTransition::Reduce => {
@@ -125,6 +126,38 @@ impl Transition for ArcEagerTransition {
             }
         }
     }
+
+    fn satisfies_constraints(&self, state: &ParserState) -> bool {
+        if !state.has_constraints() {
+            return true;
+        }
+
+        let stack = state.stack();
+        let buffer = state.buffer();
+
+        match *self {
+            ArcEagerTransition::LeftArc(ref rel) => {
+                let dependent = stack[stack.len() - 1];
+                state.arc_satisfies_constraints(buffer[0], dependent, rel)
+                    && !state.has_pending_dependents(dependent)
+            }
+            ArcEagerTransition::RightArc(ref rel) => {
+                let head = stack[stack.len() - 1];
+                state.arc_satisfies_constraints(head, buffer[0], rel)
+                    && !stack_waits_for(state, buffer[0])
+                    && root_attachment_permitted(state, head)
+            }
+            ArcEagerTransition::Shift => {
+                // A shifted token can only get a head from the buffer.
+                let left_head = state
+                    .constraint(buffer[0])
+                    .map(|constraint| constraint.head < buffer[0])
+                    .unwrap_or(false);
+                !left_head && !stack_waits_for(state, buffer[0])
+            }
+            ArcEagerTransition::Reduce => !state.has_pending_dependents(stack[stack.len() - 1]),
+        }
+    }
 }
 
 pub struct ArcEagerOracle {
@@ -159,7 +192,7 @@ impl ArcEagerOracle {
 impl Guide for ArcEagerOracle {
     type Transition = ArcEagerTransition;
 
-    fn best_transition(&mut self, state: &ParserState) -> ArcEagerTransition {
+    fn best_transition(&mut self, state: &ParserState) -> Result<ArcEagerTransition, Error> {
         assert!(
             !state.buffer().is_empty(),
             "Applying oracle to terminal configuration"
@@ -182,7 +215,7 @@ impl Guide for ArcEagerOracle {
                 let la = ArcEagerTransition::LeftArc(dep.relation.clone());
 
                 if dep.head == buffer_head && la.is_possible(state) {
-                    return la;
+                    return Ok(la);
                 }
             }
 
@@ -190,7 +223,7 @@ impl Guide for ArcEagerOracle {
                 let ra = ArcEagerTransition::RightArc(dep.relation.clone());
 
                 if dep.head == stack_tip && ra.is_possible(state) {
-                    return ra;
+                    return Ok(ra);
                 }
             }
 
@@ -201,10 +234,10 @@ impl Guide for ArcEagerOracle {
             //   the token on the tip of the stack.
             let r = ArcEagerTransition::Reduce;
             if r.is_possible(state) && self.next_attached(state) {
-                return r;
+                return Ok(r);
             }
         }
 
-        ArcEagerTransition::Shift
+        Ok(ArcEagerTransition::Shift)
     }
 }
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use failure::Error;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::guide::Guide;
@@ -9,7 +10,7 @@ use crate::system::{
 
 use crate::features::addr::Source;
 use crate::system::AttachmentAddr;
-use crate::systems::util::dep_head_mapping;
+use crate::systems::util::{dep_head_mapping, final_root_attachment, stack_waits_for};
 
 /// The arc-hybrid transition system.
 ///
@@ -110,11 +111,38 @@ impl Transition for ArcHybridTransition {
                 state.stack_mut().pop();
             }
             ArcHybridTransition::Shift => {
-                let next = state.buffer_mut().remove(0);
+                let next = state.buffer_mut().pop_front().unwrap();
                 state.stack_mut().push(next);
             }
         }
     }
+
+    fn satisfies_constraints(&self, state: &ParserState) -> bool {
+        if !state.has_constraints() {
+            return true;
+        }
+
+        let stack = state.stack();
+        let buffer = state.buffer();
+
+        match *self {
+            ArcHybridTransition::LeftArc(ref rel) => {
+                let dependent = stack[stack.len() - 1];
+                state.arc_satisfies_constraints(buffer[0], dependent, rel)
+                    && !state.has_pending_dependents(dependent)
+            }
+            ArcHybridTransition::RightArc(ref rel) => {
+                let head = stack[stack.len() - 2];
+                let dependent = stack[stack.len() - 1];
+                state.arc_satisfies_constraints(head, dependent, rel)
+                    && !state.has_pending_dependents(dependent)
+                    && final_root_attachment(state, head, buffer.is_empty())
+            }
+            // Tokens on the stack cannot be attached to a token that is
+            // shifted on top of them.
+            ArcHybridTransition::Shift => !stack_waits_for(state, buffer[0]),
+        }
+    }
 }
 
 pub struct ArcHybridOracle {
@@ -144,7 +172,7 @@ impl ArcHybridOracle {
 impl Guide for ArcHybridOracle {
     type Transition = ArcHybridTransition;
 
-    fn best_transition(&mut self, state: &ParserState) -> ArcHybridTransition {
+    fn best_transition(&mut self, state: &ParserState) -> Result<ArcHybridTransition, Error> {
         assert!(
             !state.stack().is_empty(),
             "Impossible configuration (empty stack)"
@@ -166,7 +194,7 @@ impl Guide for ArcHybridOracle {
                 let la = ArcHybridTransition::LeftArc(dep.relation.clone());
 
                 if dep.head == buffer_head && la.is_possible(state) {
-                    return la;
+                    return Ok(la);
                 }
             }
         }
@@ -181,11 +209,11 @@ impl Guide for ArcHybridOracle {
                     && ra.is_possible(state)
                     && !self.needed_for_attachment(state, stack0)
                 {
-                    return ra;
+                    return Ok(ra);
                 }
             }
         }
 
-        ArcHybridTransition::Shift
+        Ok(ArcHybridTransition::Shift)
     }
 }
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use failure::Error;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::guide::Guide;
@@ -9,7 +10,7 @@ use crate::system::{
 
 use crate::features::addr::Source;
 use crate::system::AttachmentAddr;
-use crate::systems::util::dep_head_mapping;
+use crate::systems::util::{dep_head_mapping, final_root_attachment};
 
 #[derive(Eq, PartialEq, Serialize, Deserialize)]
 pub struct ArcStandardSystem {
@@ -72,7 +73,7 @@ impl Transition for ArcStandardTransition {
     fn is_possible(&self, state: &ParserState) -> bool {
         match *self {
             ArcStandardTransition::LeftArc(_) => {
-                !state.stack().len() > 1 && !state.buffer().is_empty()
+                state.stack().len() > 1 && !state.buffer().is_empty()
             }
             ArcStandardTransition::RightArc(_) => {
                 !state.stack().is_empty() && !state.buffer().is_empty()
@@ -113,10 +114,37 @@ impl Transition for ArcStandardTransition {
             ArcStandardTransition::Shift => {
                 let next = state.buffer()[0];
                 state.stack_mut().push(next);
-                state.buffer_mut().remove(0);
+                state.buffer_mut().pop_front();
             }
         }
     }
+
+    fn satisfies_constraints(&self, state: &ParserState) -> bool {
+        if !state.has_constraints() {
+            return true;
+        }
+
+        let stack = state.stack();
+        let buffer = state.buffer();
+
+        match *self {
+            ArcStandardTransition::LeftArc(ref rel) => {
+                let dependent = stack[stack.len() - 1];
+                state.arc_satisfies_constraints(buffer[0], dependent, rel)
+                    && !state.has_pending_dependents(dependent)
+            }
+            ArcStandardTransition::RightArc(ref rel) => {
+                let head = stack[stack.len() - 1];
+                let dependent = buffer[0];
+                state.arc_satisfies_constraints(head, dependent, rel)
+                    && !state.has_pending_dependents(dependent)
+                    && final_root_attachment(state, head, buffer.len() == 1)
+            }
+            // The shifted token can return to the buffer through RightArc,
+            // so all of its attachments remain possible.
+            ArcStandardTransition::Shift => true,
+        }
+    }
 }
 
 /// Arc-standard system oracle.
@@ -147,7 +175,7 @@ impl ArcStandardOracle {
 impl Guide for ArcStandardOracle {
     type Transition = ArcStandardTransition;
 
-    fn best_transition(&mut self, state: &ParserState) -> ArcStandardTransition {
+    fn best_transition(&mut self, state: &ParserState) -> Result<ArcStandardTransition, Error> {
         let stack = &state.stack();
         let buffer = &state.buffer();
 
@@ -159,7 +187,7 @@ impl Guide for ArcStandardOracle {
                 let la = ArcStandardTransition::LeftArc(dep.relation.clone());
 
                 if dep.head == buffer_head && la.is_possible(state) {
-                    return la;
+                    return Ok(la);
                 }
             }
 
@@ -170,11 +198,11 @@ impl Guide for ArcStandardOracle {
                     && ra.is_possible(state)
                     && !self.needed_for_attachment(state, buffer_head)
                 {
-                    return ra;
+                    return Ok(ra);
                 }
             }
         }
 
-        ArcStandardTransition::Shift
+        Ok(ArcStandardTransition::Shift)
     }
 }
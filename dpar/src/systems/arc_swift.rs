@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use failure::{format_err, Error};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::guide::Guide;
+use crate::system::{
+    Dependency, DependencySet, ParserState, Transition, TransitionLookup, TransitionSystem,
+};
+
+use crate::features::addr::Source;
+use crate::system::AttachmentAddr;
+use crate::systems::util::{dep_head_mapping, root_attachment_permitted, stack_waits_for};
+
+/// The arc-swift transition system.
+///
+/// The arc-swift system is similar to the arc-eager system, but its
+/// arc transitions attach the first buffer token to a token at an
+/// arbitrary stack depth. The tokens above that stack position are
+/// reduced in the same transition. Each arc transition thus carries
+/// the stack depth of the token that is attached, where a depth of
+/// *0* refers to the stack tip.
+///
+/// Since attachments can be made at any stack depth, the attachment
+/// addresses of this system are those of the attachments at the
+/// stack tip.
+///
+/// This system is described in:
+///
+/// Peng Qi and Christopher D. Manning, Arc-swift: A novel transition
+/// system for dependency parsing, 2017
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
+pub struct ArcSwiftSystem {
+    transitions: TransitionLookup<ArcSwiftTransition>,
+}
+
+impl ArcSwiftSystem {
+    pub fn new() -> Self {
+        ArcSwiftSystem {
+            transitions: TransitionLookup::default(),
+        }
+    }
+}
+
+impl Default for ArcSwiftSystem {
+    fn default() -> Self {
+        ArcSwiftSystem::new()
+    }
+}
+
+impl TransitionSystem for ArcSwiftSystem {
+    type Transition = ArcSwiftTransition;
+    type Oracle = ArcSwiftOracle;
+
+    const ATTACHMENT_ADDRS: [AttachmentAddr; 2] = [
+        AttachmentAddr {
+            head: Source::Buffer(0),
+            dependent: Source::Stack(0),
+        },
+        AttachmentAddr {
+            head: Source::Stack(0),
+            dependent: Source::Buffer(0),
+        },
+    ];
+
+    fn is_terminal(state: &ParserState) -> bool {
+        state.buffer().is_empty()
+    }
+
+    fn oracle(gold_dependencies: &DependencySet) -> Self::Oracle {
+        ArcSwiftOracle::new(gold_dependencies)
+    }
+
+    fn transitions(&self) -> &TransitionLookup<Self::Transition> {
+        &self.transitions
+    }
+}
+
+/// Arc-swift transition.
+///
+/// The arc transitions store the stack depth of the token that is
+/// attached and the dependency relation.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum ArcSwiftTransition {
+    LeftArc(usize, String),
+    RightArc(usize, String),
+    Shift,
+}
+
+impl Transition for ArcSwiftTransition {
+    type S = ArcSwiftSystem;
+
+    fn is_possible(&self, state: &ParserState) -> bool {
+        if state.buffer().is_empty() {
+            return false;
+        }
+
+        match *self {
+            ArcSwiftTransition::LeftArc(depth, _) => {
+                let stack = state.stack();
+                stack.len() > depth + 1
+                    && state.head(stack[stack.len() - depth - 1]).is_none()
+                    && reducible(state, depth)
+            }
+            ArcSwiftTransition::RightArc(depth, _) => {
+                state.stack().len() > depth && reducible(state, depth)
+            }
+            ArcSwiftTransition::Shift => true,
+        }
+    }
+
+    fn apply(&self, state: &mut ParserState) {
+        match *self {
+            ArcSwiftTransition::LeftArc(depth, ref rel) => {
+                let head = state.buffer()[0];
+                let stack_len = state.stack().len();
+                let dependent = state.stack()[stack_len - depth - 1];
+                state.stack_mut().truncate(stack_len - depth - 1);
+
+                state.add_dependency(Dependency {
+                    head,
+                    relation: rel.clone(),
+                    dependent,
+                });
+            }
+            ArcSwiftTransition::RightArc(depth, ref rel) => {
+                let stack_len = state.stack().len();
+                let head = state.stack()[stack_len - depth - 1];
+                state.stack_mut().truncate(stack_len - depth);
+                let dependent = state.buffer_mut().pop_front().unwrap();
+
+                state.add_dependency(Dependency {
+                    head,
+                    relation: rel.clone(),
+                    dependent,
+                });
+
+                state.stack_mut().push(dependent);
+            }
+            ArcSwiftTransition::Shift => {
+                let next = state.buffer_mut().pop_front().unwrap();
+                state.stack_mut().push(next);
+            }
+        }
+    }
+
+    fn satisfies_constraints(&self, state: &ParserState) -> bool {
+        if !state.has_constraints() {
+            return true;
+        }
+
+        let stack = state.stack();
+        let buffer = state.buffer();
+
+        match *self {
+            ArcSwiftTransition::LeftArc(depth, ref rel) => {
+                let dependent = stack[stack.len() - depth - 1];
+                state.arc_satisfies_constraints(buffer[0], dependent, rel)
+                    && stack[stack.len() - depth - 1..]
+                        .iter()
+                        .all(|&token| !state.has_pending_dependents(token))
+            }
+            ArcSwiftTransition::RightArc(depth, ref rel) => {
+                let head = stack[stack.len() - depth - 1];
+                root_attachment_permitted(state, head)
+                    && state.arc_satisfies_constraints(head, buffer[0], rel)
+                    && stack[stack.len() - depth..]
+                        .iter()
+                        .all(|&token| !state.has_pending_dependents(token))
+                    && !stack_waits_for(state, buffer[0])
+            }
+            ArcSwiftTransition::Shift => {
+                // A shifted token can only get a head from the buffer.
+                let left_head = state
+                    .constraint(buffer[0])
+                    .map(|constraint| constraint.head < buffer[0])
+                    .unwrap_or(false);
+                !left_head && !stack_waits_for(state, buffer[0])
+            }
+        }
+    }
+}
+
+/// Check whether the tokens above the given stack depth can be reduced.
+fn reducible(state: &ParserState, depth: usize) -> bool {
+    let stack = state.stack();
+    stack[stack.len() - depth..]
+        .iter()
+        .all(|&token| state.head(token).is_some())
+}
+
+pub struct ArcSwiftOracle {
+    dependencies: HashMap<usize, Dependency>,
+}
+
+impl ArcSwiftOracle {
+    pub fn new(gold_dependencies: &DependencySet) -> ArcSwiftOracle {
+        ArcSwiftOracle {
+            dependencies: dep_head_mapping(gold_dependencies),
+        }
+    }
+}
+
+impl Guide for ArcSwiftOracle {
+    type Transition = ArcSwiftTransition;
+
+    fn best_transition(&mut self, state: &ParserState) -> Result<ArcSwiftTransition, Error> {
+        assert!(
+            !state.buffer().is_empty(),
+            "Applying oracle to terminal configuration"
+        );
+
+        let buffer0 = state.buffer()[0];
+
+        // Attach to the closest stack token. Tokens above the attachment
+        // site are reduced, so in a projective tree they cannot be involved
+        // in other attachments anymore.
+        let mut transition = ArcSwiftTransition::Shift;
+        for (depth, &token) in state.stack().iter().rev().enumerate() {
+            if let Some(dep) = self.dependencies.get(&token) {
+                if dep.head == buffer0 {
+                    transition = ArcSwiftTransition::LeftArc(depth, dep.relation.clone());
+                    break;
+                }
+            }
+
+            if let Some(dep) = self.dependencies.get(&buffer0) {
+                if dep.head == token {
+                    transition = ArcSwiftTransition::RightArc(depth, dep.relation.clone());
+                    break;
+                }
+            }
+        }
+
+        // The attachment is impossible when the tokens above the attachment
+        // site are not attached yet, which happens for non-projective trees.
+        if transition.is_possible(state) {
+            Ok(transition)
+        } else {
+            Err(format_err!(
+                "No gold transition is possible, the oracle requires projective trees: {:?}",
+                transition
+            ))
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use failure::Error;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::guide::Guide;
+use crate::system::{
+    Dependency, DependencySet, ParserState, Transition, TransitionLookup, TransitionSystem,
+};
+
+use crate::features::addr::Source;
+use crate::system::AttachmentAddr;
+use crate::systems::util::{dep_head_mapping, root_attachment_permitted};
+
+/// Covington's list-based transition system for non-projective parsing.
+///
+/// In this system, every token is compared to every preceding token
+/// and the parser decides whether to add an arc between the two. This
+/// makes it possible to produce non-projective trees directly.
+///
+/// A configuration consists of two lists λ1 and λ2 of processed tokens,
+/// the buffer, and the arcs. Since λ1.λ2 always contains the tokens
+/// preceding the buffer in order, λ2 is not stored explicitly. λ1 is
+/// represented by the stack, λ2 consists of the tokens between the stack
+/// tip and the first buffer token.
+///
+/// This system is described in:
+///
+/// Joakim Nivre, Algorithms for deterministic incremental dependency
+/// parsing, 2008
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
+pub struct CovingtonSystem {
+    transitions: TransitionLookup<CovingtonTransition>,
+}
+
+impl CovingtonSystem {
+    pub fn new() -> Self {
+        CovingtonSystem {
+            transitions: TransitionLookup::default(),
+        }
+    }
+}
+
+impl Default for CovingtonSystem {
+    fn default() -> Self {
+        CovingtonSystem::new()
+    }
+}
+
+impl TransitionSystem for CovingtonSystem {
+    type Transition = CovingtonTransition;
+    type Oracle = CovingtonOracle;
+
+    const ATTACHMENT_ADDRS: [AttachmentAddr; 2] = [
+        AttachmentAddr {
+            head: Source::Buffer(0),
+            dependent: Source::Stack(0),
+        },
+        AttachmentAddr {
+            head: Source::Stack(0),
+            dependent: Source::Buffer(0),
+        },
+    ];
+
+    fn is_terminal(state: &ParserState) -> bool {
+        state.buffer().is_empty()
+    }
+
+    fn oracle(gold_dependencies: &DependencySet) -> Self::Oracle {
+        CovingtonOracle::new(gold_dependencies)
+    }
+
+    fn transitions(&self) -> &TransitionLookup<Self::Transition> {
+        &self.transitions
+    }
+}
+
+/// Covington transition.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum CovingtonTransition {
+    LeftArc(String),
+    RightArc(String),
+    NoArc,
+    Shift,
+}
+
+impl Transition for CovingtonTransition {
+    type S = CovingtonSystem;
+
+    fn is_possible(&self, state: &ParserState) -> bool {
+        let stack = state.stack();
+        let buffer = state.buffer();
+
+        if buffer.is_empty() {
+            return false;
+        }
+
+        match *self {
+            CovingtonTransition::LeftArc(_) => {
+                let dependent = ok_or!(stack.last().cloned(), return false);
+                dependent != 0
+                    && state.head(dependent).is_none()
+                    && !is_ancestor(state, dependent, buffer[0])
+            }
+            CovingtonTransition::RightArc(_) => {
+                let head = ok_or!(stack.last().cloned(), return false);
+                state.head(buffer[0]).is_none() && !is_ancestor(state, buffer[0], head)
+            }
+            CovingtonTransition::NoArc => !stack.is_empty(),
+            CovingtonTransition::Shift => true,
+        }
+    }
+
+    fn apply(&self, state: &mut ParserState) {
+        match *self {
+            CovingtonTransition::LeftArc(ref rel) => {
+                let head = state.buffer()[0];
+                let dependent = state.stack_mut().pop().unwrap();
+
+                state.add_dependency(Dependency {
+                    head,
+                    relation: rel.clone(),
+                    dependent,
+                });
+            }
+            CovingtonTransition::RightArc(ref rel) => {
+                let head = state.stack_mut().pop().unwrap();
+                let dependent = state.buffer()[0];
+
+                state.add_dependency(Dependency {
+                    head,
+                    relation: rel.clone(),
+                    dependent,
+                });
+            }
+            CovingtonTransition::NoArc => {
+                state.stack_mut().pop();
+            }
+            CovingtonTransition::Shift => {
+                // Move λ2 and the first buffer token to λ1.
+                let next = state.buffer_mut().pop_front().unwrap();
+                let start = state.stack().last().map(|&t| t + 1).unwrap_or(0);
+                state.stack_mut().extend(start..=next);
+            }
+        }
+    }
+
+    fn satisfies_constraints(&self, state: &ParserState) -> bool {
+        if !state.has_constraints() {
+            return true;
+        }
+
+        let stack = state.stack();
+        let buffer0 = state.buffer()[0];
+
+        // Every pair of tokens is considered once. So, a transition that
+        // moves to the next pair should not leave a constrained arc
+        // between the current pair unattached.
+        match *self {
+            CovingtonTransition::LeftArc(ref rel) => {
+                let stack0 = stack[stack.len() - 1];
+                state.arc_satisfies_constraints(buffer0, stack0, rel)
+                    && !constrained_to(state, buffer0, stack0)
+            }
+            CovingtonTransition::RightArc(ref rel) => {
+                let stack0 = stack[stack.len() - 1];
+                state.arc_satisfies_constraints(stack0, buffer0, rel)
+                    && !constrained_to(state, stack0, buffer0)
+                    && root_attachment_permitted(state, stack0)
+            }
+            CovingtonTransition::NoArc => {
+                let stack0 = stack[stack.len() - 1];
+                !constrained_to(state, stack0, buffer0) && !constrained_to(state, buffer0, stack0)
+            }
+            CovingtonTransition::Shift => !stack.iter().any(|&token| {
+                constrained_to(state, token, buffer0) || constrained_to(state, buffer0, token)
+            }),
+        }
+    }
+}
+
+/// Check whether `dependent` is constrained to `head` and not attached yet.
+fn constrained_to(state: &ParserState, dependent: usize, head: usize) -> bool {
+    state.head(dependent).is_none()
+        && state
+            .constraint(dependent)
+            .map(|constraint| constraint.head == head)
+            .unwrap_or(false)
+}
+
+/// Check whether `ancestor` is an ancestor of (or equal to) `token`.
+fn is_ancestor(state: &ParserState, ancestor: usize, token: usize) -> bool {
+    let mut token = token;
+
+    loop {
+        if token == ancestor {
+            return true;
+        }
+
+        token = ok_or!(state.head(token), return false).head;
+    }
+}
+
+pub struct CovingtonOracle {
+    dependencies: HashMap<usize, Dependency>,
+}
+
+impl CovingtonOracle {
+    pub fn new(gold_dependencies: &DependencySet) -> CovingtonOracle {
+        CovingtonOracle {
+            dependencies: dep_head_mapping(gold_dependencies),
+        }
+    }
+
+    /// Check whether there is a gold arc between the given tokens.
+    fn has_arc(&self, token1: usize, token2: usize) -> bool {
+        self.dependencies
+            .get(&token1)
+            .map(|dep| dep.head == token2)
+            .unwrap_or(false)
+            || self
+                .dependencies
+                .get(&token2)
+                .map(|dep| dep.head == token1)
+                .unwrap_or(false)
+    }
+}
+
+impl Guide for CovingtonOracle {
+    type Transition = CovingtonTransition;
+
+    fn best_transition(&mut self, state: &ParserState) -> Result<CovingtonTransition, Error> {
+        let stack = state.stack();
+        let buffer0 = state.buffer()[0];
+
+        if let Some(&stack0) = stack.last() {
+            if let Some(dep) = self.dependencies.get(&stack0) {
+                if dep.head == buffer0 {
+                    return Ok(CovingtonTransition::LeftArc(dep.relation.clone()));
+                }
+            }
+
+            if let Some(dep) = self.dependencies.get(&buffer0) {
+                if dep.head == stack0 {
+                    return Ok(CovingtonTransition::RightArc(dep.relation.clone()));
+                }
+            }
+
+            // Continue with the next token in λ1 if there is a token in
+            // λ1 that is in a relation with the first buffer token.
+            if stack[..stack.len() - 1]
+                .iter()
+                .any(|&token| self.has_arc(token, buffer0))
+            {
+                return Ok(CovingtonTransition::NoArc);
+            }
+        }
+
+        Ok(CovingtonTransition::Shift)
+    }
+}
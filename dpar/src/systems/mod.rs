@@ -7,6 +7,12 @@ pub use self::arc_hybrid::ArcHybridSystem;
 pub mod arc_standard;
 pub use self::arc_standard::ArcStandardSystem;
 
+pub mod arc_swift;
+pub use self::arc_swift::ArcSwiftSystem;
+
+pub mod covington;
+pub use self::covington::CovingtonSystem;
+
 pub mod stack_projective;
 pub use self::stack_projective::StackProjectiveSystem;
 
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use failure::Error;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::guide::Guide;
@@ -9,7 +10,7 @@ use crate::system::{
 
 use crate::features::addr::Source;
 use crate::system::AttachmentAddr;
-use crate::systems::util::dep_head_mapping;
+use crate::systems::util::{dep_head_mapping, final_root_attachment};
 
 #[derive(Eq, PartialEq, Serialize, Deserialize)]
 pub struct StackProjectiveSystem {
@@ -106,11 +107,36 @@ impl Transition for StackProjectiveTransition {
                 state.stack_mut().pop();
             }
             StackProjectiveTransition::Shift => {
-                let next = state.buffer_mut().remove(0);
+                let next = state.buffer_mut().pop_front().unwrap();
                 state.stack_mut().push(next);
             }
         }
     }
+
+    fn satisfies_constraints(&self, state: &ParserState) -> bool {
+        if !state.has_constraints() {
+            return true;
+        }
+
+        let stack = state.stack();
+        let stack_size = stack.len();
+
+        match *self {
+            StackProjectiveTransition::LeftArc(ref rel) => {
+                let dependent = stack[stack_size - 2];
+                state.arc_satisfies_constraints(stack[stack_size - 1], dependent, rel)
+                    && !state.has_pending_dependents(dependent)
+            }
+            StackProjectiveTransition::RightArc(ref rel) => {
+                let head = stack[stack_size - 2];
+                let dependent = stack[stack_size - 1];
+                state.arc_satisfies_constraints(head, dependent, rel)
+                    && !state.has_pending_dependents(dependent)
+                    && final_root_attachment(state, head, state.buffer().is_empty())
+            }
+            StackProjectiveTransition::Shift => true,
+        }
+    }
 }
 
 pub struct StackProjectiveOracle {
@@ -140,7 +166,7 @@ impl StackProjectiveOracle {
 impl Guide for StackProjectiveOracle {
     type Transition = StackProjectiveTransition;
 
-    fn best_transition(&mut self, state: &ParserState) -> StackProjectiveTransition {
+    fn best_transition(&mut self, state: &ParserState) -> Result<StackProjectiveTransition, Error> {
         let stack = &state.stack();
 
         if stack.len() > 1 {
@@ -151,7 +177,7 @@ impl Guide for StackProjectiveOracle {
                 let la = StackProjectiveTransition::LeftArc(dep.relation.clone());
 
                 if dep.head == stack0 && la.is_possible(state) {
-                    return la;
+                    return Ok(la);
                 }
             }
 
@@ -162,11 +188,11 @@ impl Guide for StackProjectiveOracle {
                     && ra.is_possible(state)
                     && !self.needed_for_attachment(state, stack0)
                 {
-                    return ra;
+                    return Ok(ra);
                 }
             }
         }
 
-        StackProjectiveTransition::Shift
+        Ok(StackProjectiveTransition::Shift)
     }
 }
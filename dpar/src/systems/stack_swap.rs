@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use failure::Error;
 use serde_derive::{Deserialize, Serialize};
 
 use petgraph::graph::node_index;
@@ -13,7 +14,7 @@ use crate::system::{
 
 use crate::features::addr::Source;
 use crate::system::AttachmentAddr;
-use crate::systems::util::dep_head_mapping;
+use crate::systems::util::{dep_head_mapping, final_root_attachment};
 
 /// The stack-swap transition system for non-projective parsing.
 ///
@@ -120,30 +121,95 @@ impl Transition for StackSwapTransition {
                 });
             }
             StackSwapTransition::Shift => {
-                let next = state.buffer_mut().remove(0);
+                let next = state.buffer_mut().pop_front().unwrap();
                 state.stack_mut().push(next);
             }
             StackSwapTransition::Swap => {
                 let swap_token = state.stack_mut().remove(stack_len - 2);
-                state.buffer_mut().insert(0, swap_token);
+                state.buffer_mut().push_front(swap_token);
             }
         }
     }
+
+    fn satisfies_constraints(&self, state: &ParserState) -> bool {
+        if !state.has_constraints() {
+            return true;
+        }
+
+        let stack = state.stack();
+        let stack_len = stack.len();
+
+        match *self {
+            StackSwapTransition::LeftArc(ref rel) => {
+                let dependent = stack[stack_len - 2];
+                state.arc_satisfies_constraints(stack[stack_len - 1], dependent, rel)
+                    && !state.has_pending_dependents(dependent)
+            }
+            StackSwapTransition::RightArc(ref rel) => {
+                let head = stack[stack_len - 2];
+                let dependent = stack[stack_len - 1];
+                state.arc_satisfies_constraints(head, dependent, rel)
+                    && !state.has_pending_dependents(dependent)
+                    && final_root_attachment(state, head, state.buffer().is_empty())
+            }
+            StackSwapTransition::Shift | StackSwapTransition::Swap => true,
+        }
+    }
 }
 
+/// Oracle for the stack-swap transition system.
+///
+/// The oracle is eager by default: it swaps as soon as the two topmost
+/// stack tokens are not in projective order. The lazy oracle postpones
+/// swaps while the stack top can still be attached within its maximal
+/// projective component, which results in far fewer swaps.
+///
+/// The lazy oracle is described in:
+///
+/// Joakim Nivre, Marco Kuhlmann and Johan Hall, An improved oracle for
+/// dependency parsing with online reordering, 2009
 pub struct StackSwapOracle {
     dependencies: HashMap<usize, Dependency>,
     projective_order: Vec<usize>,
+    components: Option<Vec<usize>>,
 }
 
 impl StackSwapOracle {
+    /// Construct an eager swap oracle.
     pub fn new(gold_dependencies: &DependencySet) -> StackSwapOracle {
         StackSwapOracle {
             dependencies: dep_head_mapping(gold_dependencies),
             projective_order: extract_projective_order(gold_dependencies),
+            components: None,
         }
     }
 
+    /// Construct a lazy swap oracle.
+    pub fn new_lazy(gold_dependencies: &DependencySet) -> StackSwapOracle {
+        let dependencies = dep_head_mapping(gold_dependencies);
+        let components = maximal_projective_components(&dependencies);
+
+        StackSwapOracle {
+            dependencies,
+            projective_order: extract_projective_order(gold_dependencies),
+            components: Some(components),
+        }
+    }
+
+    /// Check whether a swap should be postponed.
+    ///
+    /// The lazy oracle postpones a swap when the stack top and the first
+    /// buffer token belong to the same maximal projective component.
+    fn postpone_swap(&self, state: &ParserState, stack0: usize) -> bool {
+        let components = ok_or!(self.components.as_ref(), return false);
+
+        state
+            .buffer()
+            .front()
+            .map(|&buffer0| components[stack0] == components[buffer0])
+            .unwrap_or(false)
+    }
+
     fn needed_for_attachment(&self, state: &ParserState, token: usize) -> bool {
         for buf_token in state.buffer() {
             if let Some(dep) = self.dependencies.get(&buf_token) {
@@ -168,7 +234,7 @@ impl StackSwapOracle {
 impl Guide for StackSwapOracle {
     type Transition = StackSwapTransition;
 
-    fn best_transition(&mut self, state: &ParserState) -> StackSwapTransition {
+    fn best_transition(&mut self, state: &ParserState) -> Result<StackSwapTransition, Error> {
         let stack = &state.stack();
         let stack_len = stack.len();
 
@@ -183,7 +249,7 @@ impl Guide for StackSwapOracle {
                     && la.is_possible(state)
                     && !self.needed_for_attachment(state, stack1)
                 {
-                    return la;
+                    return Ok(la);
                 }
             }
 
@@ -194,16 +260,18 @@ impl Guide for StackSwapOracle {
                     && ra.is_possible(state)
                     && !self.needed_for_attachment(state, stack0)
                 {
-                    return ra;
+                    return Ok(ra);
                 }
             }
 
-            if self.projective_order[stack0] < self.projective_order[stack1] {
-                return StackSwapTransition::Swap;
+            if self.projective_order[stack0] < self.projective_order[stack1]
+                && !self.postpone_swap(state, stack0)
+            {
+                return Ok(StackSwapTransition::Swap);
             }
         }
 
-        StackSwapTransition::Shift
+        Ok(StackSwapTransition::Shift)
     }
 }
 
@@ -238,3 +306,62 @@ fn extract_projective_order(dependencies: &DependencySet) -> Vec<usize> {
 
     order_mapping
 }
+
+/// Find the maximal projective components of a dependency tree.
+///
+/// The components are found by processing the sentence in its original
+/// order with the stack-projective transitions, attaching a token as soon
+/// as it has collected all its dependents. The tokens that remain on the
+/// stack are the roots of the maximal projective components. Returns for
+/// each token the root of its component.
+fn maximal_projective_components(dependencies: &HashMap<usize, Dependency>) -> Vec<usize> {
+    // Every token except the root has a head.
+    let n_tokens = dependencies.len() + 1;
+
+    let mut pending_dependents = vec![0; n_tokens];
+    for dep in dependencies.values() {
+        pending_dependents[dep.head] += 1;
+    }
+
+    let is_gold_arc = |head: usize, dependent: usize| {
+        dependencies
+            .get(&dependent)
+            .map(|dep| dep.head == head)
+            .unwrap_or(false)
+    };
+
+    let mut heads = vec![None; n_tokens];
+    let mut stack = Vec::with_capacity(n_tokens);
+    for token in 0..n_tokens {
+        stack.push(token);
+
+        while stack.len() > 1 {
+            let stack0 = stack[stack.len() - 1];
+            let stack1 = stack[stack.len() - 2];
+
+            let (head, dependent) =
+                if is_gold_arc(stack0, stack1) && pending_dependents[stack1] == 0 {
+                    stack.remove(stack.len() - 2);
+                    (stack0, stack1)
+                } else if is_gold_arc(stack1, stack0) && pending_dependents[stack0] == 0 {
+                    stack.pop();
+                    (stack1, stack0)
+                } else {
+                    break;
+                };
+
+            heads[dependent] = Some(head);
+            pending_dependents[head] -= 1;
+        }
+    }
+
+    (0..n_tokens)
+        .map(|mut token| {
+            while let Some(head) = heads[token] {
+                token = head;
+            }
+
+            token
+        })
+        .collect()
+}
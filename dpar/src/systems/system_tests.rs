@@ -2,16 +2,19 @@ use std::collections::BTreeSet;
 use std::fs::File;
 use std::io::BufReader;
 
-use conllx::Reader;
+use conllx::{Reader, TokenBuilder};
+use failure::{err_msg, Error};
 
 use crate::guide::Guide;
-use crate::parser::{GreedyParser, Parse};
+use crate::parser::{GreedyParser, Parse, TreeCompletion};
 use crate::system::*;
-use crate::systems::arc_eager::ArcEagerOracle;
-use crate::systems::arc_hybrid::ArcHybridOracle;
-use crate::systems::arc_standard::ArcStandardOracle;
-use crate::systems::stack_projective::StackProjectiveOracle;
-use crate::systems::stack_swap::StackSwapOracle;
+use crate::systems::arc_eager::{ArcEagerOracle, ArcEagerTransition};
+use crate::systems::arc_hybrid::{ArcHybridOracle, ArcHybridTransition};
+use crate::systems::arc_standard::{ArcStandardOracle, ArcStandardTransition};
+use crate::systems::arc_swift::{ArcSwiftOracle, ArcSwiftSystem, ArcSwiftTransition};
+use crate::systems::covington::{CovingtonOracle, CovingtonTransition};
+use crate::systems::stack_projective::{StackProjectiveOracle, StackProjectiveTransition};
+use crate::systems::stack_swap::{StackSwapOracle, StackSwapSystem, StackSwapTransition};
 
 static PROJECTIVE_DATA: &'static str = "testdata/cdb-test.conll";
 
@@ -41,6 +44,112 @@ where
     }
 }
 
+/// Guide that returns the first candidate transition that is possible
+/// and keeps the constraints satisfiable.
+struct ConstrainedGuide<T> {
+    candidates: Vec<T>,
+}
+
+impl<T> Guide for ConstrainedGuide<T>
+where
+    T: Transition,
+{
+    type Transition = T;
+
+    fn best_transition(&mut self, state: &ParserState) -> Result<T, Error> {
+        self.candidates
+            .iter()
+            .find(|t| t.is_possible(state) && t.satisfies_constraints(state))
+            .cloned()
+            .ok_or_else(|| err_msg("No transition satisfies the constraints"))
+    }
+}
+
+/// Construct candidate transitions for a guide.
+///
+/// Every arc constructor is applied to every relation, followed by the
+/// transitions in `rest`. The candidates are ordered by preference.
+fn candidates<T, F>(relations: &[String], arcs: &[F], rest: &[T]) -> Vec<T>
+where
+    T: Clone,
+    F: Fn(String) -> T,
+{
+    arcs.iter()
+        .flat_map(|arc| relations.iter().cloned().map(arc))
+        .chain(rest.iter().cloned())
+        .collect()
+}
+
+/// Parse with all gold-standard dependencies as constraints.
+///
+/// Since the candidate transitions are not scored, the gold-standard
+/// dependencies can only be recovered if the constraint checks of
+/// the transition system are correct.
+fn test_constrained<T, F>(arcs: &[F], rest: &[T], data: &str)
+where
+    T: Transition,
+    F: Fn(String) -> T,
+{
+    let f = File::open(data).unwrap();
+    let reader = Reader::new(BufReader::new(f));
+
+    for sentence in reader {
+        let sentence = sentence.unwrap();
+        let dependencies = sentence_to_dependencies(&sentence).unwrap();
+        let relations: Vec<_> = dependencies
+            .iter()
+            .map(|dep| dep.relation.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let guide = ConstrainedGuide {
+            candidates: candidates(&relations, arcs, rest),
+        };
+        let mut parser = GreedyParser::new_constrained(guide);
+
+        let result = parser.parse(&sentence).unwrap();
+
+        // Convert to ordered set for easier comparisons.
+        let dependencies: BTreeSet<_> = dependencies.into_iter().collect();
+        let result: BTreeSet<_> = result.into_iter().collect();
+
+        assert_eq!(&dependencies, &result);
+    }
+}
+
+/// Parse with the single-root constraint, preferring attachments.
+///
+/// Checks that the root has at most one dependent after decoding and
+/// that tree completion results in a complete tree with a single root.
+fn check_single_root<T, F>(arcs: &[F], rest: &[T], data: &str)
+where
+    T: Transition,
+    F: Fn(String) -> T,
+{
+    let f = File::open(data).unwrap();
+    let reader = Reader::new(BufReader::new(f));
+
+    for sentence in reader {
+        let sentence = sentence.unwrap();
+        let mut guide = ConstrainedGuide {
+            candidates: candidates(&["x".to_owned()], arcs, rest),
+        };
+
+        let mut state = ParserState::new(&sentence);
+        state.set_single_root(true);
+        while !T::S::is_terminal(&state) {
+            guide.best_transition(&state).unwrap().apply(&mut state);
+        }
+        assert!(state.leftmost_dependent(0, 1).is_none());
+
+        let mut parser = GreedyParser::new(guide).with_completion(TreeCompletion::new("dep", true));
+        let result = parser.parse(&sentence).unwrap();
+        assert_eq!(result.len(), sentence.len());
+        assert_eq!(result.iter().filter(|dep| dep.head == 0).count(), 1);
+    }
+}
+
 #[test]
 fn test_arc_eager() {
     test_system(ArcEagerOracle::new, PROJECTIVE_DATA);
@@ -56,6 +165,63 @@ fn test_arc_standard() {
     test_system(ArcStandardOracle::new, PROJECTIVE_DATA);
 }
 
+#[test]
+fn test_arc_standard_left_arc_possible() {
+    let sentence = vec![
+        TokenBuilder::new("a").token(),
+        TokenBuilder::new("b").token(),
+    ];
+    let left_arc = ArcStandardTransition::LeftArc("x".to_owned());
+
+    // The root cannot become a dependent.
+    let mut state = ParserState::new(&sentence);
+    assert!(!left_arc.is_possible(&state));
+
+    ArcStandardTransition::Shift.apply(&mut state);
+    assert!(left_arc.is_possible(&state));
+
+    // No token is left to attach to.
+    ArcStandardTransition::Shift.apply(&mut state);
+    assert!(!left_arc.is_possible(&state));
+}
+
+#[test]
+fn test_arc_swift() {
+    test_system(ArcSwiftOracle::new, PROJECTIVE_DATA);
+}
+
+#[test]
+fn test_arc_swift_non_projective() {
+    // The arcs 3 -> 1 and 4 -> 2 cross.
+    let sentence = vec![
+        TokenBuilder::new("a").head(3).head_rel("x").token(),
+        TokenBuilder::new("b").head(4).head_rel("x").token(),
+        TokenBuilder::new("c").head(0).head_rel("x").token(),
+        TokenBuilder::new("d").head(3).head_rel("x").token(),
+    ];
+    let dependencies = sentence_to_dependencies(&sentence).unwrap();
+
+    let mut oracle = ArcSwiftOracle::new(&dependencies);
+    let mut state = ParserState::new(&sentence);
+    loop {
+        assert!(
+            !ArcSwiftSystem::is_terminal(&state),
+            "Oracle derived a non-projective tree"
+        );
+
+        match oracle.best_transition(&state) {
+            Ok(transition) => transition.apply(&mut state),
+            Err(_) => break,
+        }
+    }
+}
+
+#[test]
+fn test_covington() {
+    test_system(CovingtonOracle::new, PROJECTIVE_DATA);
+    test_system(CovingtonOracle::new, NON_PROJECTIVE_DATA);
+}
+
 #[test]
 fn test_stack_projective() {
     test_system(StackProjectiveOracle::new, PROJECTIVE_DATA);
@@ -65,3 +231,159 @@ fn test_stack_projective() {
 fn test_stack_swap() {
     test_system(StackSwapOracle::new, NON_PROJECTIVE_DATA);
 }
+
+#[test]
+fn test_stack_swap_lazy() {
+    test_system(StackSwapOracle::new_lazy, NON_PROJECTIVE_DATA);
+}
+
+fn count_swaps(oracle_constructor: fn(&DependencySet) -> StackSwapOracle, data: &str) -> usize {
+    let f = File::open(data).unwrap();
+    let reader = Reader::new(BufReader::new(f));
+
+    let mut swaps = 0;
+    for sentence in reader {
+        let sentence = sentence.unwrap();
+        let dependencies = sentence_to_dependencies(&sentence).unwrap();
+
+        let mut oracle = oracle_constructor(&dependencies);
+        let mut state = ParserState::new(&sentence);
+        while !StackSwapSystem::is_terminal(&state) {
+            let transition = oracle.best_transition(&state).unwrap();
+            if transition == StackSwapTransition::Swap {
+                swaps += 1;
+            }
+            transition.apply(&mut state);
+        }
+    }
+
+    swaps
+}
+
+#[test]
+fn test_stack_swap_lazy_swaps() {
+    let eager_swaps = count_swaps(StackSwapOracle::new, NON_PROJECTIVE_DATA);
+    let lazy_swaps = count_swaps(StackSwapOracle::new_lazy, NON_PROJECTIVE_DATA);
+    assert!(lazy_swaps > 0);
+    assert!(lazy_swaps < eager_swaps);
+}
+
+#[test]
+fn test_arc_eager_constrained() {
+    test_constrained(
+        &[ArcEagerTransition::LeftArc, ArcEagerTransition::RightArc],
+        &[ArcEagerTransition::Reduce, ArcEagerTransition::Shift],
+        PROJECTIVE_DATA,
+    );
+}
+
+#[test]
+fn test_arc_hybrid_constrained() {
+    test_constrained(
+        &[ArcHybridTransition::LeftArc, ArcHybridTransition::RightArc],
+        &[ArcHybridTransition::Shift],
+        PROJECTIVE_DATA,
+    );
+}
+
+#[test]
+fn test_arc_standard_constrained() {
+    test_constrained(
+        &[
+            ArcStandardTransition::LeftArc,
+            ArcStandardTransition::RightArc,
+        ],
+        &[ArcStandardTransition::Shift],
+        PROJECTIVE_DATA,
+    );
+}
+
+#[test]
+fn test_arc_swift_constrained() {
+    // Prefer attachments that are close to the stack tip.
+    let arcs: Vec<Box<dyn Fn(String) -> ArcSwiftTransition>> = (0..100)
+        .flat_map(|depth| -> Vec<Box<dyn Fn(String) -> ArcSwiftTransition>> {
+            vec![
+                Box::new(move |rel| ArcSwiftTransition::LeftArc(depth, rel)),
+                Box::new(move |rel| ArcSwiftTransition::RightArc(depth, rel)),
+            ]
+        })
+        .collect();
+
+    test_constrained(&arcs, &[ArcSwiftTransition::Shift], PROJECTIVE_DATA);
+}
+
+#[test]
+fn test_stack_projective_constrained() {
+    test_constrained(
+        &[
+            StackProjectiveTransition::LeftArc,
+            StackProjectiveTransition::RightArc,
+        ],
+        &[StackProjectiveTransition::Shift],
+        PROJECTIVE_DATA,
+    );
+}
+
+#[test]
+fn test_stack_swap_constrained() {
+    test_constrained(
+        &[StackSwapTransition::LeftArc, StackSwapTransition::RightArc],
+        &[StackSwapTransition::Shift, StackSwapTransition::Swap],
+        PROJECTIVE_DATA,
+    );
+}
+
+#[test]
+fn test_covington_constrained() {
+    test_constrained(
+        &[CovingtonTransition::LeftArc, CovingtonTransition::RightArc],
+        &[CovingtonTransition::NoArc, CovingtonTransition::Shift],
+        NON_PROJECTIVE_DATA,
+    );
+}
+
+#[test]
+fn test_single_root() {
+    check_single_root(
+        &[ArcEagerTransition::RightArc, ArcEagerTransition::LeftArc],
+        &[ArcEagerTransition::Reduce, ArcEagerTransition::Shift],
+        PROJECTIVE_DATA,
+    );
+    check_single_root(
+        &[ArcHybridTransition::RightArc, ArcHybridTransition::LeftArc],
+        &[ArcHybridTransition::Shift],
+        PROJECTIVE_DATA,
+    );
+    check_single_root(
+        &[
+            ArcStandardTransition::RightArc,
+            ArcStandardTransition::LeftArc,
+        ],
+        &[ArcStandardTransition::Shift],
+        PROJECTIVE_DATA,
+    );
+    let swift_arcs: [Box<dyn Fn(String) -> ArcSwiftTransition>; 2] = [
+        Box::new(|rel| ArcSwiftTransition::RightArc(0, rel)),
+        Box::new(|rel| ArcSwiftTransition::LeftArc(0, rel)),
+    ];
+    check_single_root(&swift_arcs, &[ArcSwiftTransition::Shift], PROJECTIVE_DATA);
+    check_single_root(
+        &[CovingtonTransition::RightArc, CovingtonTransition::LeftArc],
+        &[CovingtonTransition::Shift],
+        PROJECTIVE_DATA,
+    );
+    check_single_root(
+        &[
+            StackProjectiveTransition::RightArc,
+            StackProjectiveTransition::LeftArc,
+        ],
+        &[StackProjectiveTransition::Shift],
+        PROJECTIVE_DATA,
+    );
+    check_single_root(
+        &[StackSwapTransition::RightArc, StackSwapTransition::LeftArc],
+        &[StackSwapTransition::Shift],
+        PROJECTIVE_DATA,
+    );
+}
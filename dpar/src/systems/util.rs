@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::system::{Dependency, DependencySet};
+use crate::system::{Dependency, DependencySet, ParserState};
 
 pub fn dep_head_mapping(dependencies: &DependencySet) -> HashMap<usize, Dependency> {
     let mut mapping = HashMap::new();
@@ -11,3 +11,32 @@ pub fn dep_head_mapping(dependencies: &DependencySet) -> HashMap<usize, Dependen
 
     mapping
 }
+
+/// Check whether an unattached token on the stack is constrained to be
+/// attached to the given head.
+pub fn stack_waits_for(state: &ParserState, head: usize) -> bool {
+    state.stack().iter().any(|&token| {
+        state.head(token).is_none()
+            && state
+                .constraint(token)
+                .map(|constraint| constraint.head == head)
+                .unwrap_or(false)
+    })
+}
+
+/// Check whether an attachment to the given head respects the single-root
+/// constraint, for systems that can attach to the root at any point.
+///
+/// Only the first attachment to the root is permitted.
+pub fn root_attachment_permitted(state: &ParserState, head: usize) -> bool {
+    !state.single_root() || head != 0 || state.leftmost_dependent(0, 0).is_none()
+}
+
+/// Check whether an attachment to the given head respects the single-root
+/// constraint, for systems that attach to the root as the final attachment.
+///
+/// `is_final` should indicate whether the attachment is the final
+/// attachment of the parse.
+pub fn final_root_attachment(state: &ParserState, head: usize, is_final: bool) -> bool {
+    !state.single_root() || head != 0 || is_final
+}
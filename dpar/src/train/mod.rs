@@ -13,4 +13,12 @@ where
     T: TransitionSystem,
 {
     fn collect(&mut self, t: &T::Transition, state: &ParserState) -> Result<(), Error>;
+
+    /// Finish a sentence.
+    ///
+    /// This method is called after the parser states of a sentence
+    /// were collected.
+    fn finish_sentence(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
 }
@@ -1,6 +1,6 @@
 use failure::Error;
 
-use crate::features::InputVectorizer;
+use crate::features::{InputVectorizer, SentenceOccurrences};
 use crate::system::{ParserState, TransitionSystem};
 use crate::train::InstanceCollector;
 
@@ -8,11 +8,14 @@ use crate::train::InstanceCollector;
 ///
 /// The no-op collector implements the `InstanceCollector` trait, but
 /// does not actually collect any data. However, since this collector
-/// vectorizes parser states, it can be used for initializing lookup
-/// tables (as a side-effect of collection).
+/// adds the values of parser states to their lookups, it can be used
+/// for initializing lookup tables (as a side-effect of collection).
+/// Every value is counted once per occurrence in a sentence, regardless
+/// of the number of parser states in which it is used.
 pub struct NoopCollector<T> {
     transition_system: T,
     vectorizer: InputVectorizer,
+    occurrences: SentenceOccurrences,
 }
 
 impl<T> NoopCollector<T>
@@ -24,6 +27,7 @@ where
         Ok(NoopCollector {
             transition_system,
             vectorizer,
+            occurrences: SentenceOccurrences::new(),
         })
     }
 
@@ -44,7 +48,97 @@ where
 {
     fn collect(&mut self, t: &T::Transition, state: &ParserState) -> Result<(), Error> {
         self.transition_system.transitions().lookup(t.clone());
-        self.vectorizer.realize(state);
+
+        let lookups = self.vectorizer.layer_lookups();
+        for (layer, value) in self.vectorizer.occurrences(state, &mut self.occurrences) {
+            if let Some(lookup) = lookups.layer_lookup(layer) {
+                lookup.add_occurrence(&value);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish_sentence(&mut self) -> Result<(), Error> {
+        self.occurrences.clear();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use conllx::TokenBuilder;
+
+    use crate::features::addr::{AddressedValue, Layer, Source};
+    use crate::features::{
+        self, AddressedValues, InputVectorizer, LayerLookups, Lookup, MutableLookupTable,
+    };
+    use crate::system::{sentence_to_dependencies, ParserState};
+    use crate::systems::StackProjectiveSystem;
+    use crate::train::{GreedyTrainer, NoopCollector};
+
+    fn test_vectorizer() -> InputVectorizer {
+        let value = |source, layer| AddressedValue {
+            address: vec![source],
+            layer,
+            input: None,
+        };
+
+        let addrs = vec![
+            value(Source::Stack(0), Layer::Token),
+            value(Source::Buffer(0), Layer::Token),
+            value(Source::Buffer(0), Layer::Char(2)),
+        ];
+
+        let mut lookups = LayerLookups::new();
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(features::Layer::TOKEN, table);
+        let table: Box<Lookup> = Box::new(MutableLookupTable::new());
+        lookups.insert(features::Layer::CHAR, table);
+        InputVectorizer::new(lookups, AddressedValues(addrs)).unwrap()
+    }
+
+    fn frequency(lookup: &Lookup, value: &str) -> Option<usize> {
+        lookup
+            .lookup(value)
+            .and_then(|r| r.index())
+            .and_then(|idx| lookup.frequency(idx))
+    }
+
+    #[test]
+    fn counts_corpus_occurrences() {
+        let sent = vec![
+            TokenBuilder::new("a").head(2).head_rel("x").token(),
+            TokenBuilder::new("b").head(0).head_rel("root").token(),
+            TokenBuilder::new("a").head(2).head_rel("y").token(),
+        ];
+        let dependencies = sentence_to_dependencies(&sent).unwrap();
+
+        let collector =
+            NoopCollector::new(StackProjectiveSystem::new(), test_vectorizer()).unwrap();
+        let mut trainer = GreedyTrainer::new(collector);
+        trainer
+            .parse_state(&dependencies, &mut ParserState::new(&sent))
+            .unwrap();
+
+        // Every token is looked up in several parser states, but is
+        // only counted once.
+        let lookups = trainer.collector().input_vectorizer().layer_lookups();
+        let tokens = lookups.layer_lookup(&features::Layer::TOKEN).unwrap();
+        assert_eq!(frequency(tokens, "ROOT"), Some(1));
+        assert_eq!(frequency(tokens, "a"), Some(2));
+        assert_eq!(frequency(tokens, "b"), Some(1));
+
+        let chars = lookups.layer_lookup(&features::Layer::CHAR).unwrap();
+        assert_eq!(frequency(chars, "a"), Some(2));
+        assert_eq!(frequency(chars, "b"), Some(1));
+
+        // Occurrences are counted again in the next sentence.
+        trainer
+            .parse_state(&dependencies, &mut ParserState::new(&sent))
+            .unwrap();
+        let lookups = trainer.collector().input_vectorizer().layer_lookups();
+        let tokens = lookups.layer_lookup(&features::Layer::TOKEN).unwrap();
+        assert_eq!(frequency(tokens, "a"), Some(4));
+    }
+}
@@ -12,6 +12,7 @@ where
     T: TransitionSystem,
 {
     collector: C,
+    oracle: fn(&DependencySet) -> T::Oracle,
     transition_type: PhantomData<T>,
 }
 
@@ -21,8 +22,17 @@ where
     T: TransitionSystem,
 {
     pub fn new(collector: C) -> Self {
+        Self::with_oracle(collector, T::oracle)
+    }
+
+    /// Construct a trainer that uses the given oracle constructor.
+    ///
+    /// This can be used to train with an oracle other than the default
+    /// oracle of the transition system.
+    pub fn with_oracle(collector: C, oracle: fn(&DependencySet) -> T::Oracle) -> Self {
         GreedyTrainer {
             collector,
+            oracle,
             transition_type: PhantomData,
         }
     }
@@ -40,14 +50,14 @@ where
         gold_dependencies: &DependencySet,
         state: &mut ParserState,
     ) -> Result<(), Error> {
-        let mut oracle = T::oracle(gold_dependencies);
+        let mut oracle = (self.oracle)(gold_dependencies);
 
         while !T::is_terminal(state) {
-            let next_transition = oracle.best_transition(state);
+            let next_transition = oracle.best_transition(state)?;
             self.collector.collect(&next_transition, state)?;
             next_transition.apply(state);
         }
 
-        Ok(())
+        self.collector.finish_sentence()
     }
 }